@@ -29,11 +29,72 @@ pub enum Entity {
     },
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub enum Wrap {
+    Clamp,
+    Tile,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub enum Terrain {
+    Fbm,
+    Sine,
+    Noise {
+        seed: u32,
+    },
+    Constant(f32),
+    ScaleOffset {
+        source: Box<Terrain>,
+        input_scale: f32,
+        output_scale: f32,
+        offset: f32,
+    },
+    Add(Box<Terrain>, Box<Terrain>),
+    Multiply(Box<Terrain>, Box<Terrain>),
+    Min(Box<Terrain>, Box<Terrain>),
+    Max(Box<Terrain>, Box<Terrain>),
+    DomainWarp {
+        source: Box<Terrain>,
+        warp_x: Box<Terrain>,
+        warp_z: Box<Terrain>,
+        strength: f32,
+    },
+    Terrace {
+        source: Box<Terrain>,
+        step_height: f32,
+        sharpness: f32,
+    },
+    Ridged(Box<Terrain>),
+    ImageMask {
+        location: String,
+        origin: [f32; 2],
+        size: [f32; 2],
+    },
+    Heightmap {
+        location: String,
+        origin: [f32; 2],
+        metres_per_pixel: f32,
+        height_range: [f32; 2],
+        wrap: Wrap,
+    },
+    Eroded {
+        source: Box<Terrain>,
+        origin: [f32; 2],
+        cells: usize,
+        cell_size: f32,
+        iterations: usize,
+        droplets: usize,
+        seed: u32,
+    },
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct Config {
     pub models: Vec<Model>,
     pub entities: Vec<Entity>,
     pub cameras: Vec<Camera>,
+    #[serde(default)]
+    pub terrain: Option<Terrain>,
 }
 
 impl Config {
@@ -42,6 +103,7 @@ impl Config {
             models: vec![],
             entities: vec![],
             cameras: vec![],
+            terrain: None,
         }
     }
     fn is_valid(&self) -> bool {
@@ -1,4 +1,4 @@
-use crate::{configuration, scene};
+use crate::{configuration, scene, terrain};
 use nalgebra_glm::quat_identity;
 
 pub fn create_cameras(config: &[configuration::Camera]) -> scene::Cameras {
@@ -63,3 +63,183 @@ pub fn create_model_meshes(config: &[configuration::Model]) -> Vec<(String, xp_m
     }
     named_meshes
 }
+
+#[derive(Debug)]
+pub enum TerrainError {
+    Image(image::ImageError),
+    // a value of the configuration the generator can not work with, by the name of its field
+    InvalidValue(&'static str),
+}
+
+impl From<image::ImageError> for TerrainError {
+    fn from(e: image::ImageError) -> TerrainError {
+        TerrainError::Image(e)
+    }
+}
+
+// the generators assert on these, config.ron should not be able to panic them
+fn positive(value: f32, name: &'static str) -> Result<f32, TerrainError> {
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(TerrainError::InvalidValue(name))
+    }
+}
+
+// Fails when an image of the terrain can not be read or a value is out of range.
+pub fn create_terrain(
+    config: &configuration::Terrain,
+) -> Result<Box<dyn terrain::Generator>, TerrainError> {
+    Ok(match config {
+        configuration::Terrain::Fbm => Box::new(terrain::Fbm::new()),
+        configuration::Terrain::Sine => Box::new(terrain::Sine),
+        configuration::Terrain::Noise { seed } => Box::new(terrain::Noise::new(*seed)),
+        configuration::Terrain::Constant(height) => Box::new(terrain::Constant(*height)),
+        configuration::Terrain::ScaleOffset {
+            source,
+            input_scale,
+            output_scale,
+            offset,
+        } => Box::new(terrain::ScaleOffset::new(
+            create_terrain(source)?,
+            if *input_scale != 0.0 {
+                *input_scale
+            } else {
+                return Err(TerrainError::InvalidValue("input_scale"));
+            },
+            *output_scale,
+            *offset,
+        )),
+        configuration::Terrain::Add(first, second) => Box::new(terrain::Combine::new(
+            terrain::Operation::Add,
+            create_terrain(first)?,
+            create_terrain(second)?,
+        )),
+        configuration::Terrain::Multiply(first, second) => Box::new(terrain::Combine::new(
+            terrain::Operation::Multiply,
+            create_terrain(first)?,
+            create_terrain(second)?,
+        )),
+        configuration::Terrain::Min(first, second) => Box::new(terrain::Combine::new(
+            terrain::Operation::Min,
+            create_terrain(first)?,
+            create_terrain(second)?,
+        )),
+        configuration::Terrain::Max(first, second) => Box::new(terrain::Combine::new(
+            terrain::Operation::Max,
+            create_terrain(first)?,
+            create_terrain(second)?,
+        )),
+        configuration::Terrain::DomainWarp {
+            source,
+            warp_x,
+            warp_z,
+            strength,
+        } => Box::new(terrain::DomainWarp::new(
+            create_terrain(source)?,
+            create_terrain(warp_x)?,
+            create_terrain(warp_z)?,
+            *strength,
+        )),
+        configuration::Terrain::Terrace {
+            source,
+            step_height,
+            sharpness,
+        } => Box::new(terrain::Terrace::new(
+            create_terrain(source)?,
+            positive(*step_height, "step_height")?,
+            *sharpness,
+        )),
+        configuration::Terrain::Ridged(source) => {
+            Box::new(terrain::Ridged::new(create_terrain(source)?))
+        }
+        configuration::Terrain::ImageMask {
+            location,
+            origin,
+            size,
+        } => Box::new(terrain::ImageMask::open(
+            location.as_str(),
+            *origin,
+            [positive(size[0], "size")?, positive(size[1], "size")?],
+        )?),
+        configuration::Terrain::Heightmap {
+            location,
+            origin,
+            metres_per_pixel,
+            height_range,
+            wrap,
+        } => Box::new(terrain::Heightmap::open(
+            location.as_str(),
+            *origin,
+            positive(*metres_per_pixel, "metres_per_pixel")?,
+            *height_range,
+            match wrap {
+                configuration::Wrap::Clamp => xp_terrain::Wrap::Clamp,
+                configuration::Wrap::Tile => xp_terrain::Wrap::Tile,
+            },
+        )?),
+        configuration::Terrain::Eroded {
+            source,
+            origin,
+            cells,
+            cell_size,
+            iterations,
+            droplets,
+            seed,
+        } => Box::new(terrain::Eroded::new(
+            create_terrain(source)?,
+            *origin,
+            *cells,
+            *cell_size,
+            *iterations,
+            &xp_terrain::HydraulicParameters {
+                droplets: *droplets,
+                seed: *seed,
+                ..Default::default()
+            },
+            &Default::default(),
+        )),
+    })
+}
+
+#[test]
+fn create_terrain_from_ron_test() {
+    let config: configuration::Terrain = ron::de::from_str(
+        "Max(ScaleOffset(source: Sine, input_scale: 2.0, output_scale: 3.0, offset: 1.0), Constant(0.5))",
+    )
+    .unwrap();
+    let generator = create_terrain(&config).unwrap();
+    // sine of the origin is 0.0, scaled and offset that gives 1.0
+    assert_eq!(generator.generate([0.0, 0.0]), 1.0);
+    // sin(-pi / 2) * 3 + 1 = -2, clamped from below by the constant
+    let x = -std::f32::consts::PI * 4.0;
+    assert_eq!(generator.generate([x, 0.0]), 0.5);
+}
+
+#[test]
+fn terrace_and_ridged_test() {
+    use crate::terrain::Generator;
+    let terrace = terrain::Terrace::new(Box::new(terrain::Constant(2.5)), 1.0, 2.0);
+    assert_eq!(terrace.generate([0.0, 0.0]), 2.25);
+    let ridged = terrain::Ridged::new(Box::new(terrain::Constant(-0.25)));
+    assert_eq!(ridged.generate([0.0, 0.0]), 0.75);
+}
+
+#[test]
+fn missing_terrain_image_is_an_error_test() {
+    let config: configuration::Terrain = ron::de::from_str(
+        r#"Add(Sine, ImageMask(location: "missing.png", origin: (0.0, 0.0), size: (1.0, 1.0)))"#,
+    )
+    .unwrap();
+    assert!(create_terrain(&config).is_err());
+}
+
+#[test]
+fn invalid_terrain_value_is_an_error_test() {
+    let config: configuration::Terrain =
+        ron::de::from_str("Terrace(source: Sine, step_height: 0.0, sharpness: 1.0)").unwrap();
+    assert!(matches!(
+        create_terrain(&config),
+        Err(TerrainError::InvalidValue("step_height"))
+    ));
+}
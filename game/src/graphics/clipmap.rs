@@ -1,3 +1,4 @@
+use crate::graphics::culling::{Aabb, Frustum};
 use crate::graphics::error::GraphicsError;
 use crate::graphics::{create_buffer_from, texture, Buffer};
 use crate::terrain;
@@ -68,6 +69,9 @@ const CM_INSTANCE_SIZE_NXN: u32 = CM_INSTANCE_SIZE_ONE_NXN * CM_MAX_LEVELS;
 const CM_INSTANCE_SIZE_DEGENERATES: u32 = CM_INSTANCE_SIZE_ONE_DEGENERATE * CM_MAX_LEVELS;
 const CM_INSTANCE_SIZE_INTERIOR: u32 = CM_INSTANCE_SIZE_ONE_INTERIOR * CM_MAX_LEVELS;
 const BASE_OFFSET: u32 = (CM_N - 3) / 2;
+// ring blocks and full grids that passed the frustum test are copied behind the static instances
+const CM_INSTANCE_SIZE_CULLED: u32 =
+    CM_INSTANCE_SIZE_MXM + CM_INSTANCE_SIZE_MXP + CM_INSTANCE_SIZE_PXM + CM_INSTANCE_SIZE_NXN;
 
 #[allow(non_snake_case)]
 pub fn create_clipmap_storage_texture(device: &wgpu::Device, N: u32) -> wgpu::Texture {
@@ -139,6 +143,7 @@ pub struct Renderer {
     pub render_pipeline: wgpu::RenderPipeline,
     pub texture: wgpu::Texture,
     pub generator: Box<dyn Generator>,
+    instances: Vec<Instance>,
     pub center: Option<[f32; 2]>,
 
     pub clipmap_data: Clipmap,
@@ -287,9 +292,18 @@ impl Renderer {
                 padding: 5,
             });
         }
+        let mut instance_data = instances.clone();
+        instance_data.resize(
+            instances.len() + CM_INSTANCE_SIZE_CULLED as usize,
+            Instance {
+                offset: [0, 0],
+                level: 0,
+                padding: 0,
+            },
+        );
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(instances.as_slice()),
+            contents: bytemuck::cast_slice(instance_data.as_slice()),
             usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
         });
 
@@ -363,9 +377,7 @@ impl Renderer {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Buffer(
-                        instance_buffer.slice(0..std::mem::size_of_val(&instances) as u64),
-                    ),
+                    resource: wgpu::BindingResource::Buffer(instance_buffer.slice(..)),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -453,10 +465,21 @@ impl Renderer {
             texture,
             clipmap_data: Clipmap::new(CM_MAX_LEVELS, CM_TEXTURE_SIZE),
             generator: Box::new(terrain::Fbm::new()),
+            instances,
             center: None,
         })
     }
 
+    // heights and normals of the ground exactly as it is drawn
+    pub fn terrain_query(&self) -> terrain::TerrainQuery {
+        let levels = self.clipmap_data.query_levels();
+        if levels.is_empty() {
+            terrain::TerrainQuery::uniform(&*self.generator, CM_UNIT_SIZE_SMALLEST)
+        } else {
+            terrain::TerrainQuery::new(&*self.generator, levels)
+        }
+    }
+
     pub fn render<'a, 'b>(
         &'a mut self,
         render_pass: &'b mut RenderPass<'a>,
@@ -526,42 +549,68 @@ impl Renderer {
         let end_degen_v_left: u32 = end_degen_h_bottom + CM_INSTANCE_SIZE_DEGENERATES;
         let end_degen_v_right: u32 = end_degen_v_left + CM_INSTANCE_SIZE_DEGENERATES;
 
-        render_pass.set_vertex_buffer(0, self.clipmap_ring_mxm.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.clipmap_ring_mxm.index_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw_indexed(
-            0..self.clipmap_ring_mxm.index_buffer_len,
-            0,
-            start_ring_level * CM_INSTANCE_SIZE_ONE_MXM..end_mxm,
-        );
-
-        render_pass.set_vertex_buffer(0, self.clipmap_ring_mxp.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.clipmap_ring_mxp.index_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw_indexed(
-            0..self.clipmap_ring_mxp.index_buffer_len,
-            0,
-            end_mxm + start_ring_level * CM_INSTANCE_SIZE_ONE_MXP..end_mxp,
-        );
-
-        render_pass.set_vertex_buffer(0, self.clipmap_ring_pxm.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.clipmap_ring_pxm.index_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw_indexed(
-            0..self.clipmap_ring_pxm.index_buffer_len,
-            0,
-            end_mxp + start_ring_level * CM_INSTANCE_SIZE_ONE_PXM..end_pxm,
-        );
+        let frustum = Frustum::from_matrix(&(projection * view));
+        let center = [position.x, position.z];
+        let culled_start = self.instances.len() as u32;
+        let mut visible = Vec::new();
+        let mut visible_ranges = Vec::new();
+        for (range, size) in [
+            (
+                start_ring_level * CM_INSTANCE_SIZE_ONE_MXM..end_mxm,
+                [CM_M, CM_M],
+            ),
+            (
+                end_mxm + start_ring_level * CM_INSTANCE_SIZE_ONE_MXP..end_mxp,
+                [CM_M, CM_P],
+            ),
+            (
+                end_mxp + start_ring_level * CM_INSTANCE_SIZE_ONE_PXM..end_pxm,
+                [CM_P, CM_M],
+            ),
+            (
+                end_pxm + full_level * CM_INSTANCE_SIZE_ONE_NXN
+                    ..(end_pxm + full_level * CM_INSTANCE_SIZE_ONE_NXN) + CM_INSTANCE_SIZE_ONE_NXN,
+                [CM_N, CM_N],
+            ),
+        ]
+        .iter()
+        {
+            let start = culled_start + visible.len() as u32;
+            visible.extend(
+                self.instances[range.start as usize..range.end as usize]
+                    .iter()
+                    .filter(|instance| {
+                        let height_range = self.clipmap_data.height_range(instance.level);
+                        frustum.intersects(&block_bounds(instance, *size, center, height_range))
+                    }),
+            );
+            visible_ranges.push(start..culled_start + visible.len() as u32);
+        }
+        if !visible.is_empty() {
+            queue.write_buffer(
+                &self.instance_buffer,
+                (culled_start as usize * std::mem::size_of::<Instance>()) as u64,
+                bytemuck::cast_slice(visible.as_slice()),
+            );
+        }
 
-        render_pass.set_vertex_buffer(0, self.clipmap_full.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.clipmap_full.index_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw_indexed(
-            0..self.clipmap_full.index_buffer_len,
-            0,
-            end_pxm + full_level * CM_INSTANCE_SIZE_ONE_NXN
-                ..(end_pxm + full_level * CM_INSTANCE_SIZE_ONE_NXN) + CM_INSTANCE_SIZE_ONE_NXN,
-        );
+        for (buffer, range) in [
+            &self.clipmap_ring_mxm,
+            &self.clipmap_ring_mxp,
+            &self.clipmap_ring_pxm,
+            &self.clipmap_full,
+        ]
+        .iter()
+        .zip(visible_ranges)
+        {
+            if range.is_empty() {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, buffer.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(buffer.index_buffer.slice(..));
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.draw_indexed(0..buffer.index_buffer_len, 0, range);
+        }
 
         for level in start_ring_level..CM_MAX_LEVELS {
             //h_bottom
@@ -807,6 +856,28 @@ fn snap_down_to_index(val: f32, level: u32) -> i32 {
     ((val / snap_size).floor() * 2.0) as i32
 }
 
+// World space box of a block of size (in vertices) drawn by an instance around center
+fn block_bounds(
+    instance: &Instance,
+    size: [u32; 2],
+    center: [f32; 2],
+    height_range: [f32; 2],
+) -> Aabb {
+    let unit_size = unit_size_for_level(instance.level);
+    let x = snap_down_to_index(center[0], instance.level) - BASE_OFFSET as i32
+        + instance.offset[0] as i32;
+    let z = snap_down_to_index(center[1], instance.level) - BASE_OFFSET as i32
+        + instance.offset[1] as i32;
+    Aabb::new(
+        vec3(x as f32 * unit_size, height_range[0], z as f32 * unit_size),
+        vec3(
+            (x + size[0] as i32 - 1) as f32 * unit_size,
+            height_range[1],
+            (z + size[1] as i32 - 1) as f32 * unit_size,
+        ),
+    )
+}
+
 fn equal_coords(first: &[i32; 2], second: &[i32; 2]) -> bool {
     first[0] == second[0] && first[1] == second[1]
 }
@@ -840,6 +911,7 @@ pub struct Clipmap {
     center: Option<[f32; 2]>, // this is the center the data is generated around
     size: u32,
     levels: u32,
+    height_ranges: Vec<[f32; 2]>,
 }
 
 impl Clipmap {
@@ -849,6 +921,7 @@ impl Clipmap {
             center: None,
             size,
             levels,
+            height_ranges: vec![[0.0, 0.0]; levels as usize],
         }
     }
     pub fn set_height(&mut self, x: u32, z: u32, level: u32, height: f32) {
@@ -857,6 +930,20 @@ impl Clipmap {
     pub fn get_height(&self, x: u32, z: u32, level: u32) -> f32 {
         self.data[((self.size * self.size * level) + x + z * self.size) as usize].height
     }
+    // lowest and highest height stored for a level, used for the bounds of its blocks
+    pub fn height_range(&self, level: u32) -> [f32; 2] {
+        self.height_ranges[level as usize]
+    }
+
+    fn update_height_range(&mut self, level: u32) {
+        let level_size = (self.size * self.size) as usize;
+        let start = level_size * level as usize;
+        self.height_ranges[level as usize] = self.data[start..start + level_size]
+            .iter()
+            .fold([f32::MAX, f32::MIN], |range, element| {
+                [range[0].min(element.height), range[1].max(element.height)]
+            });
+    }
 
     fn update_xrows(
         &mut self,
@@ -970,6 +1057,7 @@ impl Clipmap {
                         calculate_update_range_1d(previous[1], current_base_z, self.size as i32);
                     self.update_xrows(&xrows, current_base_z, level, &*generator);
                     self.update_zrows(&zrows, current_base_x, level, &*generator);
+                    self.update_height_range(level);
                 }
             } else {
                 self.update_xrows(
@@ -978,11 +1066,35 @@ impl Clipmap {
                     level,
                     generator,
                 );
+                self.update_height_range(level);
             }
         }
         //TODO: as bytes_of_row needs to be a multiple of 256 bytes, we will figure the partial copy out after adding normals
         self.center = Some(center);
     }
+
+    // regions of the ground drawn by every level around the current center, finest first
+    pub fn query_levels(&self) -> Vec<terrain::Level> {
+        let center = match self.center {
+            Some(center) => center,
+            None => return Vec::new(),
+        };
+        (0..self.levels)
+            .map(|level| {
+                let unit_size = unit_size_for_level(level);
+                let base_x = snap_down_to_index(center[0], level) - BASE_OFFSET as i32;
+                let base_z = snap_down_to_index(center[1], level) - BASE_OFFSET as i32;
+                terrain::Level {
+                    unit_size,
+                    min: [base_x as f32 * unit_size, base_z as f32 * unit_size],
+                    max: [
+                        (base_x + CM_N as i32 - 1) as f32 * unit_size,
+                        (base_z + CM_N as i32 - 1) as f32 * unit_size,
+                    ],
+                }
+            })
+            .collect()
+    }
 }
 
 fn calculate_update_range_1d(first: i32, second: i32, size: i32) -> std::ops::Range<i32> {
@@ -1039,3 +1151,40 @@ fn calculate_copy_range_1d_test() {
     assert_eq!(calculate_copy_ranges_1d(&(-2..1), 4), [0..1, 2..4]);
     assert_eq!(calculate_copy_ranges_1d(&(-2..2), 4), [0..4]);
 }
+
+#[test]
+fn block_culling_test() {
+    use nalgebra_glm::{look_at, perspective};
+    // camera above the origin looking along +x
+    let projection = perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 1000.0);
+    let view = look_at(
+        &vec3(0.0, 10.0, 0.0),
+        &vec3(1.0, 10.0, 0.0),
+        &vec3(0.0, 1.0, 0.0),
+    );
+    let frustum = Frustum::from_matrix(&(projection * view));
+    let block = |offset: [u32; 2]| {
+        let instance = Instance {
+            offset,
+            level: 1,
+            padding: 0,
+        };
+        block_bounds(&instance, [CM_M, CM_M], [0.0, 0.0], [-5.0, 5.0])
+    };
+    let front = block([CM_3M1P, CM_1M]);
+    assert_eq!([front.min.x, front.max.x], [132.0, 256.0]);
+    assert_eq!([front.min.z, front.max.z], [-124.0, 0.0]);
+    assert!(frustum.intersects(&front));
+    assert!(!frustum.intersects(&block([0, CM_1M])));
+    let full = block_bounds(
+        &Instance {
+            offset: CM_OFFSET_NXN,
+            level: 0,
+            padding: 0,
+        },
+        [CM_N, CM_N],
+        [0.0, 0.0],
+        [-5.0, 5.0],
+    );
+    assert!(frustum.intersects(&full));
+}
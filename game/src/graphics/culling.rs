@@ -0,0 +1,147 @@
+use nalgebra_glm::{vec3, vec4, Mat4, Vec3, Vec4};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points<'a, I>(points: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Vec3>,
+    {
+        let mut min = vec3(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = vec3(f32::MIN, f32::MIN, f32::MIN);
+        for p in points {
+            min = min.inf(p);
+            max = max.sup(p);
+        }
+        Self { min, max }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [
+            vec3(self.min.x, self.min.y, self.min.z),
+            vec3(self.max.x, self.min.y, self.min.z),
+            vec3(self.min.x, self.max.y, self.min.z),
+            vec3(self.max.x, self.max.y, self.min.z),
+            vec3(self.min.x, self.min.y, self.max.z),
+            vec3(self.max.x, self.min.y, self.max.z),
+            vec3(self.min.x, self.max.y, self.max.z),
+            vec3(self.max.x, self.max.y, self.max.z),
+        ]
+    }
+
+    // box around the transformed corners, so it stays conservative under rotation
+    pub fn transform(&self, m: &Mat4) -> Self {
+        let corners: Vec<Vec3> = self
+            .corners()
+            .iter()
+            .map(|c| {
+                let p = m * vec4(c.x, c.y, c.z, 1.0);
+                vec3(p.x, p.y, p.z)
+            })
+            .collect();
+        Self::from_points(corners.iter())
+    }
+}
+
+// Planes point inwards, a point p is inside when dot(plane.xyz, p) + plane.w >= 0 for all planes
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Extracts the planes from projection * view (Gribb & Hartmann). The near plane is taken for a
+    // -w..w depth range, for wgpu's 0..w this lies behind the real near plane which only makes the
+    // test a bit more conservative.
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let row = |i: usize| vec4(m[(i, 0)], m[(i, 1)], m[(i, 2)], m[(i, 3)]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let normalize = |p: Vec4| p / vec3(p.x, p.y, p.z).norm();
+        Self {
+            planes: [
+                normalize(r3 + r0),
+                normalize(r3 - r0),
+                normalize(r3 + r1),
+                normalize(r3 - r1),
+                normalize(r3 + r2),
+                normalize(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, p: &Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.x * p.x + plane.y * p.y + plane.z * p.z + plane.w >= 0.0)
+    }
+
+    // only rejects a box if it lies completely outside one of the planes
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let p = vec3(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.x * p.x + plane.y * p.y + plane.z * p.z + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+fn test_frustum() -> Frustum {
+    use nalgebra_glm::{look_at, perspective};
+    // camera at (0, 10, 20) looking at the origin, 90 degrees field of view
+    let projection = perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0);
+    let view = look_at(
+        &vec3(0.0, 10.0, 20.0),
+        &vec3(0.0, 0.0, 0.0),
+        &vec3(0.0, 1.0, 0.0),
+    );
+    Frustum::from_matrix(&(projection * view))
+}
+
+#[test]
+fn frustum_points_test() {
+    let frustum = test_frustum();
+    assert!(frustum.contains_point(&vec3(0.0, 0.0, 0.0)));
+    assert!(frustum.contains_point(&vec3(5.0, 2.0, -10.0)));
+    // behind the camera
+    assert!(!frustum.contains_point(&vec3(0.0, 12.0, 30.0)));
+    // beyond the far plane
+    assert!(!frustum.contains_point(&vec3(0.0, -40.0, -80.0)));
+    // far to the side
+    assert!(!frustum.contains_point(&vec3(50.0, 0.0, 0.0)));
+}
+
+#[test]
+fn frustum_boxes_test() {
+    let frustum = test_frustum();
+    let unit = Aabb::new(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5));
+    assert!(frustum.intersects(&unit));
+    assert!(!frustum.intersects(&Aabb::new(vec3(49.5, -0.5, -0.5), vec3(50.5, 0.5, 0.5))));
+    // straddles the left plane, center is outside but the box is still visible
+    assert!(frustum.intersects(&Aabb::new(vec3(-40.0, -1.0, -1.0), vec3(-10.0, 1.0, 1.0))));
+    let moved = unit.transform(&nalgebra_glm::translation(&vec3(0.0, 0.0, 40.0)));
+    assert!(!frustum.intersects(&moved));
+    assert!((moved.min - vec3(-0.5, -0.5, 39.5)).norm() < 1e-6);
+}
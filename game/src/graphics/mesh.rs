@@ -1,4 +1,5 @@
 use crate::graphics;
+use crate::graphics::culling::{Aabb, Frustum};
 use crate::graphics::error::GraphicsError;
 use crate::graphics::{texture, Drawables};
 use nalgebra_glm::{identity, triangle_normal, vec3, Mat4, Vec3};
@@ -268,7 +269,8 @@ impl Renderer {
             contents: bytemuck::cast_slice(vs.as_slice()),
             usage: wgpu::BufferUsage::VERTEX,
         });
-        self.drawables.add_drawable(name, vb, vs.len());
+        let bounds = Aabb::from_points(vs.iter().map(|v| &v.position));
+        self.drawables.add_drawable(name, vb, vs.len(), bounds);
     }
 
     pub fn add_entities(&mut self, mapping: &[(u32, &String)]) {
@@ -297,22 +299,22 @@ impl Renderer {
         };
         assert!(entities.len() <= MAX_NUMBER_OF_INSTANCES);
         queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        let frustum = Frustum::from_matrix(&(projection * view));
         let mut instances = Vec::new();
         let mut instance_ranges = Vec::new();
-        let mut end_previous = 0;
         for draw_description in &self.drawables.draw_descriptions {
+            let start = instances.len() as u32;
             instances.extend(draw_description.entity_ids.iter().filter_map(|id| {
-                let model = entities.get(id);
-                if let Some(model) = model {
-                    return Some(Instance {
+                let model = entities.get(id)?;
+                if frustum.intersects(&draw_description.bounds.transform(model)) {
+                    Some(Instance {
                         model: model.clone(),
-                    });
+                    })
                 } else {
-                    return None;
+                    None
                 }
             }));
-            instance_ranges.push(end_previous as u32..(end_previous + instances.len()) as u32);
-            end_previous += instances.len();
+            instance_ranges.push(start..instances.len() as u32);
         }
         queue.write_buffer(
             &self.instance_buffer,
@@ -322,6 +324,9 @@ impl Renderer {
         render_pass.set_pipeline(&self.render_pipeline);
 
         for (index, draw_description) in self.drawables.draw_descriptions.iter().enumerate() {
+            if instance_ranges[index].is_empty() {
+                continue;
+            }
             render_pass
                 .set_vertex_buffer(0, self.drawables.buffers[draw_description.vbi].slice(..));
             render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
@@ -5,6 +5,7 @@ use wgpu::util::DeviceExt;
 use winit::window::Window;
 
 pub mod clipmap;
+pub mod culling;
 pub mod error;
 pub mod mesh;
 pub mod texture;
@@ -16,6 +17,7 @@ pub struct DrawDescription {
     name: String,
     vbi: usize,
     vb_len: usize,
+    bounds: culling::Aabb,
     entity_ids: HashSet<u32>,
 }
 
@@ -37,12 +39,19 @@ impl Drawables {
             draw_descriptions: vec![],
         }
     }
-    pub fn add_drawable(&mut self, name: String, vertex_buffer: wgpu::Buffer, vb_len: usize) {
+    pub fn add_drawable(
+        &mut self,
+        name: String,
+        vertex_buffer: wgpu::Buffer,
+        vb_len: usize,
+        bounds: culling::Aabb,
+    ) {
         self.buffers.push(vertex_buffer);
         self.draw_descriptions.push(DrawDescription {
             name,
             vbi: self.buffers.len() - 1,
             vb_len,
+            bounds,
             entity_ids: HashSet::new(),
         })
     }
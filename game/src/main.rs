@@ -20,6 +20,10 @@ fn main() {
     let meshes = from_config::create_model_meshes(config.models.as_slice());
     let (mapping, mut entities) = from_config::create_entities(config.entities.as_slice());
     let mut cameras = from_config::create_cameras(config.cameras.as_slice());
+    if let Some(terrain) = &config.terrain {
+        graphics.clipmap_renderer.generator =
+            from_config::create_terrain(terrain).expect("Could not create terrain");
+    }
 
     for m in meshes {
         graphics.add_mesh_with_name(m.0, m.1.into_iter());
@@ -0,0 +1,42 @@
+use crate::terrain::Generator;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Operation {
+    Add,
+    Multiply,
+    Min,
+    Max,
+}
+
+pub struct Combine {
+    operation: Operation,
+    first: Box<dyn Generator>,
+    second: Box<dyn Generator>,
+}
+
+impl Combine {
+    pub fn new(
+        operation: Operation,
+        first: Box<dyn Generator>,
+        second: Box<dyn Generator>,
+    ) -> Self {
+        Self {
+            operation,
+            first,
+            second,
+        }
+    }
+}
+
+impl Generator for Combine {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        let first = self.first.generate(pos);
+        let second = self.second.generate(pos);
+        match self.operation {
+            Operation::Add => first + second,
+            Operation::Multiply => first * second,
+            Operation::Min => first.min(second),
+            Operation::Max => first.max(second),
+        }
+    }
+}
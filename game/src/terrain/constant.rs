@@ -0,0 +1,9 @@
+use crate::terrain::Generator;
+
+pub struct Constant(pub f32);
+
+impl Generator for Constant {
+    fn generate(&self, _pos: [f32; 2]) -> f32 {
+        self.0
+    }
+}
@@ -0,0 +1,34 @@
+use crate::terrain::Generator;
+
+// Offsets the position the source is sampled at by two other generators, this bends ridges and valleys
+pub struct DomainWarp {
+    source: Box<dyn Generator>,
+    warp_x: Box<dyn Generator>,
+    warp_z: Box<dyn Generator>,
+    strength: f32,
+}
+
+impl DomainWarp {
+    pub fn new(
+        source: Box<dyn Generator>,
+        warp_x: Box<dyn Generator>,
+        warp_z: Box<dyn Generator>,
+        strength: f32,
+    ) -> Self {
+        Self {
+            source,
+            warp_x,
+            warp_z,
+            strength,
+        }
+    }
+}
+
+impl Generator for DomainWarp {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        self.source.generate([
+            pos[0] + self.warp_x.generate(pos) * self.strength,
+            pos[1] + self.warp_z.generate(pos) * self.strength,
+        ])
+    }
+}
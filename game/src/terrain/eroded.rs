@@ -0,0 +1,77 @@
+use crate::terrain::Generator;
+use xp_terrain::{erode, Erosion, HeightGrid, HydraulicParameters, ThermalParameters, Wrap};
+
+// Erodes a cells x cells region of the source starting at origin once at creation. Outside that
+// region the source is returned unchanged.
+pub struct Eroded {
+    source: Box<dyn Generator>,
+    erosion: Erosion,
+    origin: [f32; 2],
+}
+
+impl Eroded {
+    pub fn new(
+        source: Box<dyn Generator>,
+        origin: [f32; 2],
+        cells: usize,
+        cell_size: f32,
+        iterations: usize,
+        hydraulic: &HydraulicParameters,
+        thermal: &ThermalParameters,
+    ) -> Self {
+        // at least one cell to erode, fewer samples than that leave nothing to interpolate
+        let cells = cells.max(2);
+        let grid = HeightGrid::from_fn(cells, cells, cell_size, |x, z| {
+            source.generate([
+                origin[0] + x as f32 * cell_size,
+                origin[1] + z as f32 * cell_size,
+            ])
+        });
+        Self {
+            erosion: erode(&grid, iterations, hydraulic, thermal),
+            source,
+            origin,
+        }
+    }
+
+    pub fn flow(&self) -> &HeightGrid {
+        &self.erosion.flow
+    }
+
+    pub fn sediment(&self) -> &HeightGrid {
+        &self.erosion.sediment
+    }
+}
+
+impl Generator for Eroded {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        let heights = &self.erosion.heights;
+        let x = (pos[0] - self.origin[0]) / heights.cell_size;
+        let z = (pos[1] - self.origin[1]) / heights.cell_size;
+        if x >= 0.0
+            && z >= 0.0
+            && x <= (heights.width - 1) as f32
+            && z <= (heights.height - 1) as f32
+        {
+            heights.sample(x, z, Wrap::Clamp)
+        } else {
+            self.source.generate(pos)
+        }
+    }
+}
+
+#[test]
+fn eroded_clamps_cells_test() {
+    let eroded = Eroded::new(
+        Box::new(crate::terrain::Constant(1.0)),
+        [0.0, 0.0],
+        0,
+        2.0,
+        1,
+        &Default::default(),
+        &Default::default(),
+    );
+    assert_eq!(eroded.flow().width, 2);
+    assert_eq!(eroded.generate([1.0, 1.0]), 1.0);
+    assert_eq!(eroded.generate([-1.0, 0.0]), 1.0);
+}
@@ -1,5 +1,5 @@
 use crate::terrain::Generator;
-use noise::NoiseFn;
+use noise::{NoiseFn, Seedable};
 
 pub struct Fbm {
     noise: noise::Fbm,
@@ -21,3 +21,22 @@ impl Generator for Fbm {
             * 10.0) as f32
     }
 }
+
+// Unscaled fbm, roughly in [-1, 1] with features of about one unit, meant to be shaped by the combinators
+pub struct Noise {
+    noise: noise::Fbm,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            noise: noise::Fbm::new().set_seed(seed),
+        }
+    }
+}
+
+impl Generator for Noise {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        self.noise.get([pos[0] as f64, pos[1] as f64]) as f32
+    }
+}
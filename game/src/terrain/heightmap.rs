@@ -0,0 +1,91 @@
+use crate::terrain::Generator;
+use xp_terrain::{HeightGrid, Wrap};
+
+// Grayscale heightmap image, sampled bilinearly in world coordinates. Pixel (0, 0) lies at origin and
+// every pixel covers metres_per_pixel, black maps to height_range[0] and white to height_range[1].
+pub struct Heightmap {
+    // the pixels from 0 (black) to 1 (white), a cell per pixel
+    pixels: HeightGrid,
+    origin: [f32; 2],
+    height_range: [f32; 2],
+    wrap: Wrap,
+}
+
+impl Heightmap {
+    pub fn new(
+        image: &image::ImageBuffer<image::Luma<u16>, Vec<u16>>,
+        origin: [f32; 2],
+        metres_per_pixel: f32,
+        height_range: [f32; 2],
+        wrap: Wrap,
+    ) -> Self {
+        assert!(metres_per_pixel > 0.0);
+        let (width, height) = image.dimensions();
+        Self {
+            pixels: HeightGrid {
+                width: width as usize,
+                height: height as usize,
+                cell_size: metres_per_pixel,
+                data: image
+                    .pixels()
+                    .map(|p| p.0[0] as f32 / std::u16::MAX as f32)
+                    .collect(),
+            },
+            origin,
+            height_range,
+            wrap,
+        }
+    }
+
+    // 8 bit images are widened to 16 bit, so both resolve to the same height range
+    pub fn open(
+        path: &str,
+        origin: [f32; 2],
+        metres_per_pixel: f32,
+        height_range: [f32; 2],
+        wrap: Wrap,
+    ) -> image::ImageResult<Self> {
+        Ok(Self::new(
+            &image::open(path)?.to_luma16(),
+            origin,
+            metres_per_pixel,
+            height_range,
+            wrap,
+        ))
+    }
+
+    // world size covered by the image in metres
+    pub fn size(&self) -> [f32; 2] {
+        [
+            self.pixels.width as f32 * self.pixels.cell_size,
+            self.pixels.height as f32 * self.pixels.cell_size,
+        ]
+    }
+}
+
+impl Generator for Heightmap {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        let x = (pos[0] - self.origin[0]) / self.pixels.cell_size;
+        let z = (pos[1] - self.origin[1]) / self.pixels.cell_size;
+        let normalized = self.pixels.sample(x, z, self.wrap);
+        self.height_range[0] + normalized * (self.height_range[1] - self.height_range[0])
+    }
+}
+
+#[test]
+fn heightmap_bilinear_test() {
+    let image =
+        image::ImageBuffer::from_vec(2, 2, vec![0, std::u16::MAX, 0, std::u16::MAX]).unwrap();
+    let clamped = Heightmap::new(&image, [0.0, 0.0], 10.0, [-5.0, 15.0], Wrap::Clamp);
+    assert_eq!(clamped.generate([0.0, 0.0]), -5.0);
+    assert_eq!(clamped.generate([5.0, 3.0]), 5.0);
+    assert_eq!(clamped.generate([10.0, 10.0]), 15.0);
+    assert_eq!(clamped.generate([100.0, -100.0]), 15.0);
+    assert_eq!(clamped.generate([-100.0, 0.0]), -5.0);
+
+    let tiled = Heightmap::new(&image, [0.0, 0.0], 10.0, [-5.0, 15.0], Wrap::Tile);
+    // halfway between the white column and the first column of the next tile
+    assert_eq!(tiled.generate([15.0, 0.0]), 5.0);
+    assert_eq!(tiled.generate([20.0, 0.0]), -5.0);
+    assert_eq!(tiled.size(), [20.0, 20.0]);
+}
@@ -0,0 +1,44 @@
+use crate::terrain::Generator;
+use xp_terrain::{HeightGrid, Wrap};
+
+// Grayscale image stretched over a world rectangle, returns [0, 1] inside and 0 outside
+pub struct ImageMask {
+    // the pixels from 0 (black) to 1 (white), a cell per pixel
+    pixels: HeightGrid,
+    origin: [f32; 2],
+    size: [f32; 2],
+}
+
+impl ImageMask {
+    pub fn new(image: image::GrayImage, origin: [f32; 2], size: [f32; 2]) -> Self {
+        assert!(size[0] > 0.0 && size[1] > 0.0);
+        let (width, height) = image.dimensions();
+        Self {
+            pixels: HeightGrid {
+                width: width as usize,
+                height: height as usize,
+                cell_size: 1.0,
+                data: image.pixels().map(|p| p.0[0] as f32 / 255.0).collect(),
+            },
+            origin,
+            size,
+        }
+    }
+
+    pub fn open(path: &str, origin: [f32; 2], size: [f32; 2]) -> image::ImageResult<Self> {
+        Ok(Self::new(image::open(path)?.to_luma8(), origin, size))
+    }
+}
+
+impl Generator for ImageMask {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        let u = (pos[0] - self.origin[0]) / self.size[0];
+        let v = (pos[1] - self.origin[1]) / self.size[1];
+        if u < 0.0 || u > 1.0 || v < 0.0 || v > 1.0 {
+            return 0.0;
+        }
+        let x = u * (self.pixels.width - 1) as f32;
+        let y = v * (self.pixels.height - 1) as f32;
+        self.pixels.sample(x, y, Wrap::Clamp)
+    }
+}
@@ -1,7 +1,27 @@
+pub mod combine;
+pub mod constant;
+pub mod domain_warp;
+pub mod eroded;
 pub mod fbm;
 pub mod generator;
+pub mod heightmap;
+pub mod image_mask;
+pub mod query;
+pub mod ridged;
+pub mod scale_offset;
 pub mod sine;
+pub mod terrace;
 
-pub use fbm::Fbm;
+pub use combine::{Combine, Operation};
+pub use constant::Constant;
+pub use domain_warp::DomainWarp;
+pub use eroded::Eroded;
+pub use fbm::{Fbm, Noise};
 pub use generator::Generator;
+pub use heightmap::Heightmap;
+pub use image_mask::ImageMask;
+pub use query::{Level, RayHit, TerrainQuery};
+pub use ridged::Ridged;
+pub use scale_offset::ScaleOffset;
 pub use sine::Sine;
+pub use terrace::Terrace;
@@ -0,0 +1,215 @@
+use crate::terrain::Generator;
+use nalgebra_glm::{vec3, Vec3};
+
+// Square region of the ground drawn with a grid of unit_size, heights are sampled on the grid
+// points and every cell is split into two triangles along the (x + 1, z) - (x, z + 1) diagonal,
+// the same way clipmap::create_grid does.
+#[derive(Debug, Copy, Clone)]
+pub struct Level {
+    pub unit_size: f32,
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Level {
+    fn contains(&self, pos: [f32; 2]) -> bool {
+        pos[0] >= self.min[0]
+            && pos[0] <= self.max[0]
+            && pos[1] >= self.min[1]
+            && pos[1] <= self.max[1]
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+pub struct TerrainQuery<'a> {
+    generator: &'a dyn Generator,
+    // finest first, positions outside of every level use the last (coarsest) one
+    levels: Vec<Level>,
+}
+
+impl<'a> TerrainQuery<'a> {
+    pub fn new(generator: &'a dyn Generator, levels: Vec<Level>) -> Self {
+        assert!(!levels.is_empty());
+        Self { generator, levels }
+    }
+
+    pub fn uniform(generator: &'a dyn Generator, unit_size: f32) -> Self {
+        Self::new(
+            generator,
+            vec![Level {
+                unit_size,
+                min: [f32::MIN, f32::MIN],
+                max: [f32::MAX, f32::MAX],
+            }],
+        )
+    }
+
+    fn unit_size_at(&self, pos: [f32; 2]) -> f32 {
+        self.levels
+            .iter()
+            .find(|level| level.contains(pos))
+            .unwrap_or_else(|| self.levels.last().unwrap())
+            .unit_size
+    }
+
+    // height of the triangle containing pos and its (not normalized) normal
+    fn triangle_at(&self, pos: [f32; 2]) -> (f32, Vec3) {
+        let unit_size = self.unit_size_at(pos);
+        let x = pos[0] / unit_size;
+        let z = pos[1] / unit_size;
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let height = |dx: f32, dz: f32| {
+            self.generator
+                .generate([(x0 + dx) * unit_size, (z0 + dz) * unit_size])
+        };
+        if fx + fz <= 1.0 {
+            let h00 = height(0.0, 0.0);
+            let slope_x = height(1.0, 0.0) - h00;
+            let slope_z = height(0.0, 1.0) - h00;
+            (
+                h00 + fx * slope_x + fz * slope_z,
+                vec3(-slope_x, unit_size, -slope_z),
+            )
+        } else {
+            let h11 = height(1.0, 1.0);
+            let slope_x = h11 - height(0.0, 1.0);
+            let slope_z = h11 - height(1.0, 0.0);
+            (
+                h11 - (1.0 - fx) * slope_x - (1.0 - fz) * slope_z,
+                vec3(-slope_x, unit_size, -slope_z),
+            )
+        }
+    }
+
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.triangle_at([x, z]).0
+    }
+
+    pub fn normal_at(&self, x: f32, z: f32) -> Vec3 {
+        self.triangle_at([x, z]).1.normalize()
+    }
+
+    // Marches along the ray in steps of a quarter of the local unit size and refines the first
+    // crossing of the ground by bisection
+    pub fn raycast(&self, origin: &Vec3, direction: &Vec3, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.normalize();
+        let above = |distance: f32| {
+            let p = origin + direction * distance;
+            p.y - self.height_at(p.x, p.z)
+        };
+        if above(0.0) < 0.0 {
+            return None;
+        }
+        let mut previous = 0.0;
+        while previous < max_distance {
+            let p = origin + direction * previous;
+            let step = self.unit_size_at([p.x, p.z]) * 0.25;
+            let current = (previous + step).min(max_distance);
+            if above(current) <= 0.0 {
+                let (mut low, mut high) = (previous, current);
+                for _ in 0..24 {
+                    let mid = (low + high) * 0.5;
+                    if above(mid) > 0.0 {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                let position = origin + direction * high;
+                return Some(RayHit {
+                    position: vec3(
+                        position.x,
+                        self.height_at(position.x, position.z),
+                        position.z,
+                    ),
+                    normal: self.normal_at(position.x, position.z),
+                    distance: high,
+                });
+            }
+            previous = current;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+struct Slope;
+
+#[cfg(test)]
+impl Generator for Slope {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        0.5 * pos[0] + 2.0
+    }
+}
+
+#[cfg(test)]
+struct Bump;
+
+#[cfg(test)]
+impl Generator for Bump {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        if pos[0] == 2.0 && pos[1] == 2.0 {
+            4.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[test]
+fn height_and_normal_on_plane_test() {
+    let query = TerrainQuery::uniform(&Slope, 2.0);
+    assert_eq!(query.height_at(0.0, 0.0), 2.0);
+    assert_eq!(query.height_at(3.0, -7.5), 3.5);
+    let normal = query.normal_at(1.0, 1.0);
+    let expected = vec3(-0.5, 1.0, 0.0).normalize();
+    assert!((normal - expected).norm() < 1e-6);
+}
+
+#[test]
+fn height_follows_clipmap_triangles_test() {
+    // the grid point (2, 2) is raised, the cell (0, 0) - (2, 2) only sees it in its second triangle
+    let query = TerrainQuery::uniform(&Bump, 2.0);
+    assert_eq!(query.height_at(0.5, 0.5), 0.0);
+    assert_eq!(query.height_at(1.5, 1.5), 2.0);
+    assert_eq!(query.height_at(2.0, 2.0), 4.0);
+    // a finer level around the origin does not see the bump at all
+    let levels = vec![
+        Level {
+            unit_size: 1.0,
+            min: [-1.0, -1.0],
+            max: [1.0, 1.0],
+        },
+        Level {
+            unit_size: 4.0,
+            min: [-8.0, -8.0],
+            max: [8.0, 8.0],
+        },
+    ];
+    let query = TerrainQuery::new(&Bump, levels);
+    assert_eq!(query.height_at(0.5, 0.5), 0.0);
+    assert_eq!(query.height_at(3.0, 3.0), 0.0);
+}
+
+#[test]
+fn raycast_test() {
+    let query = TerrainQuery::uniform(&Slope, 2.0);
+    let hit = query
+        .raycast(&vec3(0.0, 10.0, 0.0), &vec3(0.0, -1.0, 0.0), 100.0)
+        .unwrap();
+    assert!((hit.distance - 8.0).abs() < 1e-3);
+    assert!((hit.position - vec3(0.0, 2.0, 0.0)).norm() < 1e-3);
+    assert!(query
+        .raycast(&vec3(0.0, 10.0, 0.0), &vec3(0.0, 1.0, 0.0), 100.0)
+        .is_none());
+    assert!(query
+        .raycast(&vec3(0.0, 10.0, 0.0), &vec3(0.0, -1.0, 0.0), 5.0)
+        .is_none());
+}
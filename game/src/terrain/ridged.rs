@@ -0,0 +1,18 @@
+use crate::terrain::Generator;
+
+// Folds the source around zero, which turns the zero crossings of noise into sharp ridges
+pub struct Ridged {
+    source: Box<dyn Generator>,
+}
+
+impl Ridged {
+    pub fn new(source: Box<dyn Generator>) -> Self {
+        Self { source }
+    }
+}
+
+impl Generator for Ridged {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        1.0 - self.source.generate(pos).abs()
+    }
+}
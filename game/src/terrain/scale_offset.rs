@@ -0,0 +1,35 @@
+use crate::terrain::Generator;
+
+// Samples the source at pos / input_scale and returns height * output_scale + offset
+pub struct ScaleOffset {
+    source: Box<dyn Generator>,
+    input_scale: f32,
+    output_scale: f32,
+    offset: f32,
+}
+
+impl ScaleOffset {
+    pub fn new(
+        source: Box<dyn Generator>,
+        input_scale: f32,
+        output_scale: f32,
+        offset: f32,
+    ) -> Self {
+        assert!(input_scale != 0.0);
+        Self {
+            source,
+            input_scale,
+            output_scale,
+            offset,
+        }
+    }
+}
+
+impl Generator for ScaleOffset {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        self.source
+            .generate([pos[0] / self.input_scale, pos[1] / self.input_scale])
+            * self.output_scale
+            + self.offset
+    }
+}
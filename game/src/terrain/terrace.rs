@@ -0,0 +1,27 @@
+use crate::terrain::Generator;
+
+// Quantizes the height into steps of step_height, a sharpness of 1.0 keeps the slopes, higher values flatten the steps
+pub struct Terrace {
+    source: Box<dyn Generator>,
+    step_height: f32,
+    sharpness: f32,
+}
+
+impl Terrace {
+    pub fn new(source: Box<dyn Generator>, step_height: f32, sharpness: f32) -> Self {
+        assert!(step_height > 0.0);
+        Self {
+            source,
+            step_height,
+            sharpness,
+        }
+    }
+}
+
+impl Generator for Terrace {
+    fn generate(&self, pos: [f32; 2]) -> f32 {
+        let steps = self.source.generate(pos) / self.step_height;
+        let base = steps.floor();
+        (base + (steps - base).powf(self.sharpness)) * self.step_height
+    }
+}
@@ -0,0 +1,158 @@
+use crate::transform::{slerp, Transform};
+use glam::{Quat, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    // keeps the value of the previous keyframe until the next one
+    Step,
+}
+
+#[derive(Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+// Animates one property of a node, a value per time.
+#[derive(Clone)]
+pub struct Channel {
+    // index of the node in the scene
+    pub node: usize,
+    pub interpolation: Interpolation,
+    // in seconds, increasing
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl Channel {
+    // The keyframes around time and how far time is from the first to the second, before the first and
+    // after the last keyframe the value is held.
+    fn keyframes_around(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.iter().position(|t| *t > time).unwrap_or(self.times.len());
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        let previous = next - 1;
+        if next == self.times.len() {
+            return (previous, previous, 0.0);
+        }
+        let s = match self.interpolation {
+            Interpolation::Linear => (time - self.times[previous]) / (self.times[next] - self.times[previous]),
+            Interpolation::Step => 0.0,
+        };
+        (previous, next, s)
+    }
+
+    pub fn sample(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (previous, next, s) = self.keyframes_around(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => transform.translation = values[previous].lerp(values[next], s),
+            Keyframes::Rotation(values) => transform.rotation = slerp(values[previous], values[next], s),
+            Keyframes::Scale(values) => transform.scale = values[previous].lerp(values[next], s),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    // time of the last keyframe
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    pub fn new(name: Option<String>, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, time| duration.max(*time));
+        Self {
+            name,
+            channels,
+            duration,
+        }
+    }
+
+    // Overwrites the animated properties in pose, the local transform of every node.
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            channel.sample(time, &mut pose[channel.node]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::{AnimationClip, Channel, Interpolation, Keyframes},
+        transform::Transform,
+    };
+    use glam::{Quat, Vec3};
+
+    fn translation(interpolation: Interpolation) -> Channel {
+        Channel {
+            node: 1,
+            interpolation,
+            times: vec![1.0, 2.0, 4.0],
+            keyframes: Keyframes::Translation(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(2.0, 4.0, 0.0),
+            ]),
+        }
+    }
+
+    fn sample(channel: &Channel, time: f32) -> Vec3 {
+        let mut transform = Transform::identity();
+        channel.sample(time, &mut transform);
+        transform.translation
+    }
+
+    #[test]
+    fn linear_interpolates_between_keyframes() {
+        let channel = translation(Interpolation::Linear);
+        assert_eq!(sample(&channel, 1.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(sample(&channel, 3.0), Vec3::new(2.0, 2.0, 0.0));
+        // held before the first and after the last keyframe
+        assert_eq!(sample(&channel, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(sample(&channel, 5.0), Vec3::new(2.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn step_holds_the_previous_keyframe() {
+        let channel = translation(Interpolation::Step);
+        assert_eq!(sample(&channel, 1.9), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(sample(&channel, 2.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(sample(&channel, 3.9), Vec3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rotations_take_the_shortest_way() {
+        let channel = Channel {
+            node: 0,
+            interpolation: Interpolation::Linear,
+            times: vec![0.0, 1.0],
+            // -q is the same rotation as q, so nothing should move
+            keyframes: Keyframes::Rotation(vec![Quat::from_rotation_y(0.5), -Quat::from_rotation_y(0.5)]),
+        };
+        let mut transform = Transform::identity();
+        channel.sample(0.5, &mut transform);
+        assert!(transform.rotation.dot(Quat::from_rotation_y(0.5)).abs() > 0.9999);
+    }
+
+    #[test]
+    fn clip_only_changes_animated_nodes() {
+        let clip = AnimationClip::new(Some("move".to_string()), vec![translation(Interpolation::Linear)]);
+        assert_eq!(clip.duration, 4.0);
+        let mut pose = vec![Transform::from_scale(Vec3::new(2.0, 2.0, 2.0)), Transform::identity()];
+        clip.sample(2.0, &mut pose);
+        assert_eq!(pose[0].scale, Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(pose[1].translation, Vec3::new(2.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,7 @@
+mod clip;
+mod player;
+mod skin;
+
+pub use clip::{AnimationClip, Channel, Interpolation, Keyframes};
+pub use player::AnimationPlayer;
+pub use skin::{Skin, SkinnedMesh};
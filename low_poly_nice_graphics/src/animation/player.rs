@@ -0,0 +1,146 @@
+use crate::{animation::AnimationClip, transform::Transform};
+
+#[derive(Clone, Copy)]
+struct Playing {
+    // index into the clips
+    clip: usize,
+    time: f32,
+}
+
+// Plays one clip at a time, a newly played clip fades in over the clip that was playing.
+pub struct AnimationPlayer {
+    pub speed: f32,
+    pub looping: bool,
+    current: Option<Playing>,
+    previous: Option<Playing>,
+    // seconds since the current clip started fading in
+    fade_time: f32,
+    fade_duration: f32,
+}
+
+impl AnimationPlayer {
+    // Does nothing when the clip is already playing.
+    pub fn play(&mut self, clip: usize, fade_duration: f32) {
+        if self.playing() == Some(clip) {
+            return;
+        }
+        self.previous = if fade_duration > 0.0 { self.current } else { None };
+        self.current = Some(Playing { clip, time: 0.0 });
+        self.fade_time = 0.0;
+        self.fade_duration = fade_duration;
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.previous = None;
+    }
+
+    pub fn playing(&self) -> Option<usize> {
+        self.current.map(|playing| playing.clip)
+    }
+
+    pub fn update(&mut self, delta_time: f32, clips: &[AnimationClip]) {
+        let (speed, looping) = (self.speed, self.looping);
+        for playing in self.current.iter_mut().chain(self.previous.iter_mut()) {
+            let duration = clips[playing.clip].duration;
+            playing.time += delta_time * speed;
+            playing.time = if looping && duration > 0.0 {
+                playing.time.rem_euclid(duration)
+            } else {
+                playing.time.clamp(0.0, duration)
+            };
+        }
+        self.fade_time += delta_time;
+        if self.fade_time >= self.fade_duration {
+            self.previous = None;
+        }
+    }
+
+    // The local transform of every node, nodes that are not animated keep their transform from rest.
+    pub fn pose(&self, clips: &[AnimationClip], rest: &[Transform]) -> Vec<Transform> {
+        let mut pose = rest.to_vec();
+        if let Some(current) = self.current {
+            clips[current.clip].sample(current.time, &mut pose);
+        }
+        if let Some(previous) = self.previous {
+            let mut from = rest.to_vec();
+            clips[previous.clip].sample(previous.time, &mut from);
+            let weight = self.fade_time / self.fade_duration;
+            pose = from
+                .iter()
+                .zip(pose.iter())
+                .map(|(from, to)| from.lerp(to, weight))
+                .collect();
+        }
+        pose
+    }
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            looping: true,
+            current: None,
+            previous: None,
+            fade_time: 0.0,
+            fade_duration: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::{AnimationClip, AnimationPlayer, Channel, Interpolation, Keyframes},
+        transform::Transform,
+    };
+    use glam::Vec3;
+
+    // moves node 0 from x to x + 1 in one second
+    fn clip(x: f32) -> AnimationClip {
+        AnimationClip::new(
+            None,
+            vec![Channel {
+                node: 0,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 1.0],
+                keyframes: Keyframes::Translation(vec![Vec3::new(x, 0.0, 0.0), Vec3::new(x + 1.0, 0.0, 0.0)]),
+            }],
+        )
+    }
+
+    fn x(player: &AnimationPlayer, clips: &[AnimationClip]) -> f32 {
+        player.pose(clips, &[Transform::identity()])[0].translation.x
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let clips = vec![clip(0.0)];
+        let mut player = AnimationPlayer::default();
+        assert_eq!(x(&player, &clips), 0.0);
+        player.play(0, 0.0);
+        player.update(1.25, &clips);
+        assert!((x(&player, &clips) - 0.25).abs() < 1e-5);
+        player.looping = false;
+        player.update(1.0, &clips);
+        assert_eq!(x(&player, &clips), 1.0);
+    }
+
+    #[test]
+    fn fades_between_clips() {
+        let clips = vec![clip(0.0), clip(10.0)];
+        let mut player = AnimationPlayer {
+            speed: 0.0,
+            ..Default::default()
+        };
+        player.play(0, 0.0);
+        player.play(1, 0.5);
+        player.update(0.25, &clips);
+        assert!((x(&player, &clips) - 5.0).abs() < 1e-5);
+        // playing the same clip again does not restart the fade
+        player.play(1, 0.5);
+        player.update(0.25, &clips);
+        assert_eq!(x(&player, &clips), 10.0);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    mesh::{Mesh, Vertex},
+    registry::Handle,
+};
+use glam::{Mat4, Vec3};
+
+// The joints are nodes of the scene, an inverse bind matrix moves a vertex of the mesh into the space of its joint.
+#[derive(Clone)]
+pub struct Skin {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    // world_matrices has a matrix for every node of the scene
+    pub fn joint_matrices(&self, world_matrices: &[Mat4]) -> Vec<Mat4> {
+        self.joints
+            .iter()
+            .zip(self.inverse_bind_matrices.iter())
+            .map(|(joint, inverse_bind_matrix)| world_matrices[*joint] * *inverse_bind_matrix)
+            .collect()
+    }
+}
+
+// A mesh that is deformed on the cpu, every time it is skinned the vertices of the mesh are replaced.
+pub struct SkinnedMesh {
+    pub mesh_handle: Handle<Mesh>,
+    // index into the skins of the scene
+    pub skin: usize,
+    pub bind_pose: Vec<Vertex>,
+    // per vertex, indices into Skin::joints
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+impl SkinnedMesh {
+    pub fn skin(&self, joint_matrices: &[Mat4], mesh: &mut Mesh) {
+        let vertex_count = mesh.vertices.len();
+        mesh.vertices = self
+            .bind_pose
+            .iter()
+            .zip(self.joints.iter().zip(self.weights.iter()))
+            .map(|(vertex, (joints, weights))| {
+                let total = weights.iter().sum::<f32>();
+                // not bound to any joint, it stays where the bind pose has it
+                if total <= 0.0 {
+                    return *vertex;
+                }
+                let mut matrix = joint_matrices[joints[0] as usize] * (weights[0] / total);
+                for i in 1..4 {
+                    matrix = matrix + joint_matrices[joints[i] as usize] * (weights[i] / total);
+                }
+                Vertex {
+                    position: matrix.transform_point3(Vec3::from(vertex.position)).into(),
+                    normal: matrix.transform_vector3(Vec3::from(vertex.normal)).normalize().into(),
+                    ..*vertex
+                }
+            })
+            .collect();
+        // the buffers only have to be built again when the number of vertices changed
+        if mesh.vertices.len() == vertex_count {
+            mesh.vertices_changed = true;
+        } else {
+            mesh.just_loaded = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::SkinnedMesh,
+        mesh::{Mesh, Vertex},
+        registry::Handle,
+    };
+    use glam::{Mat4, Quat, Vec3};
+
+    #[test]
+    fn vertices_follow_their_weighted_joints() {
+        let vertex = Vertex::new([1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+        let skinned_mesh = SkinnedMesh {
+            mesh_handle: Handle::new(0),
+            skin: 0,
+            bind_pose: vec![vertex, vertex, vertex],
+            joints: vec![[0, 0, 0, 0], [0, 1, 0, 0], [0, 1, 0, 0]],
+            weights: vec![[1.0, 0.0, 0.0, 0.0], [0.5, 0.5, 0.0, 0.0], [0.0; 4]],
+        };
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            just_loaded: false,
+            vertices_changed: false,
+            lods: Vec::new(),
+        };
+        let joint_matrices = [
+            Mat4::from_rotation_translation(
+                Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                Vec3::new(0.0, 0.0, 0.0),
+            ),
+            Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+        ];
+        skinned_mesh.skin(&joint_matrices, &mut mesh);
+        // the first pose gives the mesh its vertices, so its buffers are built
+        assert!(mesh.just_loaded);
+        mesh.just_loaded = false;
+        skinned_mesh.skin(&joint_matrices, &mut mesh);
+        // after that only the vertex buffer is written
+        assert!(!mesh.just_loaded);
+        assert!(mesh.vertices_changed);
+        // rotated a quarter around z
+        assert!((Vec3::from(mesh.vertices[0].position) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+        assert!((Vec3::from(mesh.vertices[0].normal) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+        // halfway between the rotated and the translated position
+        assert!((Vec3::from(mesh.vertices[1].position) - Vec3::new(0.5, 1.5, 0.0)).length() < 1e-5);
+        // without weights the vertex keeps its bind pose instead of becoming NaN
+        assert_eq!(mesh.vertices[2].position, vertex.position);
+        assert_eq!(mesh.vertices[2].normal, vertex.normal);
+    }
+}
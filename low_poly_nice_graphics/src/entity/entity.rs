@@ -1,7 +1,9 @@
-use crate::{mesh::Mesh, physics::CollisionShape, registry::Handle, transform::Transform};
+use crate::{material::Material, mesh::Mesh, physics::CollisionShape, registry::Handle, transform::Transform};
 
 pub struct Entity {
     pub mesh_handle: Handle<Mesh>,
+    // None renders with Material::default()
+    pub material_handle: Option<Handle<Material>>,
     pub collision_shape: Option<CollisionShape>,
     pub transform: Transform,
 }
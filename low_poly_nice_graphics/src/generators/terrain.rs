@@ -1,5 +1,5 @@
 use crate::generators::Height;
-use noise::{MultiFractal, NoiseFn};
+use noise::{MultiFractal, NoiseFn, Seedable};
 
 pub struct Noise {
     noise: noise::Fbm,
@@ -15,6 +15,12 @@ impl Noise {
                 .set_persistence(1.0),
         }
     }
+
+    // Noise with another seed gives other heights.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.noise = self.noise.set_seed(seed);
+        self
+    }
 }
 
 impl Height for Noise {
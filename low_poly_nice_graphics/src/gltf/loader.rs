@@ -1,13 +1,37 @@
-use crate::mesh::{triangle_normal, Mesh, Vertex};
-use gltf::mesh::Mode;
+use crate::{
+    animation::{AnimationClip, Channel, Interpolation, Keyframes, Skin, SkinnedMesh},
+    entity::Entity,
+    gltf::{GltfNode, GltfScene},
+    material::Material,
+    mesh::{triangle_normal, Mesh, MeshProcessor, Vertex},
+    registry::{Handle, Registry},
+    texture::Texture,
+    transform::Transform,
+};
+use glam::{Mat4, Quat, Vec3};
+use gltf::{animation::util::ReadOutputs, mesh::Mode};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
 
 #[derive(Debug)]
 pub enum MeshLoadError {
     Gltf(gltf::Error),
     Decode(base64::DecodeError),
-    UnsupportedBufferFormat,
+    Image(image::ImageError),
+    Io(std::io::Error),
     UnsupportedPrimitiveMode,
     MissingBlob,
+    // a number of indices that is not a multiple of 3 or an index past the last vertex
+    InvalidIndices,
+    // normals, uvs, colors, joints or weights for a different number of vertices than there are positions, joints that
+    // the skin does not have or keyframes without a value
+    AttributeCountMismatch,
+    // a buffer with fewer bytes than the file says or a buffer view past its end
+    BufferTooShort,
+    // a node that is reached twice from the roots of the scene, through a cycle or from two parents
+    InvalidHierarchy,
 }
 
 impl From<gltf::Error> for MeshLoadError {
@@ -22,88 +46,381 @@ impl From<base64::DecodeError> for MeshLoadError {
     }
 }
 
-pub fn load_gltf(bytes: &[u8], mut named_mesh: impl FnMut(String, Mesh)) -> Result<(), MeshLoadError> {
+impl From<image::ImageError> for MeshLoadError {
+    fn from(e: image::ImageError) -> MeshLoadError {
+        MeshLoadError::Image(e)
+    }
+}
+
+impl From<std::io::Error> for MeshLoadError {
+    fn from(e: std::io::Error) -> MeshLoadError {
+        MeshLoadError::Io(e)
+    }
+}
+
+// The registries an imported scene is added to.
+pub struct GltfAssets<'a> {
+    pub meshes: &'a mut Registry<Mesh>,
+    pub materials: &'a mut Registry<Material>,
+    pub textures: &'a mut Registry<Texture>,
+    pub entities: &'a mut Registry<Entity>,
+}
+
+// Imports the default scene (or the first one) of a .gltf or .glb file, external buffers and images are
+// loaded relative to the file.
+pub fn load_gltf(path: &str, assets: GltfAssets) -> Result<GltfScene, MeshLoadError> {
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    load_gltf_from_slice(std::fs::read(path)?.as_slice(), directory, assets)
+}
+
+pub fn load_gltf_from_slice(bytes: &[u8], directory: &Path, assets: GltfAssets) -> Result<GltfScene, MeshLoadError> {
     let gltf = gltf::Gltf::from_slice(bytes)?;
-    let buffer_data = load_buffers(&gltf)?;
-    for node in gltf.nodes() {
-        if let Some(node_name) = node.name() {
-            let mesh = node.mesh().unwrap();
-            let mut vertices = Vec::new();
-            let mut indices = Vec::new();
-            for primitive in mesh.primitives() {
-                if primitive.mode() != Mode::Triangles {
-                    return Err(MeshLoadError::UnsupportedPrimitiveMode);
-                }
-                let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
-
-                if let Some(positions) = reader.read_positions().map(|v| v.collect::<Vec<[f32; 3]>>()) {
-                    if let Some(gltf_indices) = reader
-                        .read_indices()
-                        .map(|indices| indices.into_u32().collect::<Vec<u32>>())
-                    {
-                        assert!(gltf_indices.len() % 3 == 0);
-                        let mut count = 0;
-                        for i in gltf_indices.chunks(3) {
-                            let v0 = positions[i[0] as usize];
-                            let v1 = positions[i[1] as usize];
-                            let v2 = positions[i[2] as usize];
-                            let n = triangle_normal(v0, v1, v2);
-                            vertices.extend_from_slice(&[
-                                Vertex {
-                                    position: v0,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                                Vertex {
-                                    position: v1,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                                Vertex {
-                                    position: v2,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                            ]);
-                            indices.extend_from_slice(&[count, count + 1, count + 2]);
-                            count += 3;
-                        }
-                    }
+    let buffer_data = load_buffers(&gltf, directory)?;
+
+    let mut textures = Vec::new();
+    for image in gltf.images() {
+        textures.push(assets.textures.add(load_image(&image, &buffer_data, directory)?));
+    }
+    let materials = gltf
+        .materials()
+        .map(|material| assets.materials.add(convert_material(&material, &textures)))
+        .collect::<Vec<_>>();
+    // every primitive becomes a mesh, shared by all nodes that use it
+    let mut primitives: HashMap<(usize, usize), Handle<Mesh>> = HashMap::new();
+
+    let nodes = gltf
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            GltfNode {
+                name: node.name().map(str::to_string),
+                transform: Transform::from_translation_rotation_scale(
+                    Vec3::from(translation),
+                    Quat::from_xyzw(rotation[0], rotation[1], rotation[2], rotation[3]),
+                    Vec3::from(scale),
+                ),
+                entities: Vec::new(),
+                children: node.children().map(|child| child.index()).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let roots = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    let mut scene = GltfScene {
+        nodes,
+        roots,
+        skins: gltf
+            .skins()
+            .map(|skin| convert_skin(&skin, &buffer_data))
+            .collect::<Result<_, _>>()?,
+        skinned_meshes: Vec::new(),
+        animations: gltf
+            .animations()
+            .map(|animation| convert_animation(&animation, &buffer_data))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let order = scene.hierarchy().ok_or(MeshLoadError::InvalidHierarchy)?;
+    let world_matrices = scene.world_matrices(&scene.rest_pose());
+    let gltf_nodes = gltf.nodes().collect::<Vec<_>>();
+    for index in order {
+        let node = &gltf_nodes[index];
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        for primitive in mesh.primitives() {
+            let material_handle = primitive.material().index().map(|i| materials[i].clone());
+            let skinning = match node.skin() {
+                Some(skin) => read_skinning(&primitive, &buffer_data, skin.joints().count())?.map(|s| (skin, s)),
+                None => None,
+            };
+            if let Some((skin, skinning)) = skinning {
+                // every skinned node deforms its own copy, placed by the joints instead of the node
+                let (mesh, sources) = convert_primitive(&primitive, &buffer_data)?;
+                if sources.iter().any(|source| *source >= skinning.len()) {
+                    return Err(MeshLoadError::AttributeCountMismatch);
                 }
+                let bind_pose = mesh.vertices.clone();
+                let mesh_handle = assets.meshes.add(mesh);
+                scene.skinned_meshes.push(SkinnedMesh {
+                    mesh_handle: mesh_handle.clone(),
+                    skin: skin.index(),
+                    bind_pose,
+                    joints: sources.iter().map(|source| skinning[*source].0).collect(),
+                    weights: sources.iter().map(|source| skinning[*source].1).collect(),
+                });
+                scene.nodes[index].entities.push(assets.entities.add(Entity {
+                    mesh_handle,
+                    material_handle,
+                    collision_shape: None,
+                    transform: Transform::identity(),
+                }));
+                continue;
             }
-            named_mesh(
-                node_name.to_string(),
-                Mesh {
-                    vertices,
-                    indices,
-                    just_loaded: true,
-                },
-            );
+            let key = (mesh.index(), primitive.index());
+            let mesh_handle = match primitives.entry(key) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => entry
+                    .insert(assets.meshes.add(convert_primitive(&primitive, &buffer_data)?.0))
+                    .clone(),
+            };
+            let (scale, rotation, translation) = world_matrices[index].to_scale_rotation_translation();
+            scene.nodes[index].entities.push(assets.entities.add(Entity {
+                mesh_handle,
+                material_handle,
+                collision_shape: None,
+                transform: Transform::from_translation_rotation_scale(translation, rotation, scale),
+            }));
         }
     }
-    Ok(())
+    scene.apply_pose(&scene.rest_pose(), assets.meshes);
+    Ok(scene)
 }
 
-fn load_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, MeshLoadError> {
-    const OCTET_STREAM_URI: &str = "data:application/octet-stream;base64,";
-    let mut buffer_data = Vec::new();
-    for buffer in gltf.buffers() {
-        match buffer.source() {
-            gltf::buffer::Source::Uri(uri) => {
-                if uri.starts_with(OCTET_STREAM_URI) {
-                    buffer_data.push(base64::decode(&uri[OCTET_STREAM_URI.len()..])?);
-                } else {
-                    return Err(MeshLoadError::UnsupportedBufferFormat);
-                }
+fn convert_skin(skin: &gltf::Skin, buffer_data: &[Vec<u8>]) -> Result<Skin, MeshLoadError> {
+    let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+    let reader = skin.reader(|buffer| Some(&buffer_data[buffer.index()]));
+    // without inverse bind matrices the joints are at the origin in the bind pose
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect::<Vec<_>>(),
+        None => vec![Mat4::identity(); joints.len()],
+    };
+    if inverse_bind_matrices.len() != joints.len() {
+        return Err(MeshLoadError::AttributeCountMismatch);
+    }
+    Ok(Skin {
+        joints,
+        inverse_bind_matrices,
+    })
+}
+
+// the joints of a vertex and their weights
+type VertexSkinning = ([u16; 4], [f32; 4]);
+
+// Joints and weights per vertex of the file, None when the primitive is not skinned. The joints are indices into the
+// joint_count joints of the skin.
+fn read_skinning(
+    primitive: &gltf::Primitive,
+    buffer_data: &[Vec<u8>],
+    joint_count: usize,
+) -> Result<Option<Vec<VertexSkinning>>, MeshLoadError> {
+    let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
+    let (joints, weights) = match (reader.read_joints(0), reader.read_weights(0)) {
+        (Some(joints), Some(weights)) => (
+            joints.into_u16().collect::<Vec<_>>(),
+            weights.into_f32().collect::<Vec<_>>(),
+        ),
+        _ => return Ok(None),
+    };
+    let outside = joints.iter().flatten().any(|joint| *joint as usize >= joint_count);
+    if joints.len() != weights.len() || outside {
+        return Err(MeshLoadError::AttributeCountMismatch);
+    }
+    Ok(Some(joints.into_iter().zip(weights).collect()))
+}
+
+// Morph target weights are skipped, cubic spline keyframes are interpolated linearly. Every time needs one value.
+fn convert_animation(animation: &gltf::Animation, buffer_data: &[Vec<u8>]) -> Result<AnimationClip, MeshLoadError> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffer_data[buffer.index()]));
+        let (times, outputs) = match (reader.read_inputs(), reader.read_outputs()) {
+            (Some(times), Some(outputs)) => (times.collect::<Vec<f32>>(), outputs),
+            _ => continue,
+        };
+        let interpolation = channel.sampler().interpolation();
+        let (count, keyframes) = match outputs {
+            ReadOutputs::Translations(values) => {
+                let values = keyframe_values(values.map(Vec3::from), interpolation);
+                (values.len(), Keyframes::Translation(values))
             }
-            gltf::buffer::Source::Bin => {
-                if let Some(blob) = gltf.blob.as_deref() {
-                    buffer_data.push(blob.into());
-                } else {
-                    return Err(MeshLoadError::MissingBlob);
-                }
+            ReadOutputs::Rotations(values) => {
+                let values = keyframe_values(
+                    values.into_f32().map(|r| Quat::from_xyzw(r[0], r[1], r[2], r[3])),
+                    interpolation,
+                );
+                (values.len(), Keyframes::Rotation(values))
             }
+            ReadOutputs::Scales(values) => {
+                let values = keyframe_values(values.map(Vec3::from), interpolation);
+                (values.len(), Keyframes::Scale(values))
+            }
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+        if count != times.len() {
+            return Err(MeshLoadError::AttributeCountMismatch);
         }
+        channels.push(Channel {
+            node: channel.target().node().index(),
+            interpolation: match interpolation {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                _ => Interpolation::Linear,
+            },
+            times,
+            keyframes,
+        });
+    }
+    Ok(AnimationClip::new(animation.name().map(str::to_string), channels))
+}
+
+// cubic spline keyframes are stored as in tangent, value, out tangent
+fn keyframe_values<T>(values: impl Iterator<Item = T>, interpolation: gltf::animation::Interpolation) -> Vec<T> {
+    match interpolation {
+        gltf::animation::Interpolation::CubicSpline => values.skip(1).step_by(3).collect(),
+        _ => values.collect(),
+    }
+}
+
+// Blinn-Phong approximation of the metallic roughness model.
+fn convert_material(material: &gltf::Material, textures: &[Handle<Texture>]) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let roughness = pbr.roughness_factor().max(0.05);
+    let specular = 0.04 + 0.96 * pbr.metallic_factor();
+    let [r, g, b] = material.emissive_factor();
+    let mut converted = Material::new(pbr.base_color_factor())
+        .with_specular(
+            [specular, specular, specular, 1.0],
+            (2.0 / roughness.powi(4) - 2.0).clamp(1.0, 256.0),
+        )
+        .with_emissive([r, g, b, 1.0]);
+    if let Some(info) = pbr.base_color_texture() {
+        converted = converted.with_texture(textures[info.texture().source().index()].clone());
+    }
+    converted
+}
+
+// Uses the normals of the file when there are any, otherwise every triangle gets a flat normal. Also returns the
+// vertex of the file every vertex of the mesh is made from.
+fn convert_primitive(
+    primitive: &gltf::Primitive,
+    buffer_data: &[Vec<u8>],
+) -> Result<(Mesh, Vec<usize>), MeshLoadError> {
+    if primitive.mode() != Mode::Triangles {
+        return Err(MeshLoadError::UnsupportedPrimitiveMode);
+    }
+    let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
+    let positions = match reader.read_positions() {
+        Some(positions) => positions.collect::<Vec<[f32; 3]>>(),
+        None => Vec::new(),
+    };
+    let gltf_indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<u32>>(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if gltf_indices.len() % 3 != 0 || gltf_indices.iter().any(|i| *i as usize >= positions.len()) {
+        return Err(MeshLoadError::InvalidIndices);
+    }
+    let tex_coord = primitive
+        .material()
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .map_or(0, |info| info.tex_coord());
+    let uvs = reader
+        .read_tex_coords(tex_coord)
+        .map(|uvs| uvs.into_f32().collect::<Vec<[f32; 2]>>());
+    let colors = reader
+        .read_colors(0)
+        .map(|colors| colors.into_rgb_f32().collect::<Vec<[f32; 3]>>());
+    let normals = reader.read_normals().map(|normals| normals.collect::<Vec<[f32; 3]>>());
+    let lengths = [
+        uvs.as_ref().map(Vec::len),
+        colors.as_ref().map(Vec::len),
+        normals.as_ref().map(Vec::len),
+    ];
+    if lengths.iter().flatten().any(|length| *length != positions.len()) {
+        return Err(MeshLoadError::AttributeCountMismatch);
+    }
+    let vertex = |i: usize, normal: [f32; 3]| {
+        Vertex::new(
+            positions[i],
+            normal,
+            colors.as_ref().map_or([1.0, 1.0, 1.0], |colors| colors[i]),
+        )
+        .with_uv(uvs.as_ref().map_or([0.0, 0.0], |uvs| uvs[i]))
+    };
+
+    if let Some(normals) = normals {
+        let vertices = normals
+            .into_iter()
+            .enumerate()
+            .map(|(i, normal)| vertex(i, normal))
+            .collect::<Vec<_>>();
+        let sources = (0..vertices.len()).collect();
+        return Ok((
+            Mesh {
+                vertices,
+                indices: gltf_indices,
+                just_loaded: true,
+                vertices_changed: false,
+                lods: Vec::new(),
+            },
+            sources,
+        ));
+    }
+    let mut corners = Vec::new();
+    for triangle in gltf_indices.chunks(3) {
+        let normal = triangle_normal(
+            positions[triangle[0] as usize],
+            positions[triangle[1] as usize],
+            positions[triangle[2] as usize],
+        );
+        corners.extend(triangle.iter().map(|i| vertex(*i as usize, normal)));
+    }
+    // corners of triangles facing the same way share their vertices again
+    let processed = MeshProcessor::new().process(&Mesh {
+        indices: (0..corners.len() as u32).collect(),
+        vertices: corners,
+        just_loaded: true,
+        vertices_changed: false,
+        lods: Vec::new(),
+    });
+    let sources = processed
+        .sources
+        .iter()
+        .map(|corner| gltf_indices[*corner] as usize)
+        .collect();
+    Ok((processed.mesh, sources))
+}
+
+// base64 data uris or files relative to directory
+fn read_uri(uri: &str, directory: &Path) -> Result<Vec<u8>, MeshLoadError> {
+    const BASE64: &str = ";base64,";
+    match (uri.starts_with("data:"), uri.find(BASE64)) {
+        (true, Some(start)) => Ok(base64::decode(&uri[start + BASE64.len()..])?),
+        _ => Ok(std::fs::read(directory.join(uri.replace("%20", " ")))?),
+    }
+}
+
+fn load_image(image: &gltf::Image, buffer_data: &[Vec<u8>], directory: &Path) -> Result<Texture, MeshLoadError> {
+    let bytes = match image.source() {
+        gltf::image::Source::View { view, .. } => buffer_data[view.buffer().index()]
+            .get(view.offset()..view.offset() + view.length())
+            .ok_or(MeshLoadError::BufferTooShort)?
+            .to_vec(),
+        gltf::image::Source::Uri { uri, .. } => read_uri(uri, directory)?,
+    };
+    Ok(Texture::from_image(&image::load_from_memory(bytes.as_slice())?))
+}
+
+fn load_buffers(gltf: &gltf::Gltf, directory: &Path) -> Result<Vec<Vec<u8>>, MeshLoadError> {
+    let mut buffer_data = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, directory)?,
+            gltf::buffer::Source::Bin => match gltf.blob.as_deref() {
+                Some(blob) => blob.into(),
+                None => return Err(MeshLoadError::MissingBlob),
+            },
+        };
+        // the accessors read as much as the file says there is
+        if data.len() < buffer.length() {
+            return Err(MeshLoadError::BufferTooShort);
+        }
+        buffer_data.push(data);
     }
 
     Ok(buffer_data)
@@ -111,14 +428,326 @@ fn load_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, MeshLoadError> {
 
 #[cfg(test)]
 mod tests {
-    use crate::gltf::loader::load_gltf;
+    use crate::{
+        animation::Interpolation,
+        gltf::{load_gltf, load_gltf_from_slice, GltfAssets, GltfScene, MeshLoadError},
+        registry::Registry,
+    };
+    use glam::Vec3;
+    use std::path::Path;
+
+    struct Registries {
+        meshes: Registry<crate::mesh::Mesh>,
+        materials: Registry<crate::material::Material>,
+        textures: Registry<crate::texture::Texture>,
+        entities: Registry<crate::entity::Entity>,
+    }
+
+    impl Registries {
+        fn new() -> Self {
+            Self {
+                meshes: Registry::new(),
+                materials: Registry::new(),
+                textures: Registry::new(),
+                entities: Registry::new(),
+            }
+        }
+
+        fn assets(&mut self) -> GltfAssets<'_> {
+            GltfAssets {
+                meshes: &mut self.meshes,
+                materials: &mut self.materials,
+                textures: &mut self.textures,
+                entities: &mut self.entities,
+            }
+        }
+
+        fn load(&mut self, gltf: &str, directory: &Path) -> GltfScene {
+            load_gltf_from_slice(gltf.as_bytes(), directory, self.assets()).unwrap()
+        }
+    }
+
+    // one triangle with uvs, the optional extra attributes and the nodes are spliced in
+    fn triangle(attributes: &str, nodes: &str, buffer_uri: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{}],
+                "meshes": [{{ "primitives": [{{
+                    "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1 {} }}, "indices": 2, "material": 0
+                }}] }}],
+                "materials": [{{ "pbrMetallicRoughness": {{
+                    "baseColorFactor": [0.5, 0.5, 0.5, 1.0], "baseColorTexture": {{ "index": 0 }}
+                }} }}],
+                "textures": [{{ "source": 0 }}],
+                "images": [{{ "uri": "data:image/png;base64,{}" }}],
+                "buffers": [{{ "byteLength": 104, "uri": "{}" }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 60, "byteLength": 6 }},
+                    {{ "buffer": 0, "byteOffset": 68, "byteLength": 36 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                    {{ "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+                    {{ "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC3" }}
+                ]
+            }}"#,
+            nodes,
+            attributes,
+            base64::encode(png()),
+            buffer_uri
+        )
+    }
+
+    // a 2x1 red and blue texture
+    fn png() -> Vec<u8> {
+        let mut png = Vec::new();
+        let image = image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    // positions, uvs, indices padded to 4 bytes and normals that all point along z
+    fn buffer() -> Vec<u8> {
+        let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let uvs = [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let mut buffer = Vec::new();
+        for v in positions.iter().chain(uvs.iter()) {
+            buffer.extend_from_slice(&v.to_le_bytes());
+        }
+        for i in &[0u16, 1, 2, 0] {
+            buffer.extend_from_slice(&i.to_le_bytes());
+        }
+        for v in &[0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            buffer.extend_from_slice(&v.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn embedded_buffer() -> String {
+        format!("data:application/octet-stream;base64,{}", base64::encode(buffer()))
+    }
 
     #[test]
     fn load_gltf_test() {
-        load_gltf(
-            std::fs::read("res/gltf/test.gltf").unwrap().as_slice(),
-            |_mesh, _name| {},
+        let mut registries = Registries::new();
+        let scene = load_gltf("res/gltf/test.gltf", registries.assets()).unwrap();
+        let node = scene.find("Cube.1").unwrap();
+        assert_eq!(node.transform.translation, Vec3::new(5.0, 0.0, 0.0));
+        let entity = registries.entities.get(&node.entities[0]).unwrap();
+        assert_eq!(entity.transform.translation, Vec3::new(5.0, 0.0, 0.0));
+        assert!(entity.material_handle.is_some());
+    }
+
+    #[test]
+    fn imports_uvs_and_base_color_texture() {
+        let mut registries = Registries::new();
+        let scene = registries.load(
+            &triangle("", r#"{ "mesh": 0, "name": "triangle" }"#, &embedded_buffer()),
+            Path::new(""),
+        );
+        let entity = registries
+            .entities
+            .get(&scene.find("triangle").unwrap().entities[0])
+            .unwrap();
+        let mesh = registries.meshes.get(&entity.mesh_handle).unwrap();
+        let uvs = mesh.vertices.iter().map(|v| v.uv).collect::<Vec<_>>();
+        assert_eq!(uvs, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        // the base color ends up in the material, not in the vertices
+        assert!(mesh.vertices.iter().all(|v| v.color == [1.0, 1.0, 1.0]));
+        let material = registries
+            .materials
+            .get(entity.material_handle.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(material.albedo, [0.5, 0.5, 0.5, 1.0]);
+        let texture = registries.textures.get(material.texture.as_ref().unwrap()).unwrap();
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(&texture.pixels[..8], &[255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn normals_from_the_file_keep_vertices_shared() {
+        let mut registries = Registries::new();
+        let nodes = r#"{ "mesh": 0, "name": "triangle" }"#;
+        let scene = registries.load(&triangle("", nodes, &embedded_buffer()), Path::new(""));
+        let flat = &scene.find("triangle").unwrap().entities[0];
+        let scene = registries.load(&triangle(r#", "NORMAL": 3"#, nodes, &embedded_buffer()), Path::new(""));
+        let smooth = &scene.find("triangle").unwrap().entities[0];
+        let mesh = |entity| {
+            let entity = registries.entities.get(entity).unwrap();
+            registries.meshes.get(&entity.mesh_handle).unwrap()
+        };
+        assert_eq!(mesh(flat).indices, vec![0, 1, 2]);
+        assert_eq!(mesh(smooth).indices, vec![0, 1, 2]);
+        assert!(mesh(smooth).vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn primitives_that_do_not_add_up_are_errors() {
+        let mut registries = Registries::new();
+        let gltf = triangle(r#", "NORMAL": 3"#, r#"{ "mesh": 0 }"#, &embedded_buffer());
+        let mut load = |gltf: String| load_gltf_from_slice(gltf.as_bytes(), Path::new(""), registries.assets());
+        // two indices are no triangle
+        let indices = gltf.replace(r#""count": 3, "type": "SCALAR""#, r#""count": 2, "type": "SCALAR""#);
+        assert!(matches!(load(indices), Err(MeshLoadError::InvalidIndices)));
+        let uvs = gltf.replace(r#""count": 3, "type": "VEC2""#, r#""count": 2, "type": "VEC2""#);
+        assert!(matches!(load(uvs), Err(MeshLoadError::AttributeCountMismatch)));
+        // the normals are the last accessor
+        let normals = gltf.replace(
+            r#""bufferView": 3, "componentType": 5126, "count": 3"#,
+            r#""bufferView": 3, "componentType": 5126, "count": 2"#,
+        );
+        assert!(matches!(load(normals), Err(MeshLoadError::AttributeCountMismatch)));
+        assert!(load(gltf).is_ok());
+    }
+
+    #[test]
+    fn children_are_placed_relative_to_their_parent() {
+        let mut registries = Registries::new();
+        let nodes = r#"
+            { "name": "root", "children": [1, 2], "translation": [1.0, 0.0, 0.0],
+              "rotation": [0.0, 0.7071068, 0.0, 0.7071068] },
+            { "mesh": 0, "name": "child", "translation": [0.0, 0.0, 2.0] },
+            { "mesh": 0, "name": "scaled", "scale": [2.0, 2.0, 2.0] }
+        "#;
+        let scene = registries.load(&triangle("", nodes, &embedded_buffer()), Path::new(""));
+        assert_eq!(scene.roots, vec![0]);
+        let root = scene.find("root").unwrap();
+        assert!(root.entities.is_empty());
+        assert_eq!(root.children, vec![1, 2]);
+        let child = scene.find("child").unwrap();
+        assert_eq!(child.transform.translation, Vec3::new(0.0, 0.0, 2.0));
+        let entity = registries.entities.get(&child.entities[0]).unwrap();
+        // rotated 90 degrees around y, +z becomes +x
+        assert!((entity.transform.translation - Vec3::new(3.0, 0.0, 0.0)).length() < 1e-5);
+        let scaled = registries
+            .entities
+            .get(&scene.find("scaled").unwrap().entities[0])
+            .unwrap();
+        assert!((scaled.transform.scale - Vec3::new(2.0, 2.0, 2.0)).length() < 1e-5);
+        // both nodes use the same mesh
+        assert_eq!(scaled.mesh_handle.id, entity.mesh_handle.id);
+        assert_eq!(registries.meshes.registry.len(), 1);
+    }
+
+    #[test]
+    fn nodes_reached_twice_are_errors() {
+        let mut registries = Registries::new();
+        let mut load = |nodes: &str| {
+            let gltf = triangle("", nodes, &embedded_buffer());
+            load_gltf_from_slice(gltf.as_bytes(), Path::new(""), registries.assets())
+        };
+        let cycle = r#"{ "children": [1] }, { "mesh": 0, "children": [0] }"#;
+        assert!(matches!(load(cycle), Err(MeshLoadError::InvalidHierarchy)));
+        let two_parents = r#"{ "children": [1, 2] }, { "mesh": 0 }, { "children": [1] }"#;
+        assert!(matches!(load(two_parents), Err(MeshLoadError::InvalidHierarchy)));
+        assert!(load(r#"{ "children": [1] }, { "mesh": 0 }"#).is_ok());
+    }
+
+    #[test]
+    fn buffers_shorter_than_the_file_says_are_errors() {
+        let mut registries = Registries::new();
+        let gltf = triangle("", r#"{ "mesh": 0 }"#, &embedded_buffer());
+        let mut load = |gltf: String| load_gltf_from_slice(gltf.as_bytes(), Path::new(""), registries.assets());
+        let longer = gltf.replace(r#""byteLength": 104"#, r#""byteLength": 200"#);
+        assert!(matches!(load(longer), Err(MeshLoadError::BufferTooShort)));
+        // the image in a view that ends past the buffer
+        let images = gltf.find(r#""images""#).unwrap();
+        let end = images + gltf[images..].find(']').unwrap() + 1;
+        let image_in_view = format!(
+            "{}{}{}",
+            &gltf[..images],
+            r#""images": [{ "bufferView": 4, "mimeType": "image/png" }]"#,
+            &gltf[end..]
+        )
+        .replace(
+            r#"{ "buffer": 0, "byteOffset": 68, "byteLength": 36 }"#,
+            r#"{ "buffer": 0, "byteOffset": 68, "byteLength": 36 },
+               { "buffer": 0, "byteOffset": 100, "byteLength": 50 }"#,
+        );
+        assert!(matches!(load(image_in_view), Err(MeshLoadError::BufferTooShort)));
+    }
+
+    #[test]
+    fn external_buffers_are_relative_to_the_file() {
+        let directory = std::env::temp_dir().join("low_poly_nice_graphics_gltf_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("triangle data.bin"), buffer()).unwrap();
+        let path = directory.join("triangle.gltf");
+        std::fs::write(
+            &path,
+            triangle("", r#"{ "mesh": 0, "name": "triangle" }"#, "triangle%20data.bin"),
         )
         .unwrap();
+        let mut registries = Registries::new();
+        let scene = load_gltf(path.to_str().unwrap(), registries.assets()).unwrap();
+        assert_eq!(scene.find("triangle").unwrap().entities.len(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn skinned_character_follows_its_animations() {
+        let mut registries = Registries::new();
+        let scene = load_gltf("res/gltf/character.gltf", registries.assets()).unwrap();
+        assert_eq!(scene.skins[0].joints, vec![1, 2, 3, 4, 5]);
+        assert_eq!(scene.skinned_meshes.len(), 1);
+        let wave = scene.find_animation("wave").unwrap();
+        assert_eq!(scene.animations[wave].channels[0].interpolation, Interpolation::Step);
+
+        let skinned_mesh = &scene.skinned_meshes[0];
+        // the lowest and the most forward point of the left leg
+        let foot = |meshes: &Registry<crate::mesh::Mesh>| {
+            let mesh = meshes.get(&skinned_mesh.mesh_handle).unwrap();
+            mesh.vertices
+                .iter()
+                .zip(skinned_mesh.joints.iter())
+                .filter(|(_, joints)| joints[0] == 1)
+                .fold(Vec3::new(1.0, 1.0, 1.0), |foot, (vertex, _)| {
+                    foot.min(Vec3::from(vertex.position))
+                })
+        };
+        // in the rest pose the vertices are where the file has them
+        assert!((foot(&registries.meshes) - Vec3::new(0.04, -0.5, -0.08)).length() < 1e-5);
+
+        let mut pose = scene.rest_pose();
+        scene.animations[scene.find_animation("walk").unwrap()].sample(0.0, &mut pose);
+        scene.apply_pose(&pose, &mut registries.meshes);
+        // the leg swings forward
+        assert!(foot(&registries.meshes).z < -0.2);
+        assert!(registries.meshes.get(&skinned_mesh.mesh_handle).unwrap().just_loaded);
+    }
+
+    #[test]
+    fn skins_and_animations_that_do_not_add_up_are_errors() {
+        let mut registries = Registries::new();
+        let gltf = std::fs::read_to_string("res/gltf/character.gltf").unwrap();
+        let mut load = |gltf: String| load_gltf_from_slice(gltf.as_bytes(), Path::new(""), registries.assets());
+        let matrices = "\"count\": 5,\n            \"type\": \"MAT4\"";
+        let fewer_matrices = gltf.replace(matrices, "\"count\": 4,\n            \"type\": \"MAT4\"");
+        assert!(matches!(
+            load(fewer_matrices.clone()),
+            Err(MeshLoadError::AttributeCountMismatch)
+        ));
+        // the vertices of the right arm use the joint that is gone
+        let fewer_joints = fewer_matrices.replace("4,\n                5\n", "4\n");
+        assert!(matches!(load(fewer_joints), Err(MeshLoadError::AttributeCountMismatch)));
+        // the last accessor has the rotations of the wave
+        let fewer_rotations = gltf.replace(
+            "\"count\": 4,\n            \"type\": \"VEC4\"\n        }\n    ]",
+            "\"count\": 3,\n            \"type\": \"VEC4\"\n        }\n    ]",
+        );
+        assert!(matches!(
+            load(fewer_rotations),
+            Err(MeshLoadError::AttributeCountMismatch)
+        ));
+        assert!(load(gltf).is_ok());
     }
 }
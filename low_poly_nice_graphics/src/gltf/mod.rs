@@ -1,3 +1,5 @@
 mod loader;
+mod scene;
 
-pub use loader::{load_gltf, MeshLoadError};
+pub use loader::{load_gltf, load_gltf_from_slice, GltfAssets, MeshLoadError};
+pub use scene::{GltfNode, GltfScene};
@@ -0,0 +1,93 @@
+use crate::{
+    animation::{AnimationClip, Skin, SkinnedMesh},
+    entity::Entity,
+    mesh::Mesh,
+    registry::{Handle, Registry},
+    transform::Transform,
+};
+use glam::Mat4;
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    // relative to the parent node
+    pub transform: Transform,
+    // one entity per primitive of the mesh of the node, placed with the world transform of the node
+    pub entities: Vec<Handle<Entity>>,
+    // indices into GltfScene::nodes
+    pub children: Vec<usize>,
+}
+
+// The node hierarchy of an imported scene, nodes are in the order of the file.
+pub struct GltfScene {
+    pub nodes: Vec<GltfNode>,
+    pub roots: Vec<usize>,
+    pub skins: Vec<Skin>,
+    pub skinned_meshes: Vec<SkinnedMesh>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl GltfScene {
+    pub fn find(&self, name: &str) -> Option<&GltfNode> {
+        self.nodes.iter().find(|node| node.name.as_deref() == Some(name))
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations
+            .iter()
+            .position(|animation| animation.name.as_deref() == Some(name))
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.nodes.iter().map(|node| node.transform.clone()).collect()
+    }
+
+    // The nodes from the roots down, parents before their children. None when a node is reached twice, through a
+    // cycle or from two parents.
+    pub fn hierarchy(&self) -> Option<Vec<usize>> {
+        let mut reached = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut stack = self.roots.clone();
+        while let Some(index) = stack.pop() {
+            if std::mem::replace(&mut reached[index], true) {
+                return None;
+            }
+            order.push(index);
+            stack.extend(self.nodes[index].children.iter());
+        }
+        Some(order)
+    }
+
+    // Combines the local transforms of pose down the hierarchy, nodes outside of the scene get the identity. So do
+    // all nodes of a hierarchy that hierarchy rejects, load_gltf does not return those.
+    pub fn world_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+        let mut world_matrices = vec![Mat4::identity(); self.nodes.len()];
+        let order = match self.hierarchy() {
+            Some(order) => order,
+            None => return world_matrices,
+        };
+        for root in &self.roots {
+            world_matrices[*root] = pose[*root].to_matrix();
+        }
+        for index in order {
+            for child in &self.nodes[index].children {
+                world_matrices[*child] = world_matrices[index] * pose[*child].to_matrix();
+            }
+        }
+        world_matrices
+    }
+
+    // Deforms the skinned meshes, pose has the local transform of every node.
+    pub fn apply_pose(&self, pose: &[Transform], meshes: &mut Registry<Mesh>) {
+        let world_matrices = self.world_matrices(pose);
+        let joint_matrices = self
+            .skins
+            .iter()
+            .map(|skin| skin.joint_matrices(&world_matrices))
+            .collect::<Vec<_>>();
+        for skinned_mesh in &self.skinned_meshes {
+            if let Some(mesh) = meshes.get_mut(&skinned_mesh.mesh_handle) {
+                skinned_mesh.skin(&joint_matrices[skinned_mesh.skin], mesh);
+            }
+        }
+    }
+}
@@ -1,29 +1,40 @@
+pub mod animation;
 pub mod cameras;
 pub mod controllers;
 pub mod entity;
 pub mod generators;
 pub mod gltf;
 pub mod input;
+pub mod material;
 pub mod mesh;
 mod physics;
 pub mod registry;
 pub mod renderer;
+pub mod texture;
 pub mod transform;
 mod vox;
 pub mod winit_impl;
 mod world;
 
 use crate::{
+    animation::AnimationPlayer,
     cameras::FollowCamera,
     controllers::{CameraController, CharacterController},
     entity::Entity,
-    input::{keyboard_state_from_events, InputAll},
-    mesh::{Cube, IcoSphere, Mesh},
+    generators::Noise,
+    gltf::{load_gltf, GltfAssets},
+    input::{keyboard_state_from_events, Input, InputAll, KeyCode},
+    material::Material,
+    mesh::{Cube, Mesh},
     physics::{Body, BodyStatus, CollisionShape, Cuboid, Physics, Sphere},
     registry::Registry,
-    renderer::{BindGroup, DirectionalProperties, Light, LightBindGroup, PointProperties, SpotProperties},
+    renderer::{
+        BindGroup, DirectionalProperties, Light, LightBindGroup, PointProperties, ShadowBindGroup, ShadowMap,
+        SpotProperties,
+    },
     transform::Transform,
-    world::World,
+    vox::{write_vox, VoxError},
+    world::{Biomes, Caves, Heightfield, RegionError, RegionStorage, Sine, World, VOXEL_SIZE},
 };
 use glam::Vec3;
 use winit::{
@@ -33,7 +44,31 @@ use winit::{
 };
 
 #[derive(Debug)]
-pub enum GameError {}
+pub enum GameError {
+    Io(std::io::Error),
+    Vox(VoxError),
+    World(RegionError),
+    InvalidSeed(String),
+    UnknownGenerator(String),
+}
+
+impl From<std::io::Error> for GameError {
+    fn from(error: std::io::Error) -> Self {
+        GameError::Io(error)
+    }
+}
+
+impl From<VoxError> for GameError {
+    fn from(error: VoxError) -> Self {
+        GameError::Vox(error)
+    }
+}
+
+impl From<RegionError> for GameError {
+    fn from(error: RegionError) -> Self {
+        GameError::World(error)
+    }
+}
 
 fn main() -> Result<(), GameError> {
     let event_loop = EventLoop::new();
@@ -42,9 +77,18 @@ fn main() -> Result<(), GameError> {
         .expect("Could not create window");
     let mut renderer =
         futures::executor::block_on(renderer::Renderer::new(&window)).expect("Could not create renderer");
-    let pipeline_bindgroup = BindGroup::new(&renderer);
-    let pipeline = futures::executor::block_on(renderer::Pipeline::new(&renderer, &pipeline_bindgroup))
+    let shadow_map = ShadowMap::new(&renderer);
+    let shadow_pipeline_bindgroup = ShadowBindGroup::new(&renderer);
+    let shadow_pipeline =
+        futures::executor::block_on(renderer::ShadowPipeline::new(&renderer, &shadow_pipeline_bindgroup))
+            .expect("Could not create shadow pipeline");
+    let mut pipeline_bindgroup = BindGroup::new(&renderer, &shadow_map);
+    let mut pipeline = futures::executor::block_on(renderer::Pipeline::new(&renderer, &pipeline_bindgroup))
         .expect("Could not create pipeline");
+    pipeline.ssao = Some(
+        futures::executor::block_on(renderer::SsaoPipeline::new(&renderer, &pipeline_bindgroup))
+            .expect("Could not create ssao pipeline"),
+    );
     let light_pipeline_bindgroup = LightBindGroup::new(&renderer);
     let pipeline_light =
         futures::executor::block_on(renderer::LightPipeline::new(&renderer, &light_pipeline_bindgroup))
@@ -54,15 +98,33 @@ fn main() -> Result<(), GameError> {
     let mut physics = Physics::default();
     let mut meshes = Registry::new();
     let mut lights = Registry::new();
+    let mut materials = Registry::new();
+    let mut textures = Registry::new();
     let mut entities = Registry::new();
-    let mut world = World::new();
+    // low_poly_nice_graphics [seed] [caves | heightfield | sine], the same seed generates the same terrain, caves and
+    // biomes
+    let mut args = std::env::args().skip(1);
+    let seed = match args.next() {
+        Some(seed) => seed.parse().map_err(|_| GameError::InvalidSeed(seed))?,
+        None => 0,
+    };
+    let terrain =
+        Heightfield::new(Box::new(Noise::new().with_seed(seed)), Biomes::standard(seed)).with_height_scale(20.0);
+    let world = match args.next().as_deref() {
+        None | Some("caves") => World::new().with_generator(Caves::new(Box::new(terrain), seed)),
+        Some("heightfield") => World::new().with_generator(terrain),
+        Some("sine") => World::new().with_generator(Sine),
+        Some(generator) => return Err(GameError::UnknownGenerator(generator.to_string())),
+    };
+    let mut world = world.with_storage(RegionStorage::new("saves/world"));
     let light_mesh_handle = meshes.add(Mesh::from(Cube::new(0.25)));
-    lights.add(Light::Directional(DirectionalProperties::new([-1.0, -0.5, -1.0, 1.0])));
+    lights.add(Light::Directional(
+        DirectionalProperties::new([-1.0, -0.5, -1.0, 1.0]).with_shadows(),
+    ));
 
-    lights.add(Light::Spot(SpotProperties::new(
-        [0.0, 4.0, 0.0, 1.0],
-        [0.0, -1.0, 0.0, 1.0],
-    )));
+    lights.add(Light::Spot(
+        SpotProperties::new([0.0, 4.0, 0.0, 1.0], [0.0, -1.0, 0.0, 1.0]).with_shadows(),
+    ));
     lights.add(Light::Spot(SpotProperties::new(
         [8.0, 4.0, 0.0, 1.0],
         [0.0, -1.0, 0.0, 1.0],
@@ -71,37 +133,28 @@ fn main() -> Result<(), GameError> {
     lights.add(Light::Point(PointProperties::new([-8.0, 4.0, 8.0, 1.0])));
 
     let tree_house_hanlde = vox::load_vox(
-        &dot_vox::load_bytes(
-            std::fs::read("res/vox-models/#treehouse/#treehouse.vox")
-                .unwrap()
-                .as_slice(),
-        )
-        .unwrap(),
+        &dot_vox::load_bytes(&std::fs::read("res/vox-models/#treehouse/#treehouse.vox")?).map_err(VoxError::from)?,
         &mut vox_models,
     );
 
-    world.add(tree_house_hanlde.clone(), [0, 0, 0], &vox_models);
-    world.add(tree_house_hanlde.clone(), [0, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 0], &vox_models);
-
-    world.add(tree_house_hanlde.clone(), [128, 0, 0], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [256, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [256, 0, 0], &vox_models);
-
-    world.add(tree_house_hanlde.clone(), [0, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [0, 0, 256], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 256], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 128], &vox_models);
+    // a 3 x 3 grid of tree houses
+    for x in [0, 128, 256].iter() {
+        for z in [0, 128, 256].iter() {
+            world.add(tree_house_hanlde.clone(), [*x, 0, *z], &vox_models)?;
+        }
+    }
 
-    world.add(tree_house_hanlde.clone(), [128, 0, 128], &vox_models);
-    world.add(tree_house_hanlde.clone(), [128, 0, 256], &vox_models);
-    world.add(tree_house_hanlde.clone(), [256, 0, 256], &vox_models);
-    world.add(tree_house_hanlde.clone(), [256, 0, 128], &vox_models);
+    let phantom_mansion = vox::load_vox_scene(
+        &std::fs::read("res/vox-models/#phantom_mansion/#phantom_mansion.vox")?,
+        &mut vox_models,
+    )?;
+    let phantom_mansion_placements = phantom_mansion.placements(0, &mut vox_models);
+    world.add_scene(&phantom_mansion_placements, [-192, 0, 0], &vox_models)?;
 
+    let metal = materials.add(Material::new([0.6, 0.6, 0.65, 1.0]).with_specular([0.8, 0.8, 0.8, 1.0], 64.0));
     let cube = entities.add(Entity {
         mesh_handle: meshes.add(Mesh::from(Cube::new(1.0))),
+        material_handle: Some(metal),
         collision_shape: Some(CollisionShape {
             body_status: BodyStatus::Static,
             body: Body::Cuboid(Cuboid {
@@ -115,16 +168,32 @@ fn main() -> Result<(), GameError> {
 
     physics.register(cube, &entities);
 
-    let character = entities.add(Entity {
-        mesh_handle: meshes.add(Mesh::from(IcoSphere::new(0.5))),
-        collision_shape: Some(CollisionShape {
+    let character_scene = load_gltf(
+        "res/gltf/character.gltf",
+        GltfAssets {
+            meshes: &mut meshes,
+            materials: &mut materials,
+            textures: &mut textures,
+            entities: &mut entities,
+        },
+    )
+    .expect("Could not load character");
+    let character = character_scene.find("character").unwrap().entities[0].clone();
+    if let Some(entity) = entities.get_mut(&character) {
+        entity.collision_shape = Some(CollisionShape {
             body_status: BodyStatus::Dynamic,
             body: Body::Sphere(Sphere { radius: 0.5 }),
-        }),
-        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 4.0)),
-    });
+        });
+        entity.transform = Transform::from_translation(Vec3::new(0.0, 5.0, 4.0));
+    }
+    let character_rest_pose = character_scene.rest_pose();
+    let idle = character_scene.find_animation("idle").unwrap();
+    let walk = character_scene.find_animation("walk").unwrap();
+    let mut character_animation = AnimationPlayer::default();
+    character_animation.play(idle, 0.0);
     physics.register(character.clone(), &entities);
     physics.register_character(character.clone());
+    pipeline_bindgroup.update_materials(&renderer, &materials, &mut textures);
 
     let mut follow_camera = FollowCamera::new(
         entities.get(&character).unwrap().transform.clone(),
@@ -144,22 +213,42 @@ fn main() -> Result<(), GameError> {
                 let steps = steps_since_start - steps_taken;
                 keyboard_state_from_events(&input_all.keyboard_events, &mut input_all.keyboard_input);
                 character_controller.keyboard(&input_all.keyboard_input);
+                edit_world(
+                    &mut world,
+                    &input_all.keyboard_input,
+                    &entities.get(&character).unwrap().transform,
+                )
+                .expect("Could not edit the world");
                 camera_controller.mouse_handling(&input_all.mouse_wheel_events, &input_all.mouse_motion_events);
                 follow_camera.handle_camera_controller(&camera_controller);
-                for _ in 0..steps {
-                    physics.step(&mut entities, &character_controller);
+                let character_position = entities.get(&character).unwrap().transform.translation;
+                // the character waits until the ground below it has its collider
+                if world.is_generated([character_position.x, character_position.y, character_position.z]) {
+                    for _ in 0..steps {
+                        physics.step(&mut entities, &character_controller);
+                    }
                 }
                 steps_taken = steps_since_start;
+                let moving = character_controller.forward != 0.0;
+                character_animation.play(if moving { walk } else { idle }, 0.2);
+                character_animation.update(steps as f32 / 60.0, &character_scene.animations);
+                character_scene.apply_pose(
+                    &character_animation.pose(&character_scene.animations, &character_rest_pose),
+                    &mut meshes,
+                );
                 follow_camera.follow(entities.get(&character).unwrap().transform.clone());
                 input_all.clear_events();
                 let player_position = entities.get(&character).unwrap().transform.clone().translation;
                 let before_generate = std::time::Instant::now();
-                world.generate_around(
-                    &vox_models,
-                    [player_position.x, player_position.y, player_position.z],
-                    &mut meshes,
-                    &mut entities,
-                );
+                world
+                    .generate_around(
+                        [player_position.x, player_position.y, player_position.z],
+                        &mut meshes,
+                        &mut entities,
+                        &mut physics,
+                        &mut lights,
+                    )
+                    .expect("Could not load the world around the character");
                 let after_generate = std::time::Instant::now();
 
                 let before_render = std::time::Instant::now();
@@ -170,6 +259,15 @@ fn main() -> Result<(), GameError> {
                     .output
                     .view;
 
+                shadow_pipeline.render(
+                    &entities,
+                    &meshes,
+                    &lights,
+                    &shadow_pipeline_bindgroup,
+                    &shadow_map,
+                    &follow_camera,
+                    &mut renderer,
+                );
                 pipeline.render(
                     &entities,
                     &mut meshes,
@@ -204,12 +302,17 @@ fn main() -> Result<(), GameError> {
                 WindowEvent::Resized(size) => {
                     follow_camera.set_aspect_ratio(size.width as f32 / size.height as f32);
                     futures::executor::block_on(renderer.resize(size.width, size.height));
+                    pipeline_bindgroup.resize(&renderer);
                 }
                 WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                     follow_camera.set_aspect_ratio(new_inner_size.width as f32 / new_inner_size.height as f32);
                     futures::executor::block_on(renderer.resize(new_inner_size.width, new_inner_size.height));
+                    pipeline_bindgroup.resize(&renderer);
+                }
+                WindowEvent::CloseRequested => {
+                    world.save().expect("Could not save the world");
+                    *control_flow = ControlFlow::Exit
                 }
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                 WindowEvent::KeyboardInput { .. } => {
                     winit_impl::handle_input(&mut input_all, &event);
                 }
@@ -223,3 +326,39 @@ fn main() -> Result<(), GameError> {
         }
     });
 }
+
+// E digs out the voxels in front of the character, Q puts a voxel of the color it looks at against them and X writes
+// the voxels around the character to saves/export.vox.
+fn edit_world(world: &mut World, keyboard: &Input<KeyCode>, character: &Transform) -> Result<(), GameError> {
+    if keyboard.just_pressed(KeyCode::E) || keyboard.just_pressed(KeyCode::Q) {
+        // the character walks towards -forward, it looks a bit down
+        let origin = character.translation + Vec3::new(0.0, 0.5, 0.0);
+        let direction = -character.forward() - Vec3::new(0.0, 0.5, 0.0);
+        let hit = world.raycast(
+            [origin.x, origin.y, origin.z],
+            [direction.x, direction.y, direction.z],
+            3.0,
+        )?;
+        if let Some(hit) = hit {
+            if keyboard.just_pressed(KeyCode::E) {
+                let [x, y, z] = hit.voxel;
+                world.fill_box([x - 1, y - 1, z - 1], [x + 1, y + 1, z + 1], None)?;
+            } else {
+                let voxel = world.get_voxel(hit.voxel)?;
+                world.set_voxel(hit.empty, voxel)?;
+            }
+        }
+    }
+    if keyboard.just_pressed(KeyCode::X) {
+        let position = character.translation / VOXEL_SIZE;
+        let [x, y, z] = [
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        ];
+        let region = world.copy_region([x - 32, y - 32, z - 32], [x + 31, y + 31, z + 31])?;
+        std::fs::create_dir_all("saves")?;
+        std::fs::write("saves/export.vox", write_vox(&region)?)?;
+    }
+    Ok(())
+}
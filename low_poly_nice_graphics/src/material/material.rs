@@ -0,0 +1,50 @@
+use crate::{registry::Handle, texture::Texture};
+
+// Surface properties shared by all entities that use it, the color of a vertex is multiplied by the albedo.
+#[derive(Clone)]
+pub struct Material {
+    pub albedo: [f32; 4],
+    pub specular: [f32; 4],
+    pub shininess: f32,
+    // added on top of the lit color, also where no light reaches
+    pub emissive: [f32; 4],
+    pub texture: Option<Handle<Texture>>,
+}
+
+impl Material {
+    pub fn new(albedo: [f32; 4]) -> Self {
+        Self {
+            albedo,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_specular(mut self, specular: [f32; 4], shininess: f32) -> Self {
+        self.specular = specular;
+        self.shininess = shininess;
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: [f32; 4]) -> Self {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_texture(mut self, texture: Handle<Texture>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+}
+
+// what every entity without a material looks like
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            albedo: [1.0, 1.0, 1.0, 1.0],
+            specular: [0.1, 0.1, 0.1, 1.0],
+            shininess: 16.0,
+            emissive: [0.0, 0.0, 0.0, 1.0],
+            texture: None,
+        }
+    }
+}
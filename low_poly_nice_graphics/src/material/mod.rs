@@ -0,0 +1,3 @@
+mod material;
+
+pub use material::Material;
@@ -0,0 +1,137 @@
+use crate::{
+    mesh::{Mesh, MeshProcessor},
+    registry::{Handle, Registry},
+};
+use std::collections::HashMap;
+
+// A simpler version of a mesh, drawn once the mesh covers less than screen_size of the screen height.
+#[derive(Clone)]
+pub struct Lod {
+    pub mesh_handle: Handle<Mesh>,
+    pub screen_size: f32,
+}
+
+impl Mesh {
+    // The coarsest level that may be drawn at this screen size, None draws the mesh itself.
+    pub fn lod(&self, screen_size: f32) -> Option<&Handle<Mesh>> {
+        self.lods
+            .iter()
+            .rev()
+            .find(|lod| screen_size < lod.screen_size)
+            .map(|lod| &lod.mesh_handle)
+    }
+}
+
+// Decimates the mesh down to about target_triangles. Vertices with the same position are collapsed together, the
+// corners of the remaining triangles keep the normal, color and uv they had.
+pub fn simplify(mesh: &Mesh, target_triangles: usize) -> Mesh {
+    let mut lookup = HashMap::new();
+    let mut positions = Vec::new();
+    let shared = mesh
+        .vertices
+        .iter()
+        .map(|vertex| {
+            let key = [
+                vertex.position[0].to_bits(),
+                vertex.position[1].to_bits(),
+                vertex.position[2].to_bits(),
+            ];
+            *lookup.entry(key).or_insert_with(|| {
+                positions.push(vertex.position);
+                positions.len() as u32 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+    let triangles = mesh
+        .indices
+        .chunks(3)
+        .map(|t| [shared[t[0] as usize], shared[t[1] as usize], shared[t[2] as usize]])
+        .collect::<Vec<_>>();
+
+    let simplified = xp_mesh::mesh::simplify(&positions, &triangles, target_triangles);
+    let mut corners = Vec::with_capacity(simplified.triangles.len() * 3);
+    for (triangle, source) in simplified.triangles.iter().zip(simplified.sources.iter()) {
+        for (corner, position) in triangle.iter().enumerate() {
+            let mut vertex = mesh.vertices[mesh.indices[source * 3 + corner] as usize];
+            vertex.position = positions[*position as usize];
+            corners.push(vertex);
+        }
+    }
+    MeshProcessor::new()
+        .process(&Mesh {
+            indices: (0..corners.len() as u32).collect(),
+            vertices: corners,
+            just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
+        })
+        .mesh
+}
+
+// The levels per (target_triangles, screen_size), each simplified from the one before. Levels that don't remove any
+// triangles are left out.
+pub fn simplify_levels(mesh: &Mesh, levels: &[(usize, f32)]) -> Vec<(Mesh, f32)> {
+    let mut simplified: Vec<(Mesh, f32)> = Vec::new();
+    for (target_triangles, screen_size) in levels {
+        let previous = simplified.last().map(|(level, _)| level).unwrap_or(mesh);
+        let level = simplify(previous, *target_triangles);
+        if level.indices.len() < previous.indices.len() {
+            simplified.push((level, *screen_size));
+        }
+    }
+    simplified
+}
+
+// Adds the simplified levels to the mesh, see simplify_levels.
+pub fn generate_lods(mesh: &mut Mesh, levels: &[(usize, f32)], meshes: &mut Registry<Mesh>) {
+    for (level, screen_size) in simplify_levels(mesh, levels) {
+        mesh.lods.push(Lod {
+            mesh_handle: meshes.add(level),
+            screen_size,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generators::Zero,
+        mesh::{generate_lods, simplify, Lod, Mesh, Plane},
+        registry::Registry,
+    };
+
+    #[test]
+    fn flat_plane_simplifies_to_its_outline() {
+        let plane = Mesh::from(Plane::new(4.0, 3, Box::new(Zero)));
+        let simple = simplify(&plane, 2);
+        assert!(simple.indices.len() / 3 < plane.indices.len() / 3);
+        let area = simple
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let p = |i: u32| glam::Vec3::from(simple.vertices[i as usize].position);
+                (p(t[1]) - p(t[0])).cross(p(t[2]) - p(t[0])).length() / 2.0
+            })
+            .sum::<f32>();
+        assert!((area - 16.0).abs() < 1e-3);
+        for vertex in simple.vertices.iter() {
+            assert_eq!(vertex.position[1], 0.0);
+            assert!(vertex.normal[0] == 0.0 && vertex.normal[1] > 0.0 && vertex.normal[2] == 0.0);
+        }
+    }
+
+    #[test]
+    fn lods_are_picked_by_screen_size() {
+        let mut meshes = Registry::new();
+        let mut plane = Mesh::from(Plane::new(4.0, 3, Box::new(Zero)));
+        generate_lods(&mut plane, &[(64, 0.5), (16, 0.1), (8, 0.05)], &mut meshes);
+        assert!(plane.lods.len() >= 2);
+        let triangles = |lod: &Lod| meshes.get(&lod.mesh_handle).unwrap().indices.len() / 3;
+        for pair in plane.lods.windows(2) {
+            assert!(triangles(&pair[1]) < triangles(&pair[0]));
+        }
+        assert!(plane.lod(1.0).is_none());
+        assert_eq!(plane.lod(0.3).unwrap().id, plane.lods[0].mesh_handle.id);
+        assert_eq!(plane.lod(0.0).unwrap().id, plane.lods.last().unwrap().mesh_handle.id);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     generators::{Height, Zero},
-    mesh::Vertex,
+    mesh::{Lod, MeshProcessor, Vertex},
 };
 use glam::Vec3;
 use std::collections::HashMap;
@@ -10,12 +10,17 @@ pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub just_loaded: bool,
+    // only the vertices moved, their buffer is written again instead of building new buffers
+    pub vertices_changed: bool,
+    // simpler versions of this mesh, from detailed to coarse
+    pub lods: Vec<Lod>,
 }
 
 pub struct Plane {
     size: f32,
     subdivisions: u32,
     height_function: Box<dyn Height>,
+    smooth_angle: Option<f32>,
 }
 
 impl Plane {
@@ -24,6 +29,7 @@ impl Plane {
             size,
             subdivisions,
             height_function,
+            smooth_angle: None,
         }
     }
 
@@ -32,8 +38,15 @@ impl Plane {
             size,
             subdivisions: 0,
             height_function: Box::new(Zero),
+            smooth_angle: None,
         }
     }
+
+    // See MeshProcessor::with_smooth_normals, without it every triangle is flat.
+    pub fn with_smooth_normals(mut self, angle: f32) -> Self {
+        self.smooth_angle = Some(angle);
+        self
+    }
 }
 
 pub fn triangle_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
@@ -76,6 +89,8 @@ impl From<Plane> for Mesh {
                     (z + 1.0) * increment,
                 ];
 
+                // the texture is stretched once over the whole plane
+                let uv = |p: [f32; 3]| [p[0] / plane.size + 0.5, p[2] / plane.size + 0.5];
                 let n0 = triangle_normal(p00, p01, p11);
                 let n1 = triangle_normal(p00, p11, p10);
                 vertices.extend_from_slice(&[
@@ -83,31 +98,55 @@ impl From<Plane> for Mesh {
                         position: p00,
                         normal: n0,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p00),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                     Vertex {
                         position: p01,
                         normal: n0,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p01),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                     Vertex {
                         position: p11,
                         normal: n0,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p11),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                     Vertex {
                         position: p00,
                         normal: n1,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p00),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                     Vertex {
                         position: p11,
                         normal: n1,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p11),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                     Vertex {
                         position: p10,
                         normal: n1,
                         color: [0.86, 0.86, 0.86],
+                        ambient_occlusion: 1.0,
+                        uv: uv(p10),
+                        alpha: 1.0,
+                        emission: 0.0,
                     },
                 ]);
                 indices.extend_from_slice(&[
@@ -121,11 +160,19 @@ impl From<Plane> for Mesh {
                 index_count += 6;
             }
         }
-        Self {
-            vertices,
-            indices,
-            just_loaded: true,
-        }
+        let processor = match plane.smooth_angle {
+            Some(angle) => MeshProcessor::new().with_smooth_normals(angle),
+            None => MeshProcessor::new(),
+        };
+        processor
+            .process(&Self {
+                vertices,
+                indices,
+                just_loaded: true,
+                vertices_changed: false,
+                lods: Vec::new(),
+            })
+            .mesh
     }
 }
 
@@ -200,10 +247,24 @@ impl From<Cube> for Mesh {
             0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28,
             29, 30, 31, 32, 33, 34, 35,
         ]);
+        // every face shows the whole texture, projected along its normal
+        for vertex in vertices.iter_mut() {
+            let (u, v) = match vertex.normal {
+                [x, _, _] if x != 0.0 => (2, 1),
+                [_, y, _] if y != 0.0 => (0, 2),
+                _ => (0, 1),
+            };
+            vertex.uv = [
+                vertex.position[u] / cube.size + 0.5,
+                0.5 - vertex.position[v] / cube.size,
+            ];
+        }
         Self {
             vertices,
             indices,
             just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
         }
     }
 }
@@ -331,6 +392,8 @@ impl From<IcoSphere> for Mesh {
             vertices,
             indices,
             just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
         }
     }
 }
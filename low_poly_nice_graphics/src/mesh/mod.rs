@@ -1,5 +1,9 @@
+mod lod;
 mod mesh;
+mod processing;
 mod vertex;
 
+pub use lod::{generate_lods, simplify, simplify_levels, Lod};
 pub use mesh::{triangle_normal, Cube, IcoSphere, Mesh, Plane};
+pub use processing::{tangents, MeshProcessor, MeshStats, ProcessedMesh};
 pub use vertex::Vertex;
@@ -0,0 +1,318 @@
+use crate::mesh::{triangle_normal, Mesh, Vertex};
+use glam::{Vec2, Vec3};
+use std::collections::HashMap;
+
+// Normals, colors and uvs closer than this are the same when welding.
+const ATTRIBUTE_STEP: f32 = 1.0e-4;
+
+// What a mesh takes up on the gpu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshStats {
+    pub vertices: usize,
+    pub triangles: usize,
+    pub bytes: usize,
+}
+
+impl MeshStats {
+    pub fn of(mesh: &Mesh) -> Self {
+        Self {
+            vertices: mesh.vertices.len(),
+            triangles: mesh.indices.len() / 3,
+            bytes: mesh.vertices.len() * std::mem::size_of::<Vertex>()
+                + mesh.indices.len() * std::mem::size_of::<u32>(),
+        }
+    }
+}
+
+pub struct ProcessedMesh {
+    pub mesh: Mesh,
+    // per vertex of mesh, xyz points along increasing u, w is the handedness of the bitangent
+    pub tangents: Vec<[f32; 4]>,
+    // the vertex of the original mesh every vertex of mesh is made from
+    pub sources: Vec<usize>,
+    pub before: MeshStats,
+    pub after: MeshStats,
+}
+
+// Turns meshes with a vertex per triangle corner into indexed meshes, corners that end up with the same attributes
+// share a vertex.
+pub struct MeshProcessor {
+    // positions closer than this are welded together
+    weld_distance: f32,
+    // None keeps the normals of the mesh
+    smooth_angle: Option<f32>,
+}
+
+impl MeshProcessor {
+    pub fn new() -> Self {
+        Self {
+            weld_distance: 1.0e-5,
+            smooth_angle: None,
+        }
+    }
+
+    pub fn with_weld_distance(mut self, weld_distance: f32) -> Self {
+        self.weld_distance = weld_distance;
+        self
+    }
+
+    // Triangles that meet at an angle (in radians) up to angle share their normals, sharper edges stay flat.
+    pub fn with_smooth_normals(mut self, angle: f32) -> Self {
+        self.smooth_angle = Some(angle);
+        self
+    }
+
+    pub fn process(&self, mesh: &Mesh) -> ProcessedMesh {
+        let corners = match self.smooth_angle {
+            Some(angle) => self.smooth_corners(mesh, angle),
+            None => mesh.indices.iter().map(|i| mesh.vertices[*i as usize]).collect(),
+        };
+
+        let mut lookup = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut sources = Vec::new();
+        let mut indices = Vec::with_capacity(corners.len());
+        for (corner, vertex) in corners.iter().enumerate() {
+            let index = *lookup.entry(self.weld_key(vertex)).or_insert_with(|| {
+                vertices.push(*vertex);
+                sources.push(mesh.indices[corner] as usize);
+                vertices.len() as u32 - 1
+            });
+            indices.push(index);
+        }
+        let processed = Mesh {
+            vertices,
+            indices,
+            just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
+        };
+        ProcessedMesh {
+            tangents: tangents(&processed),
+            sources,
+            before: MeshStats::of(mesh),
+            after: MeshStats::of(&processed),
+            mesh: processed,
+        }
+    }
+
+    fn grid(&self, position: [f32; 3]) -> [i64; 3] {
+        let cell = |v: f32| (v / self.weld_distance).round() as i64;
+        [cell(position[0]), cell(position[1]), cell(position[2])]
+    }
+
+    fn weld_key(&self, vertex: &Vertex) -> ([i64; 3], Vec<i64>) {
+        let normal = normalized(Vec3::from(vertex.normal));
+        let attributes = normal
+            .as_ref()
+            .iter()
+            .chain(vertex.color.iter())
+            .chain(vertex.uv.iter())
+            .chain([vertex.ambient_occlusion, vertex.alpha, vertex.emission].iter())
+            .map(|v| (v / ATTRIBUTE_STEP).round() as i64)
+            .collect();
+        (self.grid(vertex.position), attributes)
+    }
+
+    // Every corner gets the area weighted normal of the triangles around its position that face about the same way
+    // as its own triangle.
+    fn smooth_corners(&self, mesh: &Mesh, angle: f32) -> Vec<Vertex> {
+        let triangles = mesh.indices.chunks(3).collect::<Vec<_>>();
+        let face_normals = triangles
+            .iter()
+            .map(|t| {
+                Vec3::from(triangle_normal(
+                    mesh.vertices[t[0] as usize].position,
+                    mesh.vertices[t[1] as usize].position,
+                    mesh.vertices[t[2] as usize].position,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut around = HashMap::new();
+        for (t, triangle) in triangles.iter().enumerate() {
+            for i in triangle.iter() {
+                let triangles_around = around
+                    .entry(self.grid(mesh.vertices[*i as usize].position))
+                    .or_insert_with(Vec::new);
+                if triangles_around.last() != Some(&t) {
+                    triangles_around.push(t);
+                }
+            }
+        }
+        let min_cos = angle.cos();
+        let mut corners = Vec::with_capacity(mesh.indices.len());
+        for (t, triangle) in triangles.iter().enumerate() {
+            let own = normalized(face_normals[t]);
+            for i in triangle.iter() {
+                let vertex = mesh.vertices[*i as usize];
+                let normal = around[&self.grid(vertex.position)]
+                    .iter()
+                    .map(|other| face_normals[*other])
+                    .filter(|normal| normalized(*normal).dot(own) >= min_cos)
+                    .fold(Vec3::zero(), |sum, normal| sum + normal);
+                corners.push(Vertex {
+                    // degenerate triangles keep the normal they had
+                    normal: if normal.length_squared() > 0.0 {
+                        normal.normalize().into()
+                    } else {
+                        vertex.normal
+                    },
+                    ..vertex
+                });
+            }
+        }
+        corners
+    }
+}
+
+// zero length vectors stay zero
+fn normalized(v: Vec3) -> Vec3 {
+    if v.length_squared() > 0.0 {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+impl Default for MeshProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Tangents along the uvs, for vertices without usable uvs any direction perpendicular to the normal.
+pub fn tangents(mesh: &Mesh) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3::zero(); mesh.vertices.len()];
+    let mut bitangents = vec![Vec3::zero(); mesh.vertices.len()];
+    for triangle in mesh.indices.chunks(3) {
+        let [v0, v1, v2] = [
+            mesh.vertices[triangle[0] as usize],
+            mesh.vertices[triangle[1] as usize],
+            mesh.vertices[triangle[2] as usize],
+        ];
+        let edge1 = Vec3::from(v1.position) - Vec3::from(v0.position);
+        let edge2 = Vec3::from(v2.position) - Vec3::from(v0.position);
+        let duv1 = Vec2::from(v1.uv) - Vec2::from(v0.uv);
+        let duv2 = Vec2::from(v2.uv) - Vec2::from(v0.uv);
+        let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+        for i in triangle {
+            tangents[*i as usize] += tangent;
+            bitangents[*i as usize] += bitangent;
+        }
+    }
+    mesh.vertices
+        .iter()
+        .zip(tangents.iter().zip(bitangents.iter()))
+        .map(|(vertex, (tangent, bitangent))| {
+            let normal = normalized(Vec3::from(vertex.normal));
+            // perpendicular to the normal
+            let mut t = normalized(*tangent - normal * normal.dot(*tangent));
+            if t == Vec3::zero() {
+                let axis = if normal.x.abs() < 0.9 {
+                    Vec3::unit_x()
+                } else {
+                    Vec3::unit_y()
+                };
+                t = normalized(normal.cross(axis));
+            }
+            let handedness = if normal.cross(t).dot(*bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [t.x, t.y, t.z, handedness]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generators::SineCosine,
+        mesh::{Cube, Mesh, MeshProcessor, Plane, Vertex},
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn welding_shares_corners_of_flat_faces() {
+        let processed = MeshProcessor::new().process(&Mesh::from(Cube::new(1.0)));
+        // the faces keep their own normals and uvs, so 4 vertices per face
+        assert_eq!(processed.after.vertices, 24);
+        assert_eq!(processed.before.vertices, 36);
+        assert_eq!(processed.after.triangles, 12);
+        for (vertex, source) in processed.mesh.vertices.iter().zip(processed.sources.iter()) {
+            assert_eq!(vertex.position, Mesh::from(Cube::new(1.0)).vertices[*source].position);
+        }
+    }
+
+    #[test]
+    fn glass_and_glowing_corners_are_not_welded() {
+        let corner = |position: [f32; 3]| Vertex::new(position, [0.0, 1.0, 0.0], [1.0, 1.0, 1.0]);
+        let mut vertices = vec![
+            corner([0.0, 0.0, 0.0]),
+            corner([0.0, 0.0, 1.0]),
+            corner([1.0, 0.0, 0.0]),
+            corner([1.0, 0.0, 0.0]),
+            corner([0.0, 0.0, 1.0]),
+            corner([1.0, 0.0, 1.0]),
+        ];
+        let mesh = |vertices: &Vec<Vertex>| Mesh {
+            vertices: vertices.clone(),
+            indices: (0..6).collect(),
+            just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
+        };
+        assert_eq!(MeshProcessor::new().process(&mesh(&vertices)).after.vertices, 4);
+        for vertex in &mut vertices[3..] {
+            vertex.alpha = 0.5;
+        }
+        assert_eq!(MeshProcessor::new().process(&mesh(&vertices)).after.vertices, 6);
+        for vertex in &mut vertices[3..] {
+            vertex.alpha = 1.0;
+            vertex.emission = 1.0;
+        }
+        assert_eq!(MeshProcessor::new().process(&mesh(&vertices)).after.vertices, 6);
+    }
+
+    #[test]
+    fn smooth_normals_respect_the_angle() {
+        let cube = Mesh::from(Cube::new(1.0));
+        // the faces of a cube meet at 90 degrees
+        let flat = MeshProcessor::new().with_smooth_normals(1.0).process(&cube);
+        assert_eq!(flat.after.vertices, 24);
+        let smooth = MeshProcessor::new().with_smooth_normals(2.0).process(&cube);
+        let corner = smooth
+            .mesh
+            .vertices
+            .iter()
+            .find(|vertex| vertex.position == [0.5, 0.5, 0.5])
+            .unwrap();
+        assert!((Vec3::from(corner.normal) - Vec3::new(1.0, 1.0, 1.0).normalize()).length() < 1e-5);
+    }
+
+    #[test]
+    fn smooth_plane_shares_all_grid_points() {
+        // 8x8 quads, 6 corners each
+        let flat = Mesh::from(Plane::new(8.0, 3, Box::new(SineCosine)));
+        assert!(flat.vertices.len() > 81);
+        let smooth = Mesh::from(Plane::new(8.0, 3, Box::new(SineCosine)).with_smooth_normals(std::f32::consts::PI));
+        assert_eq!(smooth.vertices.len(), 81);
+        assert_eq!(smooth.indices.len(), 8 * 8 * 6);
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let processed = MeshProcessor::new().process(&Mesh::from(Plane::flat(2.0)));
+        for tangent in &processed.tangents {
+            // u increases with x on the plane
+            assert!((tangent[0] - 1.0).abs() < 1e-5);
+            assert_eq!(tangent[3].abs(), 1.0);
+        }
+    }
+}
@@ -4,6 +4,14 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub color: [f32; 3],
+    // 1.0 is not occluded, baked per vertex by the voxel mesher
+    pub ambient_occlusion: f32,
+    // texture coordinates, (0, 0) is the top left of the texture
+    pub uv: [f32; 2],
+    // below 1.0 the mesh is drawn in the transparent pass, blended over what is behind it
+    pub alpha: f32,
+    // how much of its color the vertex gives off on its own, added to the light it gets
+    pub emission: f32,
 }
 
 impl Vertex {
@@ -12,6 +20,30 @@ impl Vertex {
             position,
             normal,
             color,
+            ambient_occlusion: 1.0,
+            uv: [0.0, 0.0],
+            alpha: 1.0,
+            emission: 0.0,
         }
     }
+
+    pub fn with_ambient_occlusion(mut self, ambient_occlusion: f32) -> Self {
+        self.ambient_occlusion = ambient_occlusion;
+        self
+    }
+
+    pub fn with_uv(mut self, uv: [f32; 2]) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: f32) -> Self {
+        self.emission = emission;
+        self
+    }
 }
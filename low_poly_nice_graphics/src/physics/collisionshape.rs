@@ -16,10 +16,18 @@ pub struct Sphere {
     pub radius: f32,
 }
 
+// Triangles in the space of the entity, for static bodies like the chunks of the world.
+#[derive(Clone)]
+pub struct TriMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub indices: Vec<[u32; 3]>,
+}
+
 #[derive(Clone)]
 pub enum Body {
     Cuboid(Cuboid),
     Sphere(Sphere),
+    TriMesh(TriMesh),
 }
 
 #[derive(Clone)]
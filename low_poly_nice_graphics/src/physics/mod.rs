@@ -1,5 +1,5 @@
 mod collisionshape;
 mod physics;
 
-pub use collisionshape::{Body, BodyStatus, CollisionShape, Cuboid, Sphere};
+pub use collisionshape::{Body, BodyStatus, CollisionShape, Cuboid, Sphere, TriMesh};
 pub use physics::Physics;
@@ -29,6 +29,7 @@ pub struct Physics {
     joints: JointSet,
     ccd_solver: CCDSolver,
     physics_objects_dynamic: HashMap<u64, PhysicsObjectHandle>,
+    physics_objects_static: HashMap<u64, PhysicsObjectHandle>,
     character: Option<Handle<Entity>>,
 }
 
@@ -44,6 +45,7 @@ impl Default for Physics {
             joints: JointSet::new(),
             ccd_solver: CCDSolver::new(),
             physics_objects_dynamic: HashMap::new(),
+            physics_objects_static: HashMap::new(),
             character: None,
         }
     }
@@ -63,7 +65,7 @@ impl Physics {
             }
         }
         self.pipeline.step(
-            &Vector3::new(0.0, 0.0 /*-9.81*/, 0.0),
+            &Vector3::new(0.0, -9.81, 0.0),
             &self.int_params,
             &mut self.broad_phase,
             &mut self.narrow_phase,
@@ -100,16 +102,8 @@ impl Physics {
     ) {
         let entity = entities.get(&entity_handle).unwrap();
         let mesh = meshes.get(&entity.mesh_handle).unwrap();
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-        let mut index: u32 = 0;
-        for triangle in mesh.vertices.chunks(3) {
-            vertices.push(triangle[0].position.into());
-            vertices.push(triangle[1].position.into());
-            vertices.push(triangle[2].position.into());
-            indices.push([index, index + 1, index + 2]);
-            index += 3;
-        }
+        let vertices = mesh.vertices.iter().map(|v| v.position.into()).collect();
+        let indices = mesh.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect();
         let rigid_body = RigidBodyBuilder::new_static().build();
         let handle = self.bodies.insert(rigid_body);
         let collider = ColliderBuilder::trimesh(vertices, indices).build();
@@ -126,6 +120,11 @@ impl Physics {
                             .build()
                     }
                     Body::Sphere(sphere) => ColliderBuilder::ball(sphere.radius).friction(0.0).build(),
+                    Body::TriMesh(trimesh) => ColliderBuilder::trimesh(
+                        trimesh.vertices.iter().map(|v| (*v).into()).collect(),
+                        trimesh.indices.clone(),
+                    )
+                    .build(),
                 };
                 let translation = entity.transform.translation;
                 let rigid_body = match &collision_shape.body_status {
@@ -139,7 +138,10 @@ impl Physics {
                 let r = self.bodies.insert(rigid_body);
                 let c = self.colliders.insert(collider, r, &mut self.bodies);
                 match &collision_shape.body_status {
-                    BodyStatus::Static => (),
+                    BodyStatus::Static => {
+                        self.physics_objects_static
+                            .insert(entity_handle.id, PhysicsObjectHandle { r, c });
+                    }
                     BodyStatus::Dynamic => {
                         self.physics_objects_dynamic
                             .insert(entity_handle.id, PhysicsObjectHandle { r, c });
@@ -148,10 +150,30 @@ impl Physics {
             }
         }
     }
+
+    // Removes the body and collider of a registered entity.
+    pub fn remove(&mut self, entity_handle: &Handle<Entity>) {
+        let physics_object = self
+            .physics_objects_static
+            .remove(&entity_handle.id)
+            .or_else(|| self.physics_objects_dynamic.remove(&entity_handle.id));
+        if let Some(physics_object) = physics_object {
+            self.bodies
+                .remove(physics_object.r, &mut self.colliders, &mut self.joints);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        controllers::CharacterController,
+        entity::Entity,
+        physics::{Body, BodyStatus, CollisionShape, Physics, Sphere, TriMesh},
+        registry::{Handle, Registry},
+        transform::Transform,
+    };
+    use glam::Vec3;
     use rapier2d::{
         dynamics::{IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodySet},
         geometry::{BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase},
@@ -206,4 +228,46 @@ mod tests {
             println!("{} {}", translation.x, translation.y);
         }
     }
+
+    #[test]
+    fn ball_rests_on_trimesh_until_it_is_removed() {
+        let mut entities = Registry::new();
+        let floor = entities.add(Entity {
+            mesh_handle: Handle::new(0),
+            material_handle: None,
+            collision_shape: Some(CollisionShape {
+                body_status: BodyStatus::Static,
+                body: Body::TriMesh(TriMesh {
+                    vertices: vec![[-5.0, 0.0, -5.0], [5.0, 0.0, -5.0], [5.0, 0.0, 5.0], [-5.0, 0.0, 5.0]],
+                    indices: vec![[0, 2, 1], [0, 3, 2]],
+                }),
+            }),
+            transform: Transform::from_translation(Vec3::zero()),
+        });
+        let ball = entities.add(Entity {
+            mesh_handle: Handle::new(0),
+            material_handle: None,
+            collision_shape: Some(CollisionShape {
+                body_status: BodyStatus::Dynamic,
+                body: Body::Sphere(Sphere { radius: 0.5 }),
+            }),
+            transform: Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+        });
+        let mut physics = Physics::default();
+        physics.register(floor.clone(), &entities);
+        physics.register(ball.clone(), &entities);
+        // only the character is moved along with its body
+        physics.register_character(ball.clone());
+        let controller = CharacterController::default();
+        for _ in 0..120 {
+            physics.step(&mut entities, &controller);
+        }
+        let height = |entities: &Registry<Entity>| entities.get(&ball).unwrap().transform.translation.y;
+        assert!((height(&entities) - 0.5).abs() < 0.1);
+        physics.remove(&floor);
+        for _ in 0..60 {
+            physics.step(&mut entities, &controller);
+        }
+        assert!(height(&entities) < 0.0);
+    }
 }
@@ -1,13 +1,22 @@
 use crate::{
-    registry::Registry,
+    material::Material,
+    registry::{Handle, Registry},
     renderer::{
+        clusters::{Cluster, ClusterGrid, CLUSTER_X, CLUSTER_Y, CLUSTER_Z, MAX_NR_OF_LIGHT_INDICES, NR_OF_CLUSTERS},
         light::{MAX_NR_OF_DIRECTIONAL_LIGHTS, MAX_NR_OF_POINT_LIGHTS, MAX_NR_OF_SPOT_LIGHTS},
+        shadow_map::{directional_lights_with_shadow, spot_lights_with_shadow_index, ShadowMap, ShadowUniform},
+        ssao_bindgroup::SsaoBindGroup,
+        texture_buffer::TextureBuffer,
         Camera, DirectionalProperties, Light, PointProperties, Renderer, SpotProperties,
     },
+    texture::Texture,
 };
 use glam::Mat4;
+use std::{cell::RefCell, collections::HashMap};
 
 const MAX_NR_OF_INSTANCES: usize = 50000;
+// including the default material at index 0
+pub const MAX_NR_OF_MATERIALS: usize = 1024;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -15,17 +24,61 @@ pub struct Uniform {
     pub v: Mat4,
     pub p: Mat4,
     pub world_camera_position: [f32; 4],
-    pub material_specular: [f32; 4],
-    pub material_shininess: f32,
     pub nr_of_directional_lights: u32,
     pub nr_of_spot_lights: u32,
     pub nr_of_point_lights: u32,
+    pub p0: u32,
+    pub cluster_dimensions: [u32; 4],
+    // x: scale, y: bias of the logarithmic depth slices, z, w: screen size
+    pub cluster_depth: [f32; 4],
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Instance {
     pub m: Mat4,
+    pub material_index: u32,
+    pub p0: u32,
+    pub p1: u32,
+    pub p2: u32,
+}
+
+impl Instance {
+    pub fn new(m: Mat4, material_index: u32) -> Self {
+        Self {
+            m,
+            material_index,
+            p0: 0,
+            p1: 0,
+            p2: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialProperties {
+    pub albedo: [f32; 4],
+    pub specular: [f32; 4],
+    pub emissive: [f32; 4],
+    pub shininess: f32,
+    pub p0: f32,
+    pub p1: f32,
+    pub p2: f32,
+}
+
+impl From<&Material> for MaterialProperties {
+    fn from(material: &Material) -> Self {
+        Self {
+            albedo: material.albedo,
+            specular: material.specular,
+            emissive: material.emissive,
+            shininess: material.shininess,
+            p0: 0.0,
+            p1: 0.0,
+            p2: 0.0,
+        }
+    }
 }
 
 unsafe impl bytemuck::Pod for Uniform {}
@@ -34,18 +87,48 @@ unsafe impl bytemuck::Zeroable for Uniform {}
 unsafe impl bytemuck::Pod for Instance {}
 unsafe impl bytemuck::Zeroable for Instance {}
 
+unsafe impl bytemuck::Pod for MaterialProperties {}
+unsafe impl bytemuck::Zeroable for MaterialProperties {}
+
+// The default material followed by the materials of the registry, and where each of them ended up.
+pub fn material_properties(materials: &Registry<Material>) -> (Vec<MaterialProperties>, HashMap<u64, u32>) {
+    let mut properties = vec![MaterialProperties::from(&Material::default())];
+    let mut indices = HashMap::new();
+    for (id, material) in &materials.registry {
+        indices.insert(*id, properties.len() as u32);
+        properties.push(MaterialProperties::from(material));
+    }
+    (properties, indices)
+}
+
 pub struct BindGroup {
     pub uniform: wgpu::Buffer,
     pub instances: wgpu::Buffer,
     pub directional_lights: wgpu::Buffer,
     pub spot_lights: wgpu::Buffer,
     pub point_lights: wgpu::Buffer,
+    pub shadows: wgpu::Buffer,
+    pub clusters: wgpu::Buffer,
+    pub light_indices: wgpu::Buffer,
+    pub materials: wgpu::Buffer,
+    // position of every registered material in the materials buffer
+    pub material_indices: HashMap<u64, u32>,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
+    pub ssao: SsaoBindGroup,
+    // set 2 of the main pass: the texture of a material, indexed like the materials buffer
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
+    pub material_bind_groups: Vec<wgpu::BindGroup>,
+    pub texture_sampler: wgpu::Sampler,
+    // uploaded textures by texture id, white is used by materials without one
+    pub texture_buffers: HashMap<u64, TextureBuffer>,
+    pub white_texture: TextureBuffer,
+    // the cluster bounds with the projection they are for, they only change when the projection does (on a resize)
+    cluster_grid: RefCell<Option<(Mat4, ClusterGrid)>>,
 }
 
 impl BindGroup {
-    pub fn new(renderer: &Renderer) -> Self {
+    pub fn new(renderer: &Renderer, shadow_map: &ShadowMap) -> Self {
         let uniform = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
@@ -55,24 +138,57 @@ impl BindGroup {
 
         let directional_lights = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
             size: (std::mem::size_of::<DirectionalProperties>() * MAX_NR_OF_DIRECTIONAL_LIGHTS) as u64,
             mapped_at_creation: false,
         });
         let spot_lights = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
             size: (std::mem::size_of::<SpotProperties>() * MAX_NR_OF_SPOT_LIGHTS) as u64,
             mapped_at_creation: false,
         });
 
         let point_lights = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
             size: (std::mem::size_of::<PointProperties>() * MAX_NR_OF_POINT_LIGHTS) as u64,
             mapped_at_creation: false,
         });
 
+        let shadows = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<ShadowUniform>()) as u64,
+            mapped_at_creation: false,
+        });
+
+        let clusters = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<Cluster>() * NR_OF_CLUSTERS) as u64,
+            mapped_at_creation: false,
+        });
+
+        let light_indices = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<u32>() * MAX_NR_OF_LIGHT_INDICES) as u64,
+            mapped_at_creation: false,
+        });
+
+        let materials = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<MaterialProperties>() * MAX_NR_OF_MATERIALS) as u64,
+            mapped_at_creation: false,
+        });
+        renderer.queue.write_buffer(
+            &materials,
+            0,
+            bytemuck::cast_slice(&[MaterialProperties::from(&Material::default())]),
+        );
+
         let instances = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
@@ -96,9 +212,9 @@ impl BindGroup {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 1,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
                             has_dynamic_offset: false,
                         },
@@ -106,9 +222,9 @@ impl BindGroup {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 2,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
                             has_dynamic_offset: false,
                         },
@@ -116,9 +232,9 @@ impl BindGroup {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 3,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
                             has_dynamic_offset: false,
                         },
@@ -134,6 +250,65 @@ impl BindGroup {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
                 ],
                 label: None,
             });
@@ -162,19 +337,178 @@ impl BindGroup {
                     binding: 4,
                     resource: instances.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: shadows.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: clusters.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: light_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: materials.as_entire_binding(),
+                },
             ],
         });
+        let material_bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        // repeats, so uvs outside of [0, 1] tile the texture
+        let texture_sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            anisotropy_clamp: None,
+            border_color: None,
+        });
+        let white_texture = TextureBuffer::white(renderer);
+
         Self {
             uniform,
             instances,
             directional_lights,
             spot_lights,
             point_lights,
+            shadows,
+            clusters,
+            light_indices,
+            materials,
+            material_indices: HashMap::new(),
             bind_group_layout,
             bind_group,
+            ssao: SsaoBindGroup::new(renderer),
+            material_bind_groups: vec![Self::create_material_bind_group(
+                renderer,
+                &material_bind_group_layout,
+                &white_texture,
+                &texture_sampler,
+            )],
+            material_bind_group_layout,
+            texture_sampler,
+            texture_buffers: HashMap::new(),
+            white_texture,
+            cluster_grid: RefCell::new(None),
         }
     }
 
+    fn create_material_bind_group(
+        renderer: &Renderer,
+        layout: &wgpu::BindGroupLayout,
+        texture: &TextureBuffer,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    // the screen sized ssao textures follow the size of the swap chain
+    pub fn resize(&mut self, renderer: &Renderer) {
+        self.ssao.resize(renderer);
+    }
+
+    // Has to be called again when materials are added or changed, entities with an unknown material
+    // render with the default one. Uploads the textures that were just loaded.
+    pub fn update_materials(
+        &mut self,
+        renderer: &Renderer,
+        materials: &Registry<Material>,
+        textures: &mut Registry<Texture>,
+    ) {
+        for (id, texture) in &mut textures.registry {
+            if texture.just_loaded {
+                self.texture_buffers
+                    .insert(*id, TextureBuffer::from_texture(renderer, texture));
+                texture.just_loaded = false;
+            }
+        }
+        let (properties, indices) = material_properties(materials);
+        assert!(properties.len() <= MAX_NR_OF_MATERIALS);
+        renderer
+            .queue
+            .write_buffer(&self.materials, 0, bytemuck::cast_slice(properties.as_slice()));
+
+        let mut material_textures = vec![&self.white_texture; properties.len()];
+        for (id, material) in &materials.registry {
+            if let Some(texture) = material.texture.as_ref().and_then(|t| self.texture_buffers.get(&t.id)) {
+                material_textures[indices[id] as usize] = texture;
+            }
+        }
+        self.material_bind_groups = material_textures
+            .iter()
+            .map(|texture| {
+                Self::create_material_bind_group(
+                    renderer,
+                    &self.material_bind_group_layout,
+                    texture,
+                    &self.texture_sampler,
+                )
+            })
+            .collect();
+        self.material_indices = indices;
+    }
+
+    pub fn material_index(&self, material_handle: &Option<Handle<Material>>) -> u32 {
+        material_handle
+            .as_ref()
+            .and_then(|handle| self.material_indices.get(&handle.id).copied())
+            .unwrap_or(0)
+    }
+
     pub fn update_instances(&self, renderer: &Renderer, transforms: &[Instance]) {
         renderer
             .queue
@@ -182,40 +516,48 @@ impl BindGroup {
     }
 
     pub fn update_uniforms(&self, renderer: &Renderer, lights: &Registry<Light>, camera: &dyn Camera) {
-        let mut directional_lights = Vec::new();
-        let mut spot_lights = Vec::new();
+        let directional_lights = directional_lights_with_shadow(lights);
         let mut point_lights = Vec::new();
         for (_, light) in &lights.registry {
-            match light {
-                Light::Directional(properties) => {
-                    directional_lights.push(*properties);
-                }
-                Light::Spot(properties) => {
-                    spot_lights.push(*properties);
-                }
-                Light::Point(properties) => {
-                    point_lights.push(*properties);
-                }
+            if let Light::Point(properties) = light {
+                point_lights.push(*properties);
             }
         }
+        let spot_lights = spot_lights_with_shadow_index(lights);
         assert!(directional_lights.len() <= MAX_NR_OF_DIRECTIONAL_LIGHTS);
         assert!(spot_lights.len() <= MAX_NR_OF_SPOT_LIGHTS);
         assert!(point_lights.len() <= MAX_NR_OF_POINT_LIGHTS);
 
+        let projection = camera.get_projection();
+        let cluster_grid = match self.cluster_grid.replace(None) {
+            Some((cached, cluster_grid)) if cached == projection => cluster_grid,
+            _ => ClusterGrid::new(&projection),
+        };
+        let (clusters, light_indices) =
+            cluster_grid.assign(&camera.get_view(), point_lights.as_slice(), spot_lights.as_slice());
+        let (depth_scale, depth_bias) = cluster_grid.depth_scale_bias();
+        *self.cluster_grid.borrow_mut() = Some((projection, cluster_grid));
+
         let uniform = Uniform {
             v: camera.get_view(),
-            p: camera.get_projection(),
+            p: projection,
             world_camera_position: [
                 camera.get_position().x,
                 camera.get_position().y,
                 camera.get_position().z,
                 1.0,
             ],
-            material_specular: [0.1, 0.1, 0.1, 1.0],
-            material_shininess: 16.0,
             nr_of_directional_lights: directional_lights.len() as u32,
             nr_of_spot_lights: spot_lights.len() as u32,
             nr_of_point_lights: point_lights.len() as u32,
+            p0: 0,
+            cluster_dimensions: [CLUSTER_X as u32, CLUSTER_Y as u32, CLUSTER_Z as u32, 0],
+            cluster_depth: [
+                depth_scale,
+                depth_bias,
+                renderer.swap_chain_descriptor.width as f32,
+                renderer.swap_chain_descriptor.height as f32,
+            ],
         };
         renderer
             .queue
@@ -232,5 +574,39 @@ impl BindGroup {
         renderer
             .queue
             .write_buffer(&self.point_lights, 0, bytemuck::cast_slice(point_lights.as_slice()));
+        renderer
+            .queue
+            .write_buffer(&self.clusters, 0, bytemuck::cast_slice(clusters.as_slice()));
+        renderer
+            .queue
+            .write_buffer(&self.light_indices, 0, bytemuck::cast_slice(light_indices.as_slice()));
+        renderer.queue.write_buffer(
+            &self.shadows,
+            0,
+            bytemuck::cast_slice(&[ShadowUniform::new(lights, camera)]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        material::Material,
+        registry::Registry,
+        renderer::bindgroup::{material_properties, MaterialProperties},
+    };
+
+    #[test]
+    fn default_material_comes_first() {
+        let mut materials = Registry::new();
+        let metal = materials.add(Material::new([0.5, 0.5, 0.6, 1.0]).with_specular([0.9, 0.9, 0.9, 1.0], 64.0));
+        let lamp = materials.add(Material::default().with_emissive([1.0, 0.8, 0.2, 1.0]));
+        let (properties, indices) = material_properties(&materials);
+        assert_eq!(properties.len(), 3);
+        assert_eq!(properties[0], MaterialProperties::from(&Material::default()));
+        let metal = properties[indices[&metal.id] as usize];
+        assert_eq!(metal.shininess, 64.0);
+        assert_eq!(metal.albedo, [0.5, 0.5, 0.6, 1.0]);
+        assert_eq!(properties[indices[&lamp.id] as usize].emissive, [1.0, 0.8, 0.2, 1.0]);
     }
 }
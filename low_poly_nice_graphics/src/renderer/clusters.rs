@@ -0,0 +1,254 @@
+use crate::renderer::{shadow_map::near_far, Aabb, PointProperties, SpotProperties};
+use glam::{Mat4, Vec3, Vec4};
+
+// the view frustum is divided in CLUSTER_X x CLUSTER_Y screen tiles and CLUSTER_Z exponential depth
+// slices, the fragment shader only loops over the lights binned into its cluster
+pub const CLUSTER_X: usize = 16;
+pub const CLUSTER_Y: usize = 9;
+pub const CLUSTER_Z: usize = 24;
+pub const NR_OF_CLUSTERS: usize = CLUSTER_X * CLUSTER_Y * CLUSTER_Z;
+// on average 32 lights per cluster, lights that do not fit anymore are left out of a cluster
+pub const MAX_NR_OF_LIGHT_INDICES: usize = NR_OF_CLUSTERS * 32;
+// below this fraction of its intensity a light does not visibly contribute anymore
+const LIGHT_CUT_OFF: f32 = 1.0 / 256.0;
+
+// Index range of one cluster in the light index list: first the point lights then the spot lights.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Cluster {
+    pub offset: u32,
+    pub nr_of_point_lights: u32,
+    pub nr_of_spot_lights: u32,
+    pub p0: u32,
+}
+
+unsafe impl bytemuck::Pod for Cluster {}
+unsafe impl bytemuck::Zeroable for Cluster {}
+
+pub struct ClusterGrid {
+    near: f32,
+    far: f32,
+    // view space bounds of every cluster, x fastest, then y (from the top of the screen), then z
+    bounds: Vec<Aabb>,
+}
+
+impl ClusterGrid {
+    pub fn new(projection: &Mat4) -> Self {
+        let (near, far) = near_far(projection);
+        let inverse = projection.inverse();
+        // point at view distance depth on the ray through the ndc coordinates
+        let at_depth = |x: f32, y: f32, depth: f32| {
+            let p = inverse * Vec4::new(x, y, 1.0, 1.0);
+            let p = Vec3::new(p.x, p.y, p.z) / p.w;
+            p * (depth / -p.z)
+        };
+        let mut bounds = Vec::with_capacity(NR_OF_CLUSTERS);
+        for z in 0..CLUSTER_Z {
+            let from = slice_depth(near, far, z);
+            let to = slice_depth(near, far, z + 1);
+            for y in 0..CLUSTER_Y {
+                let top = 1.0 - 2.0 * y as f32 / CLUSTER_Y as f32;
+                let bottom = 1.0 - 2.0 * (y + 1) as f32 / CLUSTER_Y as f32;
+                for x in 0..CLUSTER_X {
+                    let left = -1.0 + 2.0 * x as f32 / CLUSTER_X as f32;
+                    let right = -1.0 + 2.0 * (x + 1) as f32 / CLUSTER_X as f32;
+                    let mut corners = Vec::with_capacity(8);
+                    for depth in &[from, to] {
+                        for (x, y) in &[(left, top), (right, top), (left, bottom), (right, bottom)] {
+                            corners.push(at_depth(*x, *y, *depth));
+                        }
+                    }
+                    bounds.push(Aabb::from_points(corners));
+                }
+            }
+        }
+        Self { near, far, bounds }
+    }
+
+    // scale and bias so the shader finds the slice with floor(log(depth) * scale - bias)
+    pub fn depth_scale_bias(&self) -> (f32, f32) {
+        let scale = CLUSTER_Z as f32 / (self.far / self.near).ln();
+        (scale, self.near.ln() * scale)
+    }
+
+    pub fn slice(&self, depth: f32) -> usize {
+        let (scale, bias) = self.depth_scale_bias();
+        ((depth.max(self.near).ln() * scale - bias).floor().max(0.0) as usize).min(CLUSTER_Z - 1)
+    }
+
+    // Bins the lights into the clusters they reach, indices refer to the positions in the given slices.
+    pub fn assign(
+        &self,
+        view: &Mat4,
+        point_lights: &[PointProperties],
+        spot_lights: &[SpotProperties],
+    ) -> (Vec<Cluster>, Vec<u32>) {
+        let mut point_indices = vec![Vec::new(); NR_OF_CLUSTERS];
+        let mut spot_indices = vec![Vec::new(); NR_OF_CLUSTERS];
+        for (i, point) in point_lights.iter().enumerate() {
+            let range = light_range(point.constant, point.linear, point.quadratic, &point.diffuse);
+            let center = view.transform_point3(Vec3::new(point.position[0], point.position[1], point.position[2]));
+            self.bin(center, range, i as u32, &mut point_indices);
+        }
+        for (i, spot) in spot_lights.iter().enumerate() {
+            let range = light_range(spot.constant, spot.linear, spot.quadratic, &spot.diffuse);
+            let (center, radius) = cone_bounds(spot, range);
+            self.bin(view.transform_point3(center), radius, i as u32, &mut spot_indices);
+        }
+
+        let mut clusters = Vec::with_capacity(NR_OF_CLUSTERS);
+        let mut indices = Vec::new();
+        for (points, spots) in point_indices.iter().zip(spot_indices.iter()) {
+            let available = MAX_NR_OF_LIGHT_INDICES - indices.len();
+            let nr_of_point_lights = points.len().min(available);
+            let nr_of_spot_lights = spots.len().min(available - nr_of_point_lights);
+            clusters.push(Cluster {
+                offset: indices.len() as u32,
+                nr_of_point_lights: nr_of_point_lights as u32,
+                nr_of_spot_lights: nr_of_spot_lights as u32,
+                p0: 0,
+            });
+            indices.extend_from_slice(&points[..nr_of_point_lights]);
+            indices.extend_from_slice(&spots[..nr_of_spot_lights]);
+        }
+        (clusters, indices)
+    }
+
+    // center is in view space, only the depth slices the sphere overlaps are tested
+    fn bin(&self, center: Vec3, radius: f32, index: u32, cluster_indices: &mut [Vec<u32>]) {
+        let depth = -center.z;
+        if depth + radius < self.near || depth - radius > self.far {
+            return;
+        }
+        let tiles = CLUSTER_X * CLUSTER_Y;
+        let from = self.slice(depth - radius) * tiles;
+        let to = (self.slice(depth + radius) + 1) * tiles;
+        for (bounds, indices) in self.bounds[from..to].iter().zip(cluster_indices[from..to].iter_mut()) {
+            if bounds.intersects_sphere(center, radius) {
+                indices.push(index);
+            }
+        }
+    }
+}
+
+// start of depth slice z, slices get longer further away so clusters stay roughly cube shaped
+fn slice_depth(near: f32, far: f32, z: usize) -> f32 {
+    near * (far / near).powf(z as f32 / CLUSTER_Z as f32)
+}
+
+// distance at which the attenuated diffuse light drops below LIGHT_CUT_OFF
+pub fn light_range(constant: f32, linear: f32, quadratic: f32, diffuse: &[f32; 4]) -> f32 {
+    let intensity = diffuse[0].max(diffuse[1]).max(diffuse[2]);
+    // solve quadratic * d^2 + linear * d + constant = intensity / LIGHT_CUT_OFF
+    let c = constant - intensity / LIGHT_CUT_OFF;
+    if c >= 0.0 {
+        0.0
+    } else if quadratic > 0.0 {
+        (-linear + (linear * linear - 4.0 * quadratic * c).sqrt()) / (2.0 * quadratic)
+    } else if linear > 0.0 {
+        -c / linear
+    } else {
+        f32::INFINITY
+    }
+}
+
+// smallest sphere around the cone of a spot light with the given range, in world space
+pub fn cone_bounds(spot: &SpotProperties, range: f32) -> (Vec3, f32) {
+    let position = Vec3::new(spot.position[0], spot.position[1], spot.position[2]);
+    let direction = Vec3::new(spot.direction[0], spot.direction[1], spot.direction[2]).normalize();
+    let cos = spot.cut_off_outer;
+    if cos <= std::f32::consts::FRAC_1_SQRT_2 {
+        // wider than 90 degrees, the sphere through the rim of the cap is the smallest
+        let sin = (1.0 - cos * cos).sqrt();
+        (position + direction * range * cos, range * sin)
+    } else {
+        // narrow cone, the apex and the rim lie on the sphere
+        let radius = range / (2.0 * cos);
+        (position + direction * radius, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{
+        clusters::{cone_bounds, light_range, ClusterGrid, CLUSTER_X, CLUSTER_Y, CLUSTER_Z, NR_OF_CLUSTERS},
+        PointProperties, SpotProperties,
+    };
+    use glam::{Mat4, Vec3};
+
+    fn grid() -> ClusterGrid {
+        ClusterGrid::new(&Mat4::perspective_rh(
+            45.0 * std::f32::consts::PI * 2.0 / 360.0,
+            16.0 / 9.0,
+            0.1,
+            1000.0,
+        ))
+    }
+
+    #[test]
+    fn slices_follow_depth() {
+        let grid = grid();
+        assert_eq!(grid.slice(0.1), 0);
+        assert_eq!(grid.slice(0.01), 0);
+        assert_eq!(grid.slice(999.0), CLUSTER_Z - 1);
+        assert_eq!(grid.slice(5000.0), CLUSTER_Z - 1);
+        assert!(grid.slice(1.0) < grid.slice(10.0));
+        // the slice of a depth has bounds around that depth
+        let tiles = CLUSTER_X * CLUSTER_Y;
+        for depth in &[0.5, 7.0, 42.0, 300.0] {
+            let bounds = grid.bounds[grid.slice(*depth) * tiles];
+            assert!(-bounds.max.z <= depth * 1.001 && -bounds.min.z >= depth * 0.999);
+        }
+    }
+
+    #[test]
+    fn range_of_default_light() {
+        let point = PointProperties::new([0.0, 0.0, 0.0, 1.0]);
+        let range = light_range(point.constant, point.linear, point.quadratic, &point.diffuse);
+        let attenuation = 1.0 / (point.constant + point.linear * range + point.quadratic * range * range);
+        assert!((attenuation * 0.8 - 1.0 / 256.0).abs() < 1e-5);
+        // too dim to ever be visible, or never attenuated at all
+        assert_eq!(light_range(1.0, 0.0, 0.0, &[0.001, 0.001, 0.001, 1.0]), 0.0);
+        assert_eq!(light_range(1.0, 0.0, 0.0, &point.diffuse), f32::INFINITY);
+    }
+
+    #[test]
+    fn cone_is_inside_its_bounds() {
+        let spot = SpotProperties::new([1.0, 4.0, 0.0, 1.0], [0.0, -1.0, 0.0, 1.0]);
+        let (center, radius) = cone_bounds(&spot, 10.0);
+        let apex = Vec3::new(1.0, 4.0, 0.0);
+        let sin = (1.0 - spot.cut_off_outer * spot.cut_off_outer).sqrt();
+        let rim = apex + Vec3::new(sin * 10.0, -spot.cut_off_outer * 10.0, 0.0);
+        for p in &[apex, apex + Vec3::new(0.0, -10.0, 0.0), rim] {
+            assert!((*p - center).length() <= radius + 1e-3);
+        }
+        assert!(radius < 10.0);
+    }
+
+    #[test]
+    fn lights_only_reach_nearby_clusters() {
+        let grid = grid();
+        let view = Mat4::look_at_rh(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let near_light = PointProperties::new([0.0, 0.0, -20.0, 1.0]);
+        let behind_camera = PointProperties::new([0.0, 0.0, 200.0, 1.0]);
+        let (clusters, indices) = grid.assign(&view, &[near_light, behind_camera], &[]);
+        assert_eq!(clusters.len(), NR_OF_CLUSTERS);
+        // the cluster in the middle of the screen at the depth of the light contains it
+        let tiles = CLUSTER_X * CLUSTER_Y;
+        let center = grid.slice(20.0) * tiles + CLUSTER_Y / 2 * CLUSTER_X + CLUSTER_X / 2;
+        assert_eq!(clusters[center].nr_of_point_lights, 1);
+        assert_eq!(indices[clusters[center].offset as usize], 0);
+        // far away clusters do not, and the light behind the camera is nowhere
+        assert_eq!(clusters[(CLUSTER_Z - 1) * tiles].nr_of_point_lights, 0);
+        assert!(indices.iter().all(|i| *i == 0));
+        assert!(clusters.iter().all(|c| c.nr_of_spot_lights == 0));
+        // offsets are consecutive
+        for pair in clusters.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].nr_of_point_lights, pair[1].offset);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use glam::{Mat4, Vec3, Vec4};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Self {
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for p in points {
+            min = min.min(p);
+            max = max.max(p);
+        }
+        Self { min, max }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // box around the transformed corners, so it stays conservative under rotation
+    pub fn transform(&self, m: &Mat4) -> Self {
+        Self::from_points(self.corners().iter().map(|c| m.transform_point3(*c)))
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        let closest = center.max(self.min).min(self.max);
+        (closest - center).length_squared() <= radius * radius
+    }
+
+    // Part of the screen height the sphere around the box covers, 1.0 or more fills the screen.
+    pub fn screen_size(&self, camera_position: Vec3, projection: &Mat4) -> f32 {
+        let center = (self.min + self.max) / 2.0;
+        let radius = (self.max - self.min).length() / 2.0;
+        let distance = (center - camera_position).length();
+        if distance <= radius {
+            return f32::INFINITY;
+        }
+        // y_axis.y is 1 / tan(fov / 2), the half height of the screen at distance 1
+        radius * projection.y_axis.y / distance
+    }
+}
+
+// Planes point inwards, a point is inside when plane.xyz . p + plane.w >= 0 for all of them
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Gribb & Hartmann plane extraction from projection * view, glam's perspective_rh uses a 0..1
+    // depth range so the near plane is row 2 on its own
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let cols = m.to_cols_array_2d();
+        let row = |i: usize| Vec4::new(cols[0][i], cols[1][i], cols[2][i], cols[3][i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let normalize = |p: Vec4| p / Vec3::new(p.x, p.y, p.z).length();
+        Self {
+            planes: [
+                normalize(r3 + r0),
+                normalize(r3 - r0),
+                normalize(r3 + r1),
+                normalize(r3 - r1),
+                normalize(r2),
+                normalize(r3 - r2),
+            ],
+        }
+    }
+
+    fn distance(plane: &Vec4, p: Vec3) -> f32 {
+        plane.x * p.x + plane.y * p.y + plane.z * p.z + plane.w
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        self.planes.iter().all(|plane| Self::distance(plane, p) >= 0.0)
+    }
+
+    // only rejects a box if it lies completely outside one of the planes
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let p = Vec3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            Self::distance(plane, p) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{Aabb, Frustum};
+    use glam::{Mat4, Vec3};
+
+    // same projection as the cameras, placed at (0, 10, 20) looking at the origin
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh(45.0 * std::f32::consts::PI * 2.0 / 360.0, 1.0, 0.1, 1000.0);
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 10.0, 20.0), Vec3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    #[test]
+    fn frustum_points() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vec3::zero()));
+        assert!(frustum.contains_point(Vec3::new(2.0, 1.0, -10.0)));
+        // behind the camera, next to it and beyond the far plane
+        assert!(!frustum.contains_point(Vec3::new(0.0, 12.0, 30.0)));
+        assert!(!frustum.contains_point(Vec3::new(20.0, 0.0, 0.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, -440.0, -880.0)));
+    }
+
+    #[test]
+    fn frustum_boxes() {
+        let frustum = frustum();
+        let unit = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+        assert!(frustum.intersects(&unit));
+        assert!(!frustum.intersects(&unit.transform(&Mat4::from_translation(Vec3::new(20.0, 0.0, 0.0)))));
+        // center is outside but a corner reaches into the view
+        assert!(frustum.intersects(&Aabb::new(Vec3::new(-30.0, -1.0, -1.0), Vec3::new(-7.0, 1.0, 1.0))));
+        let moved = unit.transform(&Mat4::from_translation(Vec3::new(0.0, 0.0, 40.0)));
+        assert!(!frustum.intersects(&moved));
+        assert!((moved.min - Vec3::new(-0.5, -0.5, 39.5)).length() < 1e-6);
+    }
+
+    #[test]
+    fn screen_size_halves_with_twice_the_distance() {
+        let projection = Mat4::perspective_rh(45.0 * std::f32::consts::PI * 2.0 / 360.0, 1.0, 0.1, 1000.0);
+        let unit = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+        let near = unit.screen_size(Vec3::new(0.0, 0.0, 10.0), &projection);
+        let far = unit.screen_size(Vec3::new(0.0, 0.0, 20.0), &projection);
+        assert!((near - 2.0 * far).abs() < 1e-6);
+        assert_eq!(unit.screen_size(Vec3::zero(), &projection), f32::INFINITY);
+    }
+}
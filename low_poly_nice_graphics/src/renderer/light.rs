@@ -1,6 +1,8 @@
-pub const MAX_NR_OF_DIRECTIONAL_LIGHTS: usize = 1;
-pub const MAX_NR_OF_SPOT_LIGHTS: usize = 10;
-pub const MAX_NR_OF_POINT_LIGHTS: usize = 10;
+// sizes of the light storage buffers, point and spot lights are binned into clusters so the
+// number of lights only costs where they actually shine
+pub const MAX_NR_OF_DIRECTIONAL_LIGHTS: usize = 4;
+pub const MAX_NR_OF_SPOT_LIGHTS: usize = 256;
+pub const MAX_NR_OF_POINT_LIGHTS: usize = 1024;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -9,6 +11,10 @@ pub struct DirectionalProperties {
     pub ambient: [f32; 4],
     pub diffuse: [f32; 4],
     pub specular: [f32; 4],
+    pub casts_shadows: u32,
+    pub p0: f32,
+    pub p1: f32,
+    pub p2: f32,
 }
 
 impl DirectionalProperties {
@@ -18,8 +24,17 @@ impl DirectionalProperties {
             ambient: [0.05, 0.05, 0.05, 1.0],
             diffuse: [0.4, 0.4, 0.4, 1.0],
             specular: [0.1, 0.1, 0.1, 1.0],
+            casts_shadows: 0,
+            p0: 0.0,
+            p1: 0.0,
+            p2: 0.0,
         }
     }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.casts_shadows = 1;
+        self
+    }
 }
 
 #[repr(C)]
@@ -35,8 +50,9 @@ pub struct SpotProperties {
     pub quadratic: f32,
     pub cut_off_inner: f32,
     pub cut_off_outer: f32,
-    pub p0: f32,
-    pub p1: f32,
+    pub casts_shadows: u32,
+    // layer in the spot light shadow maps, assigned by the renderer
+    pub shadow_index: u32,
     pub p2: f32,
 }
 
@@ -53,11 +69,16 @@ impl SpotProperties {
             quadratic: 0.032,
             cut_off_inner: (12.5 * (std::f32::consts::PI / 180.0)).cos(),
             cut_off_outer: (15.0 * (std::f32::consts::PI / 180.0)).cos(),
-            p0: 0.0,
-            p1: 0.0,
+            casts_shadows: 0,
+            shadow_index: 0,
             p2: 0.0,
         }
     }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.casts_shadows = 1;
+        self
+    }
 }
 
 #[repr(C)]
@@ -86,6 +107,20 @@ impl PointProperties {
             p0: 0.0,
         }
     }
+
+    // diffuse and specular color, the ambient part stays as it is
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.diffuse = [color[0], color[1], color[2], 1.0];
+        self.specular = self.diffuse;
+        self
+    }
+
+    pub fn with_attenuation(mut self, constant: f32, linear: f32, quadratic: f32) -> Self {
+        self.constant = constant;
+        self.linear = linear;
+        self.quadratic = quadratic;
+        self
+    }
 }
 
 pub enum Light {
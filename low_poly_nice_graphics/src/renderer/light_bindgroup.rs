@@ -42,8 +42,7 @@ impl LightBindGroup {
         let instances = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
-            size: (std::mem::size_of::<Instance>() * MAX_NR_OF_SPOT_LIGHTS * MAX_NR_OF_POINT_LIGHTS)
-                as u64,
+            size: (std::mem::size_of::<Instance>() * (MAX_NR_OF_SPOT_LIGHTS + MAX_NR_OF_POINT_LIGHTS)) as u64,
             mapped_at_creation: false,
         });
 
@@ -1,5 +1,7 @@
 mod bindgroup;
 mod camera;
+mod clusters;
+mod culling;
 mod depth_texture;
 mod error;
 mod light;
@@ -7,12 +9,25 @@ mod light_bindgroup;
 mod light_pipeline;
 mod pipeline;
 mod renderer;
+mod shadow_bindgroup;
+mod shadow_map;
+mod shadow_pipeline;
+mod ssao;
+mod ssao_bindgroup;
+mod ssao_pipeline;
+mod texture_buffer;
 mod vertex_buffer;
 
 pub use bindgroup::{BindGroup, Instance};
 pub use camera::Camera;
-pub use light::{DirectionalProperties, Light, PointProperties, SpotProperties};
+pub use culling::{Aabb, Frustum};
+pub use light::{DirectionalProperties, Light, PointProperties, SpotProperties, MAX_NR_OF_POINT_LIGHTS};
 pub use light_bindgroup::LightBindGroup;
 pub use light_pipeline::LightPipeline;
 pub use pipeline::Pipeline;
 pub use renderer::Renderer;
+pub use shadow_bindgroup::ShadowBindGroup;
+pub use shadow_map::ShadowMap;
+pub use shadow_pipeline::ShadowPipeline;
+pub use ssao_pipeline::SsaoPipeline;
+pub use texture_buffer::TextureBuffer;
@@ -3,14 +3,18 @@ use crate::{
     mesh::{Mesh, Vertex},
     registry::{Handle, Registry},
     renderer::{
-        bindgroup::Instance, depth_texture::DepthTexture, error::RendererError, vertex_buffer::VertexBuffer, BindGroup,
-        Camera, Light, Renderer,
+        bindgroup::Instance, depth_texture::DepthTexture, error::RendererError, shadow_map::with_shadow_constants,
+        vertex_buffer::VertexBuffer, BindGroup, Camera, Frustum, Light, Renderer, SsaoPipeline,
     },
 };
-use std::io::Read;
+use std::{cmp::Ordering, collections::HashMap, io::Read};
 
 pub struct Pipeline {
     render_pipeline: wgpu::RenderPipeline,
+    // blends the meshes with transparent vertices over the opaque ones
+    transparent_pipeline: wgpu::RenderPipeline,
+    // screen space ambient occlusion is off by default
+    pub ssao: Option<SsaoPipeline>,
 }
 
 impl Pipeline {
@@ -22,7 +26,10 @@ impl Pipeline {
             }
             Err(ref e) => return Err(RendererError::from(e.clone())),
         }
-        match glsl_to_spirv::compile(include_str!("shaders/shader.frag"), glsl_to_spirv::ShaderType::Fragment) {
+        match glsl_to_spirv::compile(
+            &with_shadow_constants(include_str!("shaders/shader.frag")),
+            glsl_to_spirv::ShaderType::Fragment,
+        ) {
             Ok(mut spirv_vs_output) => {
                 spirv_vs_output.read_to_end(&mut spirv_fs_bytes).unwrap();
             }
@@ -42,50 +49,76 @@ impl Pipeline {
         });
         let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group.bind_group_layout],
+            bind_group_layouts: &[
+                &bind_group.bind_group_layout,
+                &bind_group.ssao.occlusion_bind_group_layout,
+                &bind_group.material_bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vs_module,
-                entry_point: "main",
-                buffers: &[Vertex::desc()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: DepthTexture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState {
-                    front: wgpu::StencilFaceState::IGNORE,
-                    back: wgpu::StencilFaceState::IGNORE,
-                    read_mask: 0,
-                    write_mask: 0,
+        // the transparent pass keeps the depth of the opaque meshes and blends over them
+        let create_pipeline = |depth_write_enabled: bool, target: wgpu::ColorTargetState| {
+            renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &vs_module,
+                    entry_point: "main",
+                    buffers: &[Vertex::desc()],
                 },
-                bias: wgpu::DepthBiasState {
-                    constant: 0,
-                    slope_scale: 0.0,
-                    clamp: 0.0,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: wgpu::CullMode::Back,
+                    polygon_mode: wgpu::PolygonMode::Fill,
                 },
-                clamp_depth: false,
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
-                targets: &[renderer.swap_chain_descriptor.format.into()],
-            }),
-        });
-        Ok(Self { render_pipeline })
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DepthTexture::DEPTH_FORMAT,
+                    depth_write_enabled,
+                    // equal passes the depth written by the ssao prepass
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilState {
+                        front: wgpu::StencilFaceState::IGNORE,
+                        back: wgpu::StencilFaceState::IGNORE,
+                        read_mask: 0,
+                        write_mask: 0,
+                    },
+                    bias: wgpu::DepthBiasState {
+                        constant: 0,
+                        slope_scale: 0.0,
+                        clamp: 0.0,
+                    },
+                    clamp_depth: false,
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &fs_module,
+                    entry_point: "main",
+                    targets: &[target],
+                }),
+            })
+        };
+        let render_pipeline = create_pipeline(true, renderer.swap_chain_descriptor.format.into());
+        let transparent_pipeline = create_pipeline(
+            false,
+            wgpu::ColorTargetState {
+                format: renderer.swap_chain_descriptor.format,
+                alpha_blend: wgpu::BlendState::REPLACE,
+                color_blend: wgpu::BlendState {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            },
+        );
+        Ok(Self {
+            render_pipeline,
+            transparent_pipeline,
+            ssao: None,
+        })
     }
 
     pub fn render(
@@ -99,39 +132,108 @@ impl Pipeline {
         target: &wgpu::TextureView,
     ) {
         bindgroup.update_uniforms(&renderer, &lights, camera);
-        let mut instance_map = Vec::new();
-        let mut start_range = 0;
-        let mut transforms = Vec::new();
+        let projection = camera.get_projection();
+        let frustum = Frustum::from_matrix(&(projection * camera.get_view()));
+        // the buffers of removed meshes, like those of chunks out of view and their levels of detail
+        renderer.vertex_buffers.retain(|id, _| meshes.registry.contains_key(id));
         for (id, mesh) in &mut meshes.registry {
             if mesh.just_loaded {
                 renderer
                     .vertex_buffers
                     .insert(*id, VertexBuffer::from_mesh(&renderer, mesh));
                 mesh.just_loaded = false;
+                mesh.vertices_changed = false;
+            } else if mesh.vertices_changed {
+                if let Some(vertex_buffer) = renderer.vertex_buffers.get_mut(id) {
+                    vertex_buffer.update_vertices(&renderer.queue, mesh);
+                }
+                mesh.vertices_changed = false;
             }
-            transforms.extend_from_slice(
-                entities
-                    .registry
-                    .iter()
-                    .filter_map(|(_, v)| {
-                        if v.mesh_handle.id == *id {
-                            Some(Instance {
-                                m: v.transform.to_matrix(),
-                            })
-                        } else {
-                            None
+        }
+        // every entity draws the level of detail that fits how big it is on screen
+        let camera_position = camera.get_position();
+        let drawn_meshes = entities
+            .registry
+            .iter()
+            .filter_map(|(entity_id, entity)| {
+                let mesh = meshes.registry.get(&entity.mesh_handle.id)?;
+                let bounds = renderer.vertex_buffers.get(&entity.mesh_handle.id)?.bounds;
+                let screen_size = bounds
+                    .transform(&entity.transform.to_matrix())
+                    .screen_size(camera_position, &projection);
+                let mesh_handle = mesh.lod(screen_size).unwrap_or(&entity.mesh_handle);
+                Some((*entity_id, mesh_handle.id))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut instance_map = Vec::new();
+        // (squared distance to the camera, mesh, instance) of the transparent meshes in view
+        let mut transparent_instances = Vec::new();
+        let mut start_range = 0;
+        let mut transforms = Vec::new();
+        for id in meshes.registry.keys() {
+            let vertex_buffer = renderer.vertex_buffers.get(id).unwrap();
+            let bounds = vertex_buffer.bounds;
+            let mut instances = entities
+                .registry
+                .iter()
+                .filter_map(|(entity_id, v)| {
+                    if drawn_meshes.get(entity_id) == Some(id) {
+                        let m = v.transform.to_matrix();
+                        if frustum.intersects(&bounds.transform(&m)) {
+                            return Some(Instance::new(m, bindgroup.material_index(&v.material_handle)));
                         }
-                    })
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            );
-            instance_map.push((Handle::<Mesh>::new(*id), start_range..transforms.len() as u32));
+                    }
+                    None
+                })
+                .collect::<Vec<_>>();
+            if vertex_buffer.transparent {
+                let center = (bounds.min + bounds.max) * 0.5;
+                for instance in instances {
+                    let distance = (instance.m.transform_point3(center) - camera_position).length_squared();
+                    transparent_instances.push((distance, *id, instance));
+                }
+                continue;
+            }
+            // one draw per material of this mesh
+            instances.sort_by_key(|instance| instance.material_index);
+            for (i, instance) in instances.iter().enumerate() {
+                transforms.push(*instance);
+                if instances.get(i + 1).map(|next| next.material_index) != Some(instance.material_index) {
+                    instance_map.push((
+                        Handle::<Mesh>::new(*id),
+                        instance.material_index,
+                        start_range..transforms.len() as u32,
+                    ));
+                    start_range = transforms.len() as u32;
+                }
+            }
+        }
+        // transparent meshes are blended back to front, one draw each so that their order is kept
+        transparent_instances.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        let mut transparent_map = Vec::new();
+        for (_, id, instance) in transparent_instances {
+            transforms.push(instance);
+            transparent_map.push((
+                Handle::<Mesh>::new(id),
+                instance.material_index,
+                start_range..transforms.len() as u32,
+            ));
             start_range = transforms.len() as u32;
         }
         bindgroup.update_instances(&renderer, transforms.as_slice());
         let mut encoder = renderer
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let depth_load = match &self.ssao {
+            Some(ssao) => {
+                ssao.render(&mut encoder, &instance_map, bindgroup, camera, &renderer);
+                wgpu::LoadOp::Load
+            }
+            None => {
+                bindgroup.ssao.clear(&mut encoder);
+                wgpu::LoadOp::Clear(1.0)
+            }
+        };
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -151,21 +253,29 @@ impl Pipeline {
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
                     attachment: &renderer.depth_texture.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: depth_load,
                         store: true,
                     }),
                     stencil_ops: None,
                 }),
             });
 
-            for (mesh_handle, instance_range) in instance_map {
-                if !instance_range.is_empty() {
-                    let mesh = renderer.vertex_buffers.get(&mesh_handle.id).unwrap();
-                    render_pass.set_pipeline(&self.render_pipeline);
-                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                    render_pass.set_bind_group(0, &bindgroup.bind_group, &[]);
-                    render_pass.draw_indexed(0..mesh.len, 0, instance_range);
+            let passes = vec![
+                (&self.render_pipeline, instance_map),
+                (&self.transparent_pipeline, transparent_map),
+            ];
+            for (render_pipeline, draws) in passes {
+                for (mesh_handle, material_index, instance_range) in draws {
+                    if !instance_range.is_empty() {
+                        let mesh = renderer.vertex_buffers.get(&mesh_handle.id).unwrap();
+                        render_pass.set_pipeline(render_pipeline);
+                        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                        render_pass.set_bind_group(0, &bindgroup.bind_group, &[]);
+                        render_pass.set_bind_group(1, &bindgroup.ssao.occlusion_bind_group, &[]);
+                        render_pass.set_bind_group(2, &bindgroup.material_bind_groups[material_index as usize], &[]);
+                        render_pass.draw_indexed(0..mesh.len, 0, instance_range);
+                    }
                 }
             }
         }
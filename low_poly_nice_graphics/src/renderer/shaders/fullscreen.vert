@@ -0,0 +1,10 @@
+#version 450
+
+layout(location=0) out vec2 out_uv;
+
+// one triangle covering the whole screen, no vertex buffer needed
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    out_uv = uv;
+    gl_Position = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location=0) in vec3 in_world_position;
+layout(location=1) in vec3 in_world_normal;
+
+// xyz: view space normal, w: linear view depth, 0 where nothing is drawn
+layout(location=0) out vec4 out_normal_depth;
+
+layout(std140, set=0, binding=0)
+uniform Uniform {
+    mat4 view;
+    mat4 proj;
+    vec4 world_camera_position;
+    uint nr_of_directional_lights;
+    uint nr_of_spot_lights;
+    uint nr_of_point_lights;
+    uint p0;
+    uvec4 cluster_dimensions;
+    vec4 cluster_depth;
+};
+
+void main() {
+    vec3 view_normal = normalize(mat3(view) * normalize(in_world_normal));
+    float view_depth = -(view * vec4(in_world_position, 1.0)).z;
+    out_normal_depth = vec4(view_normal, view_depth);
+}
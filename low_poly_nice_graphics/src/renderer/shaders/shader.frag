@@ -3,6 +3,11 @@
 layout(location=0) in vec3 in_world_position;
 layout(location=1) in vec3 in_world_normal;
 layout(location=2) in vec3 in_color;
+layout(location=3) in float in_ambient_occlusion;
+layout(location=4) flat in uint in_material_index;
+layout(location=5) in vec2 in_uv;
+layout(location=6) in float in_alpha;
+layout(location=7) in float in_emission;
 
 layout(location=0) out vec4 out_color;
 
@@ -13,6 +18,8 @@ struct DirectionalLight
     vec4 ambient;
     vec4 diffuse;
     vec4 specular;
+    uint casts_shadows;
+    float p0, p1, p2;
 };
 
 struct SpotLight
@@ -27,6 +34,17 @@ struct SpotLight
     float quadratic;
     float cut_off_inner;
     float cut_off_outer;
+    uint casts_shadows;
+    uint shadow_index;
+    float p2;
+};
+
+struct Material
+{
+    vec4 albedo;
+    vec4 specular;
+    vec4 emissive;
+    float shininess;
     float p0, p1, p2;
 };
 
@@ -47,36 +65,101 @@ uniform Uniform {
     mat4 view;
     mat4 proj;
     vec4 world_camera_position;
-    vec4 material_specular;
-    float material_shininess;
     uint nr_of_directional_lights;
     uint nr_of_spot_lights;
     uint nr_of_point_lights;
+    uint p0;
+    uvec4 cluster_dimensions;
+    // scale, bias, screen width, screen height
+    vec4 cluster_depth;
+};
+layout(std430, set=0, binding=1)
+readonly buffer DirectionalLightBlock {
+    DirectionalLight directional_lights[];
 };
-const uint MAX_NR_OF_DIRECTIONAL_LIGHTS = 1;
-layout(std140, set=0, binding=1)
-uniform DirectionalLightBlock {
-    DirectionalLight directional_lights[MAX_NR_OF_DIRECTIONAL_LIGHTS];
+
+layout(std430, set=0, binding=2)
+readonly buffer SpotLightBlock {
+    SpotLight spot_lights[];
 };
 
-const uint MAX_NR_OF_SPOT_LIGHTS = 10;
-layout(std140, set=0, binding=2)
-uniform SpotLightBlock {
-    SpotLight spot_lights[MAX_NR_OF_SPOT_LIGHTS];
+layout(std430, set=0, binding=3)
+readonly buffer PointLightBlock {
+    PointLight point_lights[];
 };
 
-const uint MAX_NR_OF_POINT_LIGHTS = 10;
-layout(std140, set=0, binding=3)
-uniform PointLightBlock {
-    PointLight point_lights[MAX_NR_OF_POINT_LIGHTS];
+// layers [0..NR_OF_CASCADES) of shadow_maps are the directional cascades, then one per shadow_index. The two
+// constants are added by with_shadow_constants from the ones of shadow_map.rs.
+layout(std140, set=0, binding=5)
+uniform ShadowBlock {
+    mat4 directional_light_spaces[NR_OF_CASCADES];
+    mat4 spot_light_spaces[MAX_NR_OF_SPOT_SHADOWS];
+    vec4 cascade_splits;
 };
+layout(set=0, binding=6) uniform texture2DArray shadow_maps;
+layout(set=0, binding=7) uniform samplerShadow shadow_sampler;
 
-layout(std140, set=0, binding=4)
-buffer Instance {
-    mat4 models[];
+// per cluster: offset into light_indices, nr of point lights, nr of spot lights (following the point lights)
+layout(std430, set=0, binding=8)
+readonly buffer ClusterBlock {
+    uvec4 clusters[];
+};
+layout(std430, set=0, binding=9)
+readonly buffer LightIndexBlock {
+    uint light_indices[];
 };
 
-vec3 calculate_directional_light(uint i, vec3 normal, vec3 view_direction)
+// index 0 is the default material
+layout(std430, set=0, binding=10)
+readonly buffer MaterialBlock {
+    Material materials[];
+};
+
+// material of the fragment, set at the start of main
+vec3 albedo;
+vec4 material_specular;
+float material_shininess;
+
+// blurred screen space ambient occlusion, 1.0 is not occluded
+layout(set=1, binding=0) uniform texture2D ambient_occlusion;
+layout(set=1, binding=1) uniform sampler ambient_occlusion_sampler;
+
+// texture of the material, white when it has none
+layout(set=2, binding=0) uniform texture2D albedo_texture;
+layout(set=2, binding=1) uniform sampler albedo_sampler;
+
+// 1.0 is fully lit, 0.0 is fully in shadow, 3x3 pcf on top of the linear filtered comparison
+float calculate_shadow(uint layer, mat4 light_space, vec3 frag_position)
+{
+    vec4 light_position = light_space * vec4(frag_position, 1.0);
+    vec3 ndc = light_position.xyz / light_position.w;
+    vec2 uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (ndc.z > 1.0 || uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        return 1.0;
+    }
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2DArrayShadow(shadow_maps, shadow_sampler), 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(x, y) * texel_size;
+            lit += texture(sampler2DArrayShadow(shadow_maps, shadow_sampler), vec4(uv + offset, float(layer), ndc.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+float calculate_directional_shadow(vec3 frag_position)
+{
+    float depth = -(view * vec4(frag_position, 1.0)).z;
+    for (uint cascade = 0; cascade < NR_OF_CASCADES; cascade++) {
+        if (depth < cascade_splits[cascade]) {
+            return calculate_shadow(cascade, directional_light_spaces[cascade], frag_position);
+        }
+    }
+    return 1.0;
+}
+
+vec3 calculate_directional_light(uint i, vec3 normal, vec3 view_direction, float occlusion)
 {
     // negate light direction -> we want direction towards light
     vec3 light_direction = normalize(-directional_lights[i].direction.xyz);
@@ -86,14 +169,18 @@ vec3 calculate_directional_light(uint i, vec3 normal, vec3 view_direction)
     vec3 halfway_direction = normalize(light_direction + view_direction);
     float spec = pow(max(dot(view_direction, halfway_direction), 0.0), material_shininess);
 
-    vec3 ambient  = directional_lights[i].ambient.xyz * in_color;
-    vec3 diffuse = directional_lights[i].diffuse.xyz * diff * in_color;
+    vec3 ambient  = directional_lights[i].ambient.xyz * albedo * occlusion;
+    vec3 diffuse = directional_lights[i].diffuse.xyz * diff * albedo;
     vec3 specular = directional_lights[i].specular.xyz * spec * material_specular.xyz;
+    float shadow = 1.0;
+    if (directional_lights[i].casts_shadows != 0) {
+        shadow = calculate_directional_shadow(in_world_position);
+    }
 
-    return ambient + diffuse + specular;
+    return ambient + shadow * (diffuse + specular);
 }
 
-vec3 calculate_spot_light(uint i, vec3 normal, vec3 frag_position, vec3 view_direction)
+vec3 calculate_spot_light(uint i, vec3 normal, vec3 frag_position, vec3 view_direction, float occlusion)
 {
     vec3 light_direction = normalize(spot_lights[i].position.xyz - frag_position);
 
@@ -109,13 +196,18 @@ vec3 calculate_spot_light(uint i, vec3 normal, vec3 frag_position, vec3 view_dir
     float theta = dot(light_direction, normalize(-spot_lights[i].direction.xyz));
     float epsilon = spot_lights[i].cut_off_inner - spot_lights[i].cut_off_outer;
     float intensity = clamp ((theta -spot_lights[i].cut_off_outer) / epsilon, 0.0, 1.0);
-    vec3 ambient = spot_lights[i].ambient.xyz * in_color * attenuation * intensity;
-    vec3 diffuse = spot_lights[i].diffuse.xyz * diff * in_color * attenuation * intensity;
+    vec3 ambient = spot_lights[i].ambient.xyz * albedo * attenuation * intensity * occlusion;
+    vec3 diffuse = spot_lights[i].diffuse.xyz * diff * albedo * attenuation * intensity;
     vec3 specular = spot_lights[i].specular.xyz * spec * material_specular.xyz * attenuation * intensity;
-    return ambient + diffuse + specular;
+    float shadow = 1.0;
+    if (spot_lights[i].casts_shadows != 0) {
+        uint shadow_index = spot_lights[i].shadow_index;
+        shadow = calculate_shadow(NR_OF_CASCADES + shadow_index, spot_light_spaces[shadow_index], frag_position);
+    }
+    return ambient + shadow * (diffuse + specular);
 }
 
-vec3 calculate_point_light(uint i, vec3 normal, vec3 frag_position, vec3 view_direction)
+vec3 calculate_point_light(uint i, vec3 normal, vec3 frag_position, vec3 view_direction, float occlusion)
 {
     vec3 light_direction = normalize(point_lights[i].position.xyz - frag_position);
     // diffuse
@@ -127,29 +219,51 @@ vec3 calculate_point_light(uint i, vec3 normal, vec3 frag_position, vec3 view_di
     float distance = distance(point_lights[i].position.xyz, frag_position);
     float attenuation = 1.0 / (point_lights[i].cons + point_lights[i].linear * distance + point_lights[i].quadratic * (distance * distance));
 
-    vec3 ambient = point_lights[i].ambient.xyz * in_color * attenuation;
-    vec3 diffuse = point_lights[i].diffuse.xyz * diff * in_color * attenuation;
+    vec3 ambient = point_lights[i].ambient.xyz * albedo * attenuation * occlusion;
+    vec3 diffuse = point_lights[i].diffuse.xyz * diff * albedo * attenuation;
     vec3 specular = point_lights[i].specular.xyz * spec * material_specular.xyz * attenuation;
     return ambient + diffuse + specular;
 }
 
+// same slicing as ClusterGrid, x from the left and y from the top of the screen
+uint cluster_index(vec3 frag_position)
+{
+    uvec2 tile = uvec2(gl_FragCoord.xy / cluster_depth.zw * vec2(cluster_dimensions.xy));
+    tile = min(tile, cluster_dimensions.xy - 1);
+    float depth = -(view * vec4(frag_position, 1.0)).z;
+    uint slice = uint(max(floor(log(depth) * cluster_depth.x - cluster_depth.y), 0.0));
+    slice = min(slice, cluster_dimensions.z - 1);
+    return tile.x + tile.y * cluster_dimensions.x + slice * cluster_dimensions.x * cluster_dimensions.y;
+}
+
 void main()
 {
     vec3 normal = normalize(in_world_normal);
     vec3 view_direction = normalize(world_camera_position.xyz - in_world_position);
+    float occlusion = texelFetch(sampler2D(ambient_occlusion, ambient_occlusion_sampler), ivec2(gl_FragCoord.xy), 0).r
+        * in_ambient_occlusion;
+
+    Material material = materials[in_material_index];
+    albedo = in_color * material.albedo.rgb * texture(sampler2D(albedo_texture, albedo_sampler), in_uv).rgb;
+    material_specular = material.specular;
+    material_shininess = material.shininess;
 
-    vec3 result = vec3(0.0, 0.0, 0.0);
+    // emissive voxels glow in their own color
+    vec3 result = material.emissive.rgb + albedo * in_emission;
 
     for(uint i = 0; i < nr_of_directional_lights; i++) {
-        result += calculate_directional_light(i, normal, view_direction);
+        result += calculate_directional_light(i, normal, view_direction, occlusion);
     }
-    for(uint i = 0; i < nr_of_spot_lights; i++) {
-        result += calculate_spot_light(i, normal, in_world_position, view_direction);
+    uvec4 cluster = clusters[cluster_index(in_world_position)];
+    for(uint i = 0; i < cluster.y; i++) {
+        result += calculate_point_light(light_indices[cluster.x + i], normal, in_world_position, view_direction, occlusion);
     }
-    for(uint i = 0; i < nr_of_point_lights; i++) {
-        result += calculate_point_light(i, normal, in_world_position, view_direction);
+    for(uint i = 0; i < cluster.z; i++) {
+        uint light = light_indices[cluster.x + cluster.y + i];
+        result += calculate_spot_light(light, normal, in_world_position, view_direction, occlusion);
     }
     // gamma correction
     float gamma = 2.2;
-    out_color = vec4(pow(result, vec3(1.0 / gamma)), 1.0);
+    // only the transparent pass blends, the opaque one ignores alpha
+    out_color = vec4(pow(result, vec3(1.0 / gamma)), in_alpha * material.albedo.a);
 }
\ No newline at end of file
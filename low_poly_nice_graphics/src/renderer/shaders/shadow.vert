@@ -0,0 +1,22 @@
+#version 450
+
+layout(location=0) in vec3 in_model_position;
+
+layout(set=0, binding=0)
+uniform LightSpace {
+    mat4 light_space;
+};
+
+struct Instance {
+    mat4 model;
+    uint material_index;
+};
+
+layout(std140, set=0, binding=1)
+buffer Instances {
+    Instance instances[];
+};
+
+void main() {
+    gl_Position = light_space * instances[gl_InstanceIndex].model * vec4(in_model_position, 1.0);
+}
@@ -0,0 +1,68 @@
+#version 450
+
+layout(location=0) in vec2 in_uv;
+
+layout(location=0) out float out_occlusion;
+
+const uint KERNEL_SIZE = 32;
+const uint NOISE_SIZE = 4;
+layout(std140, set=0, binding=0)
+uniform SsaoUniform {
+    mat4 projection;
+    mat4 inverse_projection;
+    vec4 kernel[KERNEL_SIZE];
+    vec4 noise[NOISE_SIZE * NOISE_SIZE];
+    float radius;
+    float bias;
+};
+layout(set=0, binding=1) uniform texture2D gbuffer;
+layout(set=0, binding=2) uniform sampler gbuffer_sampler;
+
+vec4 fetch(vec2 uv)
+{
+    ivec2 size = textureSize(sampler2D(gbuffer, gbuffer_sampler), 0);
+    ivec2 texel = clamp(ivec2(uv * vec2(size)), ivec2(0), size - 1);
+    return texelFetch(sampler2D(gbuffer, gbuffer_sampler), texel, 0);
+}
+
+// view space position on the ray through uv at the given linear depth
+vec3 view_position(vec2 uv, float depth)
+{
+    vec4 far_point = inverse_projection * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    vec3 ray = far_point.xyz / far_point.w;
+    return ray * (depth / -ray.z);
+}
+
+void main()
+{
+    vec4 normal_depth = fetch(in_uv);
+    if (normal_depth.w <= 0.0) {
+        out_occlusion = 1.0;
+        return;
+    }
+    vec3 position = view_position(in_uv, normal_depth.w);
+    vec3 normal = normalize(normal_depth.xyz);
+
+    // tangent space to view space with a per pixel random rotation around the normal
+    ivec2 noise_texel = ivec2(gl_FragCoord.xy) % int(NOISE_SIZE);
+    vec3 random = noise[noise_texel.y * int(NOISE_SIZE) + noise_texel.x].xyz;
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+    for (uint i = 0; i < KERNEL_SIZE; i++) {
+        vec3 sample_position = position + tbn * kernel[i].xyz * radius;
+        vec4 offset = projection * vec4(sample_position, 1.0);
+        offset.xy /= offset.w;
+        vec2 sample_uv = vec2(offset.x * 0.5 + 0.5, 0.5 - offset.y * 0.5);
+        float sample_depth = fetch(sample_uv).w;
+        if (sample_depth <= 0.0) {
+            continue;
+        }
+        // geometry far in front of the sample is not occluding, it is a different object
+        float range = smoothstep(0.0, 1.0, radius / abs(normal_depth.w - sample_depth));
+        occlusion += (-sample_depth >= sample_position.z + bias ? 1.0 : 0.0) * range;
+    }
+    out_occlusion = 1.0 - occlusion / float(KERNEL_SIZE);
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(location=0) out float out_occlusion;
+
+const int NOISE_SIZE = 4;
+layout(set=0, binding=0) uniform texture2D occlusion;
+layout(set=0, binding=1) uniform sampler occlusion_sampler;
+
+// box blur over the size of the noise pattern
+void main()
+{
+    ivec2 size = textureSize(sampler2D(occlusion, occlusion_sampler), 0);
+    ivec2 center = ivec2(gl_FragCoord.xy);
+    float result = 0.0;
+    for (int x = -NOISE_SIZE / 2; x < NOISE_SIZE / 2; x++) {
+        for (int y = -NOISE_SIZE / 2; y < NOISE_SIZE / 2; y++) {
+            ivec2 texel = clamp(center + ivec2(x, y), ivec2(0), size - 1);
+            result += texelFetch(sampler2D(occlusion, occlusion_sampler), texel, 0).r;
+        }
+    }
+    out_occlusion = result / float(NOISE_SIZE * NOISE_SIZE);
+}
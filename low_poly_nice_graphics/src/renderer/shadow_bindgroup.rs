@@ -0,0 +1,98 @@
+use crate::renderer::{bindgroup::Instance, shadow_map::NR_OF_SHADOW_MAPS, Renderer};
+use glam::Mat4;
+
+pub const MAX_NR_OF_SHADOW_INSTANCES: usize = 50000;
+// every light space matrix lives at its own dynamic offset
+pub const LIGHT_SPACE_STRIDE: u64 = wgpu::BIND_BUFFER_ALIGNMENT;
+
+pub struct ShadowBindGroup {
+    pub light_spaces: wgpu::Buffer,
+    pub instances: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ShadowBindGroup {
+    pub fn new(renderer: &Renderer) -> Self {
+        let light_spaces = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: LIGHT_SPACE_STRIDE * NR_OF_SHADOW_MAPS as u64,
+            mapped_at_creation: false,
+        });
+
+        let instances = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<Instance>() * MAX_NR_OF_SHADOW_INSTANCES) as u64,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                            has_dynamic_offset: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+
+        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &light_spaces,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                    },
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instances.as_entire_binding(),
+                },
+            ],
+        });
+        Self {
+            light_spaces,
+            instances,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn update_light_space(&self, renderer: &Renderer, layer: usize, light_space: &Mat4) {
+        renderer.queue.write_buffer(
+            &self.light_spaces,
+            layer as u64 * LIGHT_SPACE_STRIDE,
+            bytemuck::cast_slice(&light_space.to_cols_array()),
+        );
+    }
+
+    pub fn update_instances(&self, renderer: &Renderer, transforms: &[Instance]) {
+        renderer
+            .queue
+            .write_buffer(&self.instances, 0, bytemuck::cast_slice(transforms));
+    }
+}
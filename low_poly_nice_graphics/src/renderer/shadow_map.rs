@@ -0,0 +1,407 @@
+use crate::{
+    registry::Registry,
+    renderer::{Camera, DirectionalProperties, Light, Renderer, SpotProperties},
+};
+use glam::{Mat4, Vec3, Vec4};
+use std::num::NonZeroU32;
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const NR_OF_CASCADES: usize = 3;
+// shadow casting spot lights beyond this number are lit without shadows
+pub const MAX_NR_OF_SPOT_SHADOWS: usize = 10;
+// layers [0..NR_OF_CASCADES) belong to the directional light, after that one per shadow casting spot light
+pub const NR_OF_SHADOW_MAPS: usize = NR_OF_CASCADES + MAX_NR_OF_SPOT_SHADOWS;
+// cascades only cover the first part of the view frustum, further away nothing is shadowed
+const SHADOW_DISTANCE: f32 = 150.0;
+// how far behind a cascade occluders are still rendered into it
+const SHADOW_CASTER_DISTANCE: f32 = 100.0;
+// blend between logarithmic (1.0) and uniform (0.0) cascade splits
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+const SPOT_SHADOW_NEAR: f32 = 0.1;
+const SPOT_SHADOW_FAR: f32 = 100.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowUniform {
+    pub directional_light_spaces: [Mat4; NR_OF_CASCADES],
+    pub spot_light_spaces: [Mat4; MAX_NR_OF_SPOT_SHADOWS],
+    // view space distance where each cascade ends
+    pub cascade_splits: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for ShadowUniform {}
+unsafe impl bytemuck::Zeroable for ShadowUniform {}
+
+impl ShadowUniform {
+    pub fn new(lights: &Registry<Light>, camera: &dyn Camera) -> Self {
+        let projection = camera.get_projection();
+        let view = camera.get_view();
+        let (near, far) = near_far(&projection);
+        let splits = cascade_splits(near, far.min(SHADOW_DISTANCE));
+        let mut uniform = Self {
+            directional_light_spaces: [Mat4::identity(); NR_OF_CASCADES],
+            spot_light_spaces: [Mat4::identity(); MAX_NR_OF_SPOT_SHADOWS],
+            cascade_splits: [0.0; 4],
+        };
+        uniform.cascade_splits[..NR_OF_CASCADES].copy_from_slice(&splits);
+        if let Some(properties) = shadowing_directional_light(lights) {
+            let direction = Vec3::new(
+                properties.direction[0],
+                properties.direction[1],
+                properties.direction[2],
+            );
+            let mut from = near;
+            for (cascade, to) in splits.iter().enumerate() {
+                let corners = frustum_slice_corners(&projection, &view, from, *to);
+                uniform.directional_light_spaces[cascade] = directional_light_space(direction, &corners);
+                from = *to;
+            }
+        }
+        for spot in spot_lights_with_shadow_index(lights) {
+            if spot.casts_shadows != 0 {
+                uniform.spot_light_spaces[spot.shadow_index as usize] = spot_light_space(&spot);
+            }
+        }
+        uniform
+    }
+
+    // light space matrices of the layers that have to be rendered for the current lights
+    pub fn active_layers(&self, lights: &Registry<Light>) -> Vec<(usize, Mat4)> {
+        let mut layers = Vec::new();
+        if shadowing_directional_light(lights).is_some() {
+            layers.extend(self.directional_light_spaces.iter().cloned().enumerate());
+        }
+        for spot in spot_lights_with_shadow_index(lights) {
+            if spot.casts_shadows != 0 {
+                let index = spot.shadow_index as usize;
+                layers.push((NR_OF_CASCADES + index, self.spot_light_spaces[index]));
+            }
+        }
+        layers
+    }
+}
+
+// There is only one set of cascades, so only the first shadow casting directional light gets them.
+fn shadowing_directional_light(lights: &Registry<Light>) -> Option<&DirectionalProperties> {
+    lights.registry.values().find_map(|light| match light {
+        Light::Directional(properties) if properties.casts_shadows != 0 => Some(properties),
+        _ => None,
+    })
+}
+
+// The directional lights in upload order, the ones after the first shadow caster do not cast shadows.
+pub fn directional_lights_with_shadow(lights: &Registry<Light>) -> Vec<DirectionalProperties> {
+    let mut has_shadow = false;
+    let mut directional_lights = Vec::new();
+    for light in lights.registry.values() {
+        if let Light::Directional(properties) = light {
+            let mut properties = *properties;
+            if properties.casts_shadows != 0 {
+                if has_shadow {
+                    properties.casts_shadows = 0;
+                }
+                has_shadow = true;
+            }
+            directional_lights.push(properties);
+        }
+    }
+    directional_lights
+}
+
+// The spot lights in upload order. The first MAX_NR_OF_SPOT_SHADOWS shadow casters get a shadow
+// index, the ones after that do not cast shadows this frame.
+pub fn spot_lights_with_shadow_index(lights: &Registry<Light>) -> Vec<SpotProperties> {
+    let mut shadow_index = 0;
+    let mut spot_lights = Vec::new();
+    for light in lights.registry.values() {
+        if let Light::Spot(properties) = light {
+            let mut properties = *properties;
+            if properties.casts_shadows != 0 {
+                if shadow_index < MAX_NR_OF_SPOT_SHADOWS {
+                    properties.shadow_index = shadow_index as u32;
+                    shadow_index += 1;
+                } else {
+                    properties.casts_shadows = 0;
+                }
+            }
+            spot_lights.push(properties);
+        }
+    }
+    spot_lights
+}
+
+// The shader source with the shadow constants it shares with ShadowUniform declared below its #version line.
+pub fn with_shadow_constants(source: &str) -> String {
+    let (version, rest) = source.split_at(source.find('\n').map_or(0, |end| end + 1));
+    format!(
+        "{}const uint NR_OF_CASCADES = {};\nconst uint MAX_NR_OF_SPOT_SHADOWS = {};\n{}",
+        version, NR_OF_CASCADES, MAX_NR_OF_SPOT_SHADOWS, rest
+    )
+}
+
+// near and far plane of a Mat4::perspective_rh projection
+pub fn near_far(projection: &Mat4) -> (f32, f32) {
+    let cols = projection.to_cols_array_2d();
+    (cols[3][2] / cols[2][2], cols[3][2] / (cols[2][2] + 1.0))
+}
+
+// practical split scheme, ends of the cascades between near and far
+pub fn cascade_splits(near: f32, far: f32) -> [f32; NR_OF_CASCADES] {
+    let mut splits = [0.0; NR_OF_CASCADES];
+    for (i, split) in splits.iter_mut().enumerate() {
+        let p = (i + 1) as f32 / NR_OF_CASCADES as f32;
+        let logarithmic = near * (far / near).powf(p);
+        let uniform = near + (far - near) * p;
+        *split = CASCADE_SPLIT_LAMBDA * logarithmic + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+    }
+    splits
+}
+
+// world space corners of the part of the view frustum between the view distances from and to
+pub fn frustum_slice_corners(projection: &Mat4, view: &Mat4, from: f32, to: f32) -> [Vec3; 8] {
+    let (near, far) = near_far(projection);
+    let inverse = (*projection * *view).inverse();
+    let unproject = |x: f32, y: f32, z: f32| {
+        let p = inverse * Vec4::new(x, y, z, 1.0);
+        Vec3::new(p.x, p.y, p.z) / p.w
+    };
+    let t_from = (from - near) / (far - near);
+    let t_to = (to - near) / (far - near);
+    let mut corners = [Vec3::zero(); 8];
+    for (i, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].iter().enumerate() {
+        let near_corner = unproject(*x, *y, 0.0);
+        let far_corner = unproject(*x, *y, 1.0);
+        corners[i] = near_corner + (far_corner - near_corner) * t_from;
+        corners[i + 4] = near_corner + (far_corner - near_corner) * t_to;
+    }
+    corners
+}
+
+fn up_for(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+// Orthographic projection around the bounding sphere of the corners, snapped to whole shadow map
+// texels so the shadows don't shimmer when the camera moves
+pub fn directional_light_space(direction: Vec3, corners: &[Vec3; 8]) -> Mat4 {
+    let center = corners.iter().fold(Vec3::zero(), |sum, c| sum + *c) / 8.0;
+    let radius = corners.iter().map(|c| (*c - center).length()).fold(0.0, f32::max);
+    let direction = direction.normalize();
+    let eye = center - direction * (radius + SHADOW_CASTER_DISTANCE);
+    let view = Mat4::look_at_rh(eye, center, up_for(direction));
+    let projection = Mat4::orthographic_rh(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + SHADOW_CASTER_DISTANCE,
+    );
+    let light_space = projection * view;
+    let half_size = SHADOW_MAP_SIZE as f32 / 2.0;
+    let origin = light_space * Vec4::new(0.0, 0.0, 0.0, 1.0) * half_size;
+    let offset = Vec3::new(origin.x.round() - origin.x, origin.y.round() - origin.y, 0.0) / half_size;
+    Mat4::from_translation(offset) * light_space
+}
+
+pub fn spot_light_space(spot: &SpotProperties) -> Mat4 {
+    let position = Vec3::new(spot.position[0], spot.position[1], spot.position[2]);
+    let direction = Vec3::new(spot.direction[0], spot.direction[1], spot.direction[2]).normalize();
+    let fov = 2.0 * spot.cut_off_outer.acos();
+    Mat4::perspective_rh(fov, 1.0, SPOT_SHADOW_NEAR, SPOT_SHADOW_FAR)
+        * Mat4::look_at_rh(position, position + direction, up_for(direction))
+}
+
+pub struct ShadowMap {
+    pub texture: wgpu::Texture,
+    // all layers, sampled by the main pipeline
+    pub view: wgpu::TextureView,
+    // one view per layer, rendered to by the shadow pipeline
+    pub layer_views: Vec<wgpu::TextureView>,
+    pub sampler: wgpu::Sampler,
+}
+
+impl ShadowMap {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(renderer: &Renderer) -> Self {
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth: NR_OF_SHADOW_MAPS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..NR_OF_SHADOW_MAPS as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        // comparison sampler with linear filtering gives 2x2 pcf for free, shader.frag adds more taps
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            anisotropy_clamp: None,
+            border_color: None,
+        });
+        Self {
+            texture,
+            view,
+            layer_views,
+            sampler,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        registry::Registry,
+        renderer::{
+            shadow_map::{
+                cascade_splits, directional_light_space, directional_lights_with_shadow, frustum_slice_corners,
+                near_far, spot_light_space, spot_lights_with_shadow_index, with_shadow_constants, ShadowUniform,
+                MAX_NR_OF_SPOT_SHADOWS, NR_OF_CASCADES,
+            },
+            DirectionalProperties, Light, SpotProperties,
+        },
+    };
+    use glam::{Mat4, Vec3, Vec4};
+
+    fn to_ndc(m: &Mat4, p: Vec3) -> Vec3 {
+        let p = *m * Vec4::new(p.x, p.y, p.z, 1.0);
+        Vec3::new(p.x, p.y, p.z) / p.w
+    }
+
+    fn inside(ndc: Vec3) -> bool {
+        ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && ndc.z >= 0.0 && ndc.z <= 1.0
+    }
+
+    #[test]
+    fn near_far_of_projection() {
+        let projection = Mat4::perspective_rh(0.8, 1.5, 0.1, 1000.0);
+        let (near, far) = near_far(&projection);
+        assert!((near - 0.1).abs() < 1e-4);
+        assert!((far - 1000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn cascades_are_increasing() {
+        let splits = cascade_splits(0.1, 150.0);
+        assert_eq!(splits.len(), NR_OF_CASCADES);
+        assert!(splits[0] > 0.1);
+        assert!(splits.windows(2).all(|w| w[0] < w[1]));
+        assert!((splits[NR_OF_CASCADES - 1] - 150.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cascade_covers_its_slice() {
+        let projection = Mat4::perspective_rh(45.0 * std::f32::consts::PI * 2.0 / 360.0, 1.5, 0.1, 1000.0);
+        let view = Mat4::look_at_rh(
+            Vec3::new(0.0, 5.0, 10.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let corners = frustum_slice_corners(&projection, &view, 5.0, 20.0);
+        // the corners lie at the requested view distances
+        for (i, c) in corners.iter().enumerate() {
+            let depth = -(view.transform_point3(*c)).z;
+            let expected = if i < 4 { 5.0 } else { 20.0 };
+            assert!((depth - expected).abs() < expected * 1e-2, "{} {}", depth, expected);
+        }
+        let light_space = directional_light_space(Vec3::new(-1.0, -0.5, -1.0), &corners);
+        assert!(corners.iter().all(|c| inside(to_ndc(&light_space, *c))));
+        // an occluder between the slice and the sun is still in front of the near plane
+        let occluder = corners[0] + Vec3::new(1.0, 0.5, 1.0).normalize() * 30.0;
+        assert!(inside(to_ndc(&light_space, occluder)));
+    }
+
+    #[test]
+    fn spot_light_looks_along_its_direction() {
+        let spot = SpotProperties::new([0.0, 4.0, 0.0, 1.0], [0.0, -1.0, 0.0, 1.0]);
+        let light_space = spot_light_space(&spot);
+        assert!(inside(to_ndc(&light_space, Vec3::new(0.0, 0.0, 0.0))));
+        assert!(inside(to_ndc(&light_space, Vec3::new(0.5, 0.0, 0.5))));
+        // above the light and far outside of the cone
+        assert!(!inside(to_ndc(&light_space, Vec3::new(0.0, 8.0, 0.0))));
+        assert!(!inside(to_ndc(&light_space, Vec3::new(10.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn shader_gets_the_shadow_constants() {
+        let source = with_shadow_constants(include_str!("shaders/shader.frag"));
+        assert!(source.starts_with("#version 450\n"));
+        assert!(source.contains(&format!("const uint NR_OF_CASCADES = {};", NR_OF_CASCADES)));
+        assert!(source.contains(&format!(
+            "const uint MAX_NR_OF_SPOT_SHADOWS = {};",
+            MAX_NR_OF_SPOT_SHADOWS
+        )));
+        assert_eq!(source.matches("const uint NR_OF_CASCADES").count(), 1);
+    }
+
+    #[test]
+    fn shadow_indices_are_limited() {
+        let mut lights = Registry::new();
+        for i in 0..MAX_NR_OF_SPOT_SHADOWS + 2 {
+            let spot = SpotProperties::new([i as f32, 4.0, 0.0, 1.0], [0.0, -1.0, 0.0, 1.0]);
+            lights.add(Light::Spot(spot.with_shadows()));
+            lights.add(Light::Spot(spot));
+        }
+        let spot_lights = spot_lights_with_shadow_index(&lights);
+        assert_eq!(spot_lights.len(), 2 * (MAX_NR_OF_SPOT_SHADOWS + 2));
+        let mut indices: Vec<_> = spot_lights
+            .iter()
+            .filter(|s| s.casts_shadows != 0)
+            .map(|s| s.shadow_index as usize)
+            .collect();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..MAX_NR_OF_SPOT_SHADOWS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn only_one_directional_light_gets_the_cascades() {
+        let mut lights = Registry::new();
+        for direction in &[[0.0, -1.0, 0.0, 0.0], [1.0, -1.0, 0.0, 0.0], [0.0, -1.0, 1.0, 0.0]] {
+            lights.add(Light::Directional(
+                DirectionalProperties::new(*direction).with_shadows(),
+            ));
+        }
+        let directional_lights = directional_lights_with_shadow(&lights);
+        assert_eq!(directional_lights.len(), 3);
+        assert_eq!(directional_lights.iter().filter(|d| d.casts_shadows != 0).count(), 1);
+        let uniform = ShadowUniform {
+            directional_light_spaces: [Mat4::identity(); NR_OF_CASCADES],
+            spot_light_spaces: [Mat4::identity(); MAX_NR_OF_SPOT_SHADOWS],
+            cascade_splits: [0.0; 4],
+        };
+        let layers: Vec<_> = uniform.active_layers(&lights).iter().map(|(layer, _)| *layer).collect();
+        assert_eq!(layers, (0..NR_OF_CASCADES).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    entity::Entity,
+    mesh::{Mesh, Vertex},
+    registry::Registry,
+    renderer::{
+        bindgroup::Instance,
+        error::RendererError,
+        shadow_bindgroup::{ShadowBindGroup, LIGHT_SPACE_STRIDE, MAX_NR_OF_SHADOW_INSTANCES},
+        shadow_map::{ShadowMap, ShadowUniform},
+        Camera, Frustum, Light, Renderer,
+    },
+};
+use std::io::Read;
+
+pub struct ShadowPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowPipeline {
+    pub async fn new(renderer: &Renderer, bind_group: &ShadowBindGroup) -> Result<Self, RendererError> {
+        let mut spirv_vs_bytes = Vec::new();
+        match glsl_to_spirv::compile(include_str!("shaders/shadow.vert"), glsl_to_spirv::ShaderType::Vertex) {
+            Ok(mut spirv_vs_output) => {
+                spirv_vs_output.read_to_end(&mut spirv_vs_bytes).unwrap();
+            }
+            Err(ref e) => return Err(RendererError::from(e.clone())),
+        }
+        let vs_module_source = wgpu::util::make_spirv(spirv_vs_bytes.as_slice());
+        let vs_module = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: vs_module_source,
+            flags: Default::default(),
+        });
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // depth only, no fragment stage
+        let render_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: ShadowMap::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState {
+                    front: wgpu::StencilFaceState::IGNORE,
+                    back: wgpu::StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                // against shadow acne
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+                clamp_depth: false,
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+        Ok(Self { render_pipeline })
+    }
+
+    // Renders the depth of every shadow casting light into its layer(s) of the shadow map, has to
+    // run before Pipeline::render. Meshes without a vertex buffer yet are picked up next frame.
+    pub fn render(
+        &self,
+        entities: &Registry<Entity>,
+        meshes: &Registry<Mesh>,
+        lights: &Registry<Light>,
+        bindgroup: &ShadowBindGroup,
+        shadow_map: &ShadowMap,
+        camera: &dyn Camera,
+        renderer: &mut Renderer,
+    ) {
+        let layers = ShadowUniform::new(lights, camera).active_layers(lights);
+        let mut transforms = Vec::new();
+        let mut layer_draws = Vec::new();
+        for (layer, light_space) in &layers {
+            bindgroup.update_light_space(&renderer, *layer, light_space);
+            let frustum = Frustum::from_matrix(light_space);
+            let mut draws = Vec::new();
+            for id in meshes.registry.keys() {
+                let vertex_buffer = match renderer.vertex_buffers.get(id) {
+                    Some(vertex_buffer) => vertex_buffer,
+                    None => continue,
+                };
+                let start = transforms.len() as u32;
+                transforms.extend(entities.registry.values().filter_map(|entity| {
+                    if entity.mesh_handle.id == *id {
+                        let m = entity.transform.to_matrix();
+                        if frustum.intersects(&vertex_buffer.bounds.transform(&m)) {
+                            return Some(Instance::new(m, 0));
+                        }
+                    }
+                    None
+                }));
+                if start < transforms.len() as u32 {
+                    draws.push((*id, start..transforms.len() as u32));
+                }
+            }
+            layer_draws.push((*layer, draws));
+        }
+        assert!(transforms.len() <= MAX_NR_OF_SHADOW_INSTANCES);
+        bindgroup.update_instances(&renderer, transforms.as_slice());
+
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (layer, draws) in layer_draws {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &shadow_map.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &bindgroup.bind_group, &[(layer as u64 * LIGHT_SPACE_STRIDE) as u32]);
+            for (id, instance_range) in draws {
+                let mesh = renderer.vertex_buffers.get(&id).unwrap();
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.len, 0, instance_range);
+            }
+        }
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
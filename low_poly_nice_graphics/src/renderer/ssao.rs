@@ -0,0 +1,109 @@
+use glam::{Mat4, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub const KERNEL_SIZE: usize = 32;
+// the rotation noise tiles the screen in blocks of NOISE_SIZE x NOISE_SIZE pixels, the blur pass
+// averages over the same block size to hide the tiling pattern
+pub const NOISE_SIZE: usize = 4;
+// view space radius of the sampled hemisphere
+const RADIUS: f32 = 0.75;
+// against self occlusion of flat surfaces
+const BIAS: f32 = 0.025;
+// the kernel only has to look random, a fixed seed gives the same one every run
+const SEED: u64 = 0x55A0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SsaoUniform {
+    pub projection: Mat4,
+    pub inverse_projection: Mat4,
+    pub kernel: [[f32; 4]; KERNEL_SIZE],
+    pub noise: [[f32; 4]; NOISE_SIZE * NOISE_SIZE],
+    pub radius: f32,
+    pub bias: f32,
+    pub p0: f32,
+    pub p1: f32,
+}
+
+unsafe impl bytemuck::Pod for SsaoUniform {}
+unsafe impl bytemuck::Zeroable for SsaoUniform {}
+
+impl SsaoUniform {
+    pub fn new(projection: Mat4) -> Self {
+        let mut random = StdRng::seed_from_u64(SEED);
+        let mut kernel = [[0.0; 4]; KERNEL_SIZE];
+        for (sample, v) in hemisphere_kernel(&mut random).iter().zip(kernel.iter_mut()) {
+            *v = [sample.x, sample.y, sample.z, 0.0];
+        }
+        let mut noise = [[0.0; 4]; NOISE_SIZE * NOISE_SIZE];
+        for (rotation, v) in rotation_noise(&mut random).iter().zip(noise.iter_mut()) {
+            *v = [rotation.x, rotation.y, rotation.z, 0.0];
+        }
+        Self {
+            projection,
+            inverse_projection: projection.inverse(),
+            kernel,
+            noise,
+            radius: RADIUS,
+            bias: BIAS,
+            p0: 0.0,
+            p1: 0.0,
+        }
+    }
+}
+
+// samples in the tangent space hemisphere around +z, more of them close to the origin since
+// nearby geometry occludes the most
+fn hemisphere_kernel(random: &mut StdRng) -> Vec<Vec3> {
+    (0..KERNEL_SIZE)
+        .map(|i| {
+            let direction = Vec3::new(
+                random.gen::<f32>() * 2.0 - 1.0,
+                random.gen::<f32>() * 2.0 - 1.0,
+                random.gen::<f32>().max(0.05),
+            )
+            .normalize();
+            let scale = i as f32 / KERNEL_SIZE as f32;
+            direction * random.gen::<f32>() * (0.1 + 0.9 * scale * scale)
+        })
+        .collect()
+}
+
+// random rotations around the tangent space z axis, decorrelates the kernel between neighbouring pixels
+fn rotation_noise(random: &mut StdRng) -> Vec<Vec3> {
+    (0..NOISE_SIZE * NOISE_SIZE)
+        .map(|_| Vec3::new(random.gen::<f32>() * 2.0 - 1.0, random.gen::<f32>() * 2.0 - 1.0, 0.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::ssao::{hemisphere_kernel, rotation_noise, SsaoUniform, KERNEL_SIZE, SEED};
+    use glam::{Mat4, Vec4};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn kernel_lies_in_unit_hemisphere() {
+        let kernel = hemisphere_kernel(&mut StdRng::seed_from_u64(SEED));
+        assert_eq!(kernel.len(), KERNEL_SIZE);
+        assert!(kernel.iter().all(|s| s.z > 0.0 && s.length() <= 1.0));
+        // scaled towards the origin, the second half reaches further out than the first
+        let reach = |samples: &[glam::Vec3]| samples.iter().map(|s| s.length()).sum::<f32>();
+        assert!(reach(&kernel[..KERNEL_SIZE / 2]) < reach(&kernel[KERNEL_SIZE / 2..]));
+    }
+
+    #[test]
+    fn noise_rotates_around_z() {
+        let noise = rotation_noise(&mut StdRng::seed_from_u64(SEED));
+        assert!(noise.iter().all(|r| r.z == 0.0 && r.x.abs() <= 1.0 && r.y.abs() <= 1.0));
+        assert!(noise.iter().any(|r| r.x < 0.0) && noise.iter().any(|r| r.x > 0.0));
+    }
+
+    #[test]
+    fn uniform_inverts_projection() {
+        let uniform = SsaoUniform::new(Mat4::perspective_rh(0.8, 1.5, 0.1, 1000.0));
+        let p = uniform.inverse_projection * (uniform.projection * Vec4::new(1.0, -2.0, -10.0, 1.0));
+        let p = p / p.w;
+        assert!((p.x - 1.0).abs() < 1e-3 && (p.y + 2.0).abs() < 1e-3 && (p.z + 10.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::renderer::{ssao::SsaoUniform, Camera, Renderer};
+
+pub struct SsaoTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl SsaoTarget {
+    fn new(renderer: &Renderer, format: wgpu::TextureFormat) -> Self {
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: renderer.swap_chain_descriptor.width,
+                height: renderer.swap_chain_descriptor.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&Default::default());
+        Self { texture, view }
+    }
+}
+
+// The screen sized targets of the ssao passes: the g-buffer with view space normal and linear depth,
+// the raw occlusion and the blurred occlusion that is multiplied into the ambient light.
+// Has to be resized together with the renderer.
+pub struct SsaoBindGroup {
+    pub uniform: wgpu::Buffer,
+    pub sampler: wgpu::Sampler,
+    pub gbuffer: SsaoTarget,
+    pub occlusion: SsaoTarget,
+    pub blurred_occlusion: SsaoTarget,
+    pub ssao_bind_group_layout: wgpu::BindGroupLayout,
+    pub occlusion_bind_group_layout: wgpu::BindGroupLayout,
+    // input of the occlusion pass
+    pub ssao_bind_group: wgpu::BindGroup,
+    // input of the blur pass
+    pub blur_bind_group: wgpu::BindGroup,
+    // input of the lighting in the main pass
+    pub occlusion_bind_group: wgpu::BindGroup,
+}
+
+impl SsaoBindGroup {
+    pub const GBUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+    pub const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    pub fn new(renderer: &Renderer) -> Self {
+        let uniform = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<SsaoUniform>()) as u64,
+            mapped_at_creation: false,
+        });
+        // all passes read with texelFetch, no filtering
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            anisotropy_clamp: None,
+            border_color: None,
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                filtering: false,
+                comparison: false,
+            },
+            count: None,
+        };
+        let ssao_bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1),
+                    sampler_entry(2),
+                ],
+                label: None,
+            });
+        let occlusion_bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[texture_entry(0), sampler_entry(1)],
+                label: None,
+            });
+
+        let gbuffer = SsaoTarget::new(renderer, Self::GBUFFER_FORMAT);
+        let occlusion = SsaoTarget::new(renderer, Self::OCCLUSION_FORMAT);
+        let blurred_occlusion = SsaoTarget::new(renderer, Self::OCCLUSION_FORMAT);
+        let ssao_bind_group =
+            Self::create_ssao_bind_group(renderer, &ssao_bind_group_layout, &uniform, &gbuffer, &sampler);
+        let blur_bind_group =
+            Self::create_occlusion_bind_group(renderer, &occlusion_bind_group_layout, &occlusion, &sampler);
+        let occlusion_bind_group =
+            Self::create_occlusion_bind_group(renderer, &occlusion_bind_group_layout, &blurred_occlusion, &sampler);
+        Self {
+            uniform,
+            sampler,
+            gbuffer,
+            occlusion,
+            blurred_occlusion,
+            ssao_bind_group_layout,
+            occlusion_bind_group_layout,
+            ssao_bind_group,
+            blur_bind_group,
+            occlusion_bind_group,
+        }
+    }
+
+    fn create_ssao_bind_group(
+        renderer: &Renderer,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &wgpu::Buffer,
+        gbuffer: &SsaoTarget,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&gbuffer.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_occlusion_bind_group(
+        renderer: &Renderer,
+        layout: &wgpu::BindGroupLayout,
+        occlusion: &SsaoTarget,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&occlusion.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(&mut self, renderer: &Renderer) {
+        self.gbuffer = SsaoTarget::new(renderer, Self::GBUFFER_FORMAT);
+        self.occlusion = SsaoTarget::new(renderer, Self::OCCLUSION_FORMAT);
+        self.blurred_occlusion = SsaoTarget::new(renderer, Self::OCCLUSION_FORMAT);
+        self.ssao_bind_group = Self::create_ssao_bind_group(
+            renderer,
+            &self.ssao_bind_group_layout,
+            &self.uniform,
+            &self.gbuffer,
+            &self.sampler,
+        );
+        self.blur_bind_group = Self::create_occlusion_bind_group(
+            renderer,
+            &self.occlusion_bind_group_layout,
+            &self.occlusion,
+            &self.sampler,
+        );
+        self.occlusion_bind_group = Self::create_occlusion_bind_group(
+            renderer,
+            &self.occlusion_bind_group_layout,
+            &self.blurred_occlusion,
+            &self.sampler,
+        );
+    }
+
+    pub fn update_uniforms(&self, renderer: &Renderer, camera: &dyn Camera) {
+        renderer.queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[SsaoUniform::new(camera.get_projection())]),
+        );
+    }
+
+    // without the ssao passes nothing is occluded
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &self.blurred_occlusion.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+    }
+}
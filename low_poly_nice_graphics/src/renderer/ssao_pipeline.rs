@@ -0,0 +1,230 @@
+use crate::{
+    mesh::{Mesh, Vertex},
+    registry::Handle,
+    renderer::{
+        depth_texture::DepthTexture, error::RendererError, ssao_bindgroup::SsaoBindGroup, BindGroup, Camera, Renderer,
+    },
+};
+use std::{io::Read, ops::Range};
+
+fn create_shader_module(
+    renderer: &Renderer,
+    source: &str,
+    shader_type: glsl_to_spirv::ShaderType,
+) -> Result<wgpu::ShaderModule, RendererError> {
+    let mut spirv_bytes = Vec::new();
+    match glsl_to_spirv::compile(source, shader_type) {
+        Ok(mut spirv_output) => {
+            spirv_output.read_to_end(&mut spirv_bytes).unwrap();
+        }
+        Err(ref e) => return Err(RendererError::from(e.clone())),
+    }
+    Ok(renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::util::make_spirv(spirv_bytes.as_slice()),
+        flags: Default::default(),
+    }))
+}
+
+fn create_fullscreen_pipeline(
+    renderer: &Renderer,
+    layout: &wgpu::BindGroupLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &[SsaoBindGroup::OCCLUSION_FORMAT.into()],
+        }),
+    })
+}
+
+// Screen space ambient occlusion, optional part of Pipeline::render:
+// 1. depth prepass that also writes view space normals and linear depth into the g-buffer
+// 2. hemisphere sampled occlusion around every pixel of the g-buffer
+// 3. blur to remove the noise pattern, the result darkens the ambient light of the main pass
+pub struct SsaoPipeline {
+    gbuffer_pipeline: wgpu::RenderPipeline,
+    occlusion_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+}
+
+impl SsaoPipeline {
+    pub async fn new(renderer: &Renderer, bind_group: &BindGroup) -> Result<Self, RendererError> {
+        let vs_module = create_shader_module(
+            renderer,
+            include_str!("shaders/shader.vert"),
+            glsl_to_spirv::ShaderType::Vertex,
+        )?;
+        let gbuffer_fs_module = create_shader_module(
+            renderer,
+            include_str!("shaders/gbuffer.frag"),
+            glsl_to_spirv::ShaderType::Fragment,
+        )?;
+        let fullscreen_vs_module = create_shader_module(
+            renderer,
+            include_str!("shaders/fullscreen.vert"),
+            glsl_to_spirv::ShaderType::Vertex,
+        )?;
+        let occlusion_fs_module = create_shader_module(
+            renderer,
+            include_str!("shaders/ssao.frag"),
+            glsl_to_spirv::ShaderType::Fragment,
+        )?;
+        let blur_fs_module = create_shader_module(
+            renderer,
+            include_str!("shaders/ssao_blur.frag"),
+            glsl_to_spirv::ShaderType::Fragment,
+        )?;
+
+        let gbuffer_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let gbuffer_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&gbuffer_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState {
+                    front: wgpu::StencilFaceState::IGNORE,
+                    back: wgpu::StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: 0.0,
+                    clamp: 0.0,
+                },
+                clamp_depth: false,
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &gbuffer_fs_module,
+                entry_point: "main",
+                targets: &[SsaoBindGroup::GBUFFER_FORMAT.into()],
+            }),
+        });
+        let occlusion_pipeline = create_fullscreen_pipeline(
+            renderer,
+            &bind_group.ssao.ssao_bind_group_layout,
+            &fullscreen_vs_module,
+            &occlusion_fs_module,
+        );
+        let blur_pipeline = create_fullscreen_pipeline(
+            renderer,
+            &bind_group.ssao.occlusion_bind_group_layout,
+            &fullscreen_vs_module,
+            &blur_fs_module,
+        );
+        Ok(Self {
+            gbuffer_pipeline,
+            occlusion_pipeline,
+            blur_pipeline,
+        })
+    }
+
+    // Fills the depth texture of the renderer, the main pass loads it instead of clearing it.
+    // Expects the uniforms and instances of bind_group to be up to date.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        instance_map: &[(Handle<Mesh>, u32, Range<u32>)],
+        bind_group: &BindGroup,
+        camera: &dyn Camera,
+        renderer: &Renderer,
+    ) {
+        let ssao = &bind_group.ssao;
+        ssao.update_uniforms(renderer, camera);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &ssao.gbuffer.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &renderer.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.gbuffer_pipeline);
+            render_pass.set_bind_group(0, &bind_group.bind_group, &[]);
+            for (mesh_handle, _, instance_range) in instance_map {
+                if !instance_range.is_empty() {
+                    let mesh = renderer.vertex_buffers.get(&mesh_handle.id).unwrap();
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..mesh.len, 0, instance_range.clone());
+                }
+            }
+        }
+        for &(pipeline, input, output) in &[
+            (&self.occlusion_pipeline, &ssao.ssao_bind_group, &ssao.occlusion.view),
+            (&self.blur_pipeline, &ssao.blur_bind_group, &ssao.blurred_occlusion.view),
+        ] {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, input, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
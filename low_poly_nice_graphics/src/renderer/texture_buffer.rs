@@ -0,0 +1,66 @@
+use crate::{renderer::Renderer, texture::Texture};
+
+pub struct TextureBuffer {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl TextureBuffer {
+    // srgb so sampling returns linear colors, the lighting happens in linear space
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    // Uploads the texture with all its mip levels.
+    pub fn from_texture(renderer: &Renderer, texture: &Texture) -> Self {
+        let gpu_texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: texture.width,
+                height: texture.height,
+                depth: 1,
+            },
+            mip_level_count: texture.mip_level_count(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        for (mip_level, level) in texture.mip_chain().iter().enumerate() {
+            renderer.queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &gpu_texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                level.pixels.as_slice(),
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * level.width,
+                    rows_per_image: level.height,
+                },
+                wgpu::Extent3d {
+                    width: level.width,
+                    height: level.height,
+                    depth: 1,
+                },
+            );
+        }
+        let view = gpu_texture.create_view(&Default::default());
+        Self {
+            texture: gpu_texture,
+            view,
+        }
+    }
+
+    // bound for materials without a texture
+    pub fn white(renderer: &Renderer) -> Self {
+        Self::from_texture(
+            renderer,
+            &Texture {
+                width: 1,
+                height: 1,
+                pixels: vec![255, 255, 255, 255],
+                just_loaded: true,
+            },
+        )
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
     mesh::{Mesh, Vertex},
-    renderer::Renderer,
+    renderer::{Aabb, Renderer},
 };
+use glam::Vec3;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -10,6 +11,9 @@ pub struct VertexBuffer {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub len: u32,
+    pub bounds: Aabb,
+    // drawn after the opaque meshes, back to front
+    pub transparent: bool,
 }
 
 impl VertexBuffer {
@@ -17,7 +21,7 @@ impl VertexBuffer {
         let vertex_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(mesh.vertices.as_slice()),
-            usage: wgpu::BufferUsage::VERTEX,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
         });
         let index_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -28,8 +32,16 @@ impl VertexBuffer {
             vertex_buffer,
             index_buffer,
             len: mesh.indices.len() as u32,
+            bounds: Aabb::from_points(mesh.vertices.iter().map(|v| Vec3::from(v.position))),
+            transparent: mesh.vertices.iter().any(|v| v.alpha < 1.0),
         }
     }
+
+    // for meshes whose vertices moved but kept their number, like skinned meshes
+    pub fn update_vertices(&mut self, queue: &wgpu::Queue, mesh: &Mesh) {
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(mesh.vertices.as_slice()));
+        self.bounds = Aabb::from_points(mesh.vertices.iter().map(|v| Vec3::from(v.position)));
+    }
 }
 
 impl Vertex {
@@ -54,6 +66,26 @@ impl Vertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float3,
                 },
+                wgpu::VertexAttribute {
+                    offset: 3 * mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttribute {
+                    offset: (3 * mem::size_of::<[f32; 3]>() + mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttribute {
+                    offset: (3 * mem::size_of::<[f32; 3]>() + 3 * mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttribute {
+                    offset: (3 * mem::size_of::<[f32; 3]>() + 4 * mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float,
+                },
             ],
         }
     }
@@ -0,0 +1,3 @@
+mod texture;
+
+pub use texture::Texture;
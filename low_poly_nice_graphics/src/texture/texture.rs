@@ -0,0 +1,93 @@
+// Rgba8 pixels in srgb, rows from top to bottom.
+#[derive(Clone)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub just_loaded: bool,
+}
+
+impl Texture {
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        let image = image.to_rgba8();
+        Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+            just_loaded: true,
+        }
+    }
+
+    pub fn load(path: &str) -> image::ImageResult<Self> {
+        Ok(Self::from_image(&image::open(path)?))
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        32 - self.width.max(self.height).max(1).leading_zeros()
+    }
+
+    // All mip levels starting with the texture itself, every level halves the size down to 1x1.
+    pub fn mip_chain(&self) -> Vec<Texture> {
+        let image = image::RgbaImage::from_raw(self.width, self.height, self.pixels.clone())
+            .expect("pixels do not match the size of the texture");
+        let mut levels = vec![self.clone()];
+        for level in 1..self.mip_level_count() {
+            let width = (self.width >> level).max(1);
+            let height = (self.height >> level).max(1);
+            // from the full image every time, so the errors of the filter do not add up
+            let resized = image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle);
+            levels.push(Self {
+                width,
+                height,
+                pixels: resized.into_raw(),
+                just_loaded: self.just_loaded,
+            });
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::texture::Texture;
+
+    fn checker(width: u32, height: u32) -> Texture {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let v = if (x + y) % 2 == 0 { 255 } else { 0 };
+                pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        Texture {
+            width,
+            height,
+            pixels,
+            just_loaded: true,
+        }
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_one_pixel() {
+        let texture = checker(16, 4);
+        assert_eq!(texture.mip_level_count(), 5);
+        let sizes = texture
+            .mip_chain()
+            .iter()
+            .map(|level| {
+                assert_eq!(level.pixels.len(), (level.width * level.height * 4) as usize);
+                (level.width, level.height)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(16, 4), (8, 2), (4, 1), (2, 1), (1, 1)]);
+        assert_eq!(checker(1, 1).mip_chain().len(), 1);
+    }
+
+    #[test]
+    fn smallest_level_averages_the_texture() {
+        let chain = checker(8, 8).mip_chain();
+        let last = chain.last().unwrap();
+        assert!((last.pixels[0] as i32 - 128).abs() < 16);
+        assert_eq!(last.pixels[3], 255);
+    }
+}
@@ -1,2 +1,2 @@
 mod transform;
-pub use transform::Transform;
+pub use transform::{slerp, Transform};
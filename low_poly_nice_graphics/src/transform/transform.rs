@@ -75,6 +75,24 @@ impl Transform {
     pub fn to_matrix(&self) -> Mat4 {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
     }
+
+    // s = 0.0 gives self, s = 1.0 gives other
+    pub fn lerp(&self, other: &Transform, s: f32) -> Transform {
+        Transform {
+            translation: self.translation.lerp(other.translation, s),
+            rotation: slerp(self.rotation, other.rotation, s),
+            scale: self.scale.lerp(other.scale, s),
+        }
+    }
+}
+
+// Takes the shortest way around, q and -q are the same rotation.
+pub fn slerp(from: Quat, to: Quat, s: f32) -> Quat {
+    if from.dot(to) < 0.0 {
+        from.slerp(-to, s)
+    } else {
+        from.slerp(to, s)
+    }
 }
 
 impl Default for Transform {
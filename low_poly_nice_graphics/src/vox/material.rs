@@ -0,0 +1,122 @@
+use crate::vox::VoxError;
+use std::collections::HashMap;
+
+// How the voxels of a color id are rendered, from the MATL chunks of .vox files.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VoxMaterial {
+    Diffuse,
+    // alpha from 0 (invisible) to 1 (opaque)
+    Glass { alpha: f32 },
+    // emission from 0 to 1 and the MagicaVoxel power (flux) from 0 to 4
+    Emit { emission: f32, power: f32 },
+    // only kept so saving and writing the voxels does not lose it, the renderer draws metal like diffuse voxels
+    Metal { metalness: f32, roughness: f32 },
+}
+
+impl VoxMaterial {
+    // The properties of a MATL chunk. Older files have a key per type for its value, newer ones call it _weight.
+    pub fn from_properties(properties: &HashMap<String, String>) -> Result<Self, VoxError> {
+        let value = |keys: &[&str], default: f32| match keys.iter().find_map(|key| properties.get(*key)) {
+            Some(value) => value.parse::<f32>().map_err(|_| VoxError::Corrupt),
+            None => Ok(default),
+        };
+        Ok(match properties.get("_type").map(String::as_str) {
+            Some("_glass") => VoxMaterial::Glass {
+                // the files store how transparent the glass is
+                alpha: (1.0 - value(&["_alpha", "_trans", "_weight"], 0.5)?).clamp(0.0, 1.0),
+            },
+            Some("_emit") => VoxMaterial::Emit {
+                emission: value(&["_emit", "_weight"], 1.0)?,
+                power: value(&["_flux"], 0.0)?,
+            },
+            Some("_metal") => VoxMaterial::Metal {
+                metalness: value(&["_metal", "_weight"], 1.0)?,
+                roughness: value(&["_rough"], 0.0)?,
+            },
+            _ => VoxMaterial::Diffuse,
+        })
+    }
+
+    // 1.0 unless the voxels are glass
+    pub fn alpha(&self) -> f32 {
+        match self {
+            VoxMaterial::Glass { alpha } => *alpha,
+            _ => 1.0,
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha() < 1.0
+    }
+
+    // How much the voxels glow, every step of power doubles it. 0.0 unless they emit light.
+    pub fn emission(&self) -> f32 {
+        match self {
+            VoxMaterial::Emit { emission, power } => emission * 2.0f32.powf(*power),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{registry::Registry, vox::load_vox_scene, vox::VoxMaterial};
+    use std::collections::HashMap;
+
+    fn properties(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn materials_from_old_and_new_properties() {
+        let old_glass = properties(&[("_type", "_glass"), ("_alpha", "0.4")]);
+        assert_eq!(
+            VoxMaterial::from_properties(&old_glass).unwrap(),
+            VoxMaterial::Glass { alpha: 0.6 }
+        );
+        let new_emit = properties(&[("_type", "_emit"), ("_weight", "0.5"), ("_flux", "2")]);
+        let emit = VoxMaterial::from_properties(&new_emit).unwrap();
+        assert_eq!(
+            emit,
+            VoxMaterial::Emit {
+                emission: 0.5,
+                power: 2.0
+            }
+        );
+        assert_eq!(emit.emission(), 2.0);
+        assert!(!emit.is_transparent());
+        let diffuse = properties(&[("_type", "_diffuse"), ("_weight", "1")]);
+        assert_eq!(VoxMaterial::from_properties(&diffuse).unwrap(), VoxMaterial::Diffuse);
+        assert!(VoxMaterial::from_properties(&properties(&[("_type", "_metal"), ("_metal", "x")])).is_err());
+    }
+
+    #[test]
+    fn scene_models_get_the_materials_of_their_colors() {
+        let mut registry = Registry::new();
+        let scene = load_vox_scene(
+            std::fs::read("res/vox-models/#skyscraper/#skyscraper_03_000.vox")
+                .unwrap()
+                .as_slice(),
+            &mut registry,
+        )
+        .unwrap();
+        let (mut glass, mut emit) = (0, 0);
+        for handle in &scene.models {
+            let vox = registry.get(handle).unwrap();
+            for z in 0..vox.z_size {
+                for y in 0..vox.y_size {
+                    for x in 0..vox.x_size {
+                        match vox.get(x, y, z).map(|color_id| vox.get_material(color_id)) {
+                            Some(VoxMaterial::Glass { .. }) => glass += 1,
+                            Some(VoxMaterial::Emit { .. }) => emit += 1,
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+        // palette indices 8 and 28 are glass and 37 emits light, the voxels have them as color ids 7, 27 and 36
+        assert_eq!(glass, 451 + 19197);
+        assert_eq!(emit, 12150);
+    }
+}
@@ -1,3 +1,11 @@
+mod material;
+mod reader;
+mod scene;
 mod vox_loader;
+mod writer;
 
+pub use material::VoxMaterial;
+pub use reader::VoxError;
+pub use scene::{load_vox_scene, VoxPlacement};
 pub use vox_loader::{load_vox, Vox};
+pub use writer::write_vox;
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, convert::TryInto};
+
+#[derive(Debug)]
+pub enum VoxError {
+    DotVox(&'static str),
+    Corrupt,
+    // more than 256 voxels along an axis or more than 255 colors, what a .vox model can not have
+    TooLarge,
+}
+
+impl From<&'static str> for VoxError {
+    fn from(e: &'static str) -> VoxError {
+        VoxError::DotVox(e)
+    }
+}
+
+// id and content of a chunk
+pub type RawChunk<'a> = ([u8; 4], &'a [u8]);
+
+// The chunks inside the MAIN chunk of a .vox file, children of chunks are listed after their parent.
+pub fn read_chunks(bytes: &[u8]) -> Result<Vec<RawChunk<'_>>, VoxError> {
+    if bytes.get(0..4) != Some(b"VOX ") || bytes.get(8..12) != Some(b"MAIN") {
+        return Err(VoxError::Corrupt);
+    }
+    let mut reader = VoxReader::new(&bytes[12..]);
+    let content_size = reader.u32()? as usize;
+    reader.bytes(4)?;
+    reader.bytes(content_size)?;
+    let mut chunks = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.bytes(4)?.try_into().unwrap();
+        let content_size = reader.u32()? as usize;
+        // the children follow the content directly, they are read as chunks of their own
+        reader.bytes(4)?;
+        chunks.push((id, reader.bytes(content_size)?));
+    }
+    Ok(chunks)
+}
+
+// Reads the little endian values, strings and dictionaries that .vox chunks are made of.
+pub struct VoxReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> VoxReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&mut self, length: usize) -> Result<&'a [u8], VoxError> {
+        if length > self.bytes.len() {
+            return Err(VoxError::Corrupt);
+        }
+        let (read, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(read)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, VoxError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, VoxError> {
+        Ok(self.u32()? as i32)
+    }
+
+    pub fn string(&mut self) -> Result<String, VoxError> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+
+    pub fn dict(&mut self) -> Result<HashMap<String, String>, VoxError> {
+        let count = self.u32()?;
+        let mut dict = HashMap::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+}
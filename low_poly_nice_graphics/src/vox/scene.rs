@@ -0,0 +1,479 @@
+use crate::{
+    registry::{Handle, Registry},
+    vox::{
+        reader::{read_chunks, VoxError, VoxReader},
+        vox_loader::model_to_vox,
+        Vox, VoxMaterial,
+    },
+};
+use std::collections::HashMap;
+
+type Rotation = [[i32; 3]; 3];
+
+const IDENTITY: Rotation = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+// One shape of the scene graph. Like the models it is in vox coordinates (y up), a voxel at v of the model ends up at
+// rotation * (v - size / 2) + translation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub rotation: Rotation,
+    pub translation: [i32; 3],
+}
+
+// A model placed at a position in world voxel coordinates, what World::add takes.
+#[derive(Clone)]
+pub struct VoxPlacement {
+    pub handle: Handle<Vox>,
+    pub position: [i32; 3],
+}
+
+pub struct VoxScene {
+    // every model in the file, a shape with animation frames has a model per frame
+    pub models: Vec<Handle<Vox>>,
+    // the visible shapes of every animation frame
+    pub frames: Vec<Vec<VoxInstance>>,
+}
+
+enum Node {
+    Transform {
+        child: i32,
+        layer: i32,
+        hidden: bool,
+        // (animation frame, rotation, translation), the key frames of the transform
+        frames: Vec<(usize, Rotation, [i32; 3])>,
+    },
+    Group {
+        children: Vec<i32>,
+    },
+    Shape {
+        // (animation frame, model)
+        models: Vec<(usize, usize)>,
+    },
+}
+
+// Loads every model of a .vox file together with the MagicaVoxel scene graph (nTRN, nGRP and nSHP chunks) that
+// places them and the materials (MATL chunks) of their colors. Files without a scene graph get one frame with all
+// models at the origin.
+pub fn load_vox_scene(bytes: &[u8], registry: &mut Registry<Vox>) -> Result<VoxScene, VoxError> {
+    let data = dot_vox::load_bytes(bytes)?;
+    let models = data
+        .models
+        .iter()
+        .map(|model| registry.add(model_to_vox(model, &data.palette)))
+        .collect::<Vec<_>>();
+
+    let mut nodes = HashMap::new();
+    let mut hidden_layers = Vec::new();
+    let mut materials = Vec::new();
+    for (id, content) in read_chunks(bytes)? {
+        let mut reader = VoxReader::new(content);
+        match &id {
+            b"nTRN" => {
+                let node_id = reader.i32()?;
+                let attributes = reader.dict()?;
+                let child = reader.i32()?;
+                let _reserved = reader.i32()?;
+                let layer = reader.i32()?;
+                let frame_count = reader.u32()?;
+                let mut frames = Vec::new();
+                for _ in 0..frame_count {
+                    let frame = reader.dict()?;
+                    frames.push((
+                        key_frame(&frame),
+                        frame
+                            .get("_r")
+                            .map(|r| r.parse().map_err(|_| VoxError::Corrupt).and_then(rotation_from_byte))
+                            .transpose()?
+                            .unwrap_or(IDENTITY),
+                        frame
+                            .get("_t")
+                            .map(|t| parse_translation(t))
+                            .transpose()?
+                            .unwrap_or([0, 0, 0]),
+                    ));
+                }
+                let hidden = attributes.get("_hidden").map(|h| h == "1").unwrap_or(false);
+                nodes.insert(
+                    node_id,
+                    Node::Transform {
+                        child,
+                        layer,
+                        hidden,
+                        frames,
+                    },
+                );
+            }
+            b"nGRP" => {
+                let node_id = reader.i32()?;
+                reader.dict()?;
+                let child_count = reader.u32()?;
+                let children = (0..child_count).map(|_| reader.i32()).collect::<Result<_, _>>()?;
+                nodes.insert(node_id, Node::Group { children });
+            }
+            b"nSHP" => {
+                let node_id = reader.i32()?;
+                reader.dict()?;
+                let model_count = reader.u32()?;
+                let mut shape_models = Vec::new();
+                for _ in 0..model_count {
+                    let model = reader.u32()? as usize;
+                    let attributes = reader.dict()?;
+                    if model >= models.len() {
+                        return Err(VoxError::Corrupt);
+                    }
+                    shape_models.push((key_frame(&attributes), model));
+                }
+                nodes.insert(node_id, Node::Shape { models: shape_models });
+            }
+            b"LAYR" => {
+                let layer = reader.i32()?;
+                if reader.dict()?.get("_hidden").map(|h| h == "1").unwrap_or(false) {
+                    hidden_layers.push(layer);
+                }
+            }
+            b"MATL" => {
+                // materials belong to palette indices 1 to 255, the voxels with index i have color id i - 1
+                let index = reader.i32()?;
+                let material = VoxMaterial::from_properties(&reader.dict()?)?;
+                if (1..=255).contains(&index) {
+                    materials.push(((index - 1) as u8, material));
+                }
+            }
+            _ => (),
+        }
+    }
+    for handle in &models {
+        let vox = registry.get_mut(handle).unwrap();
+        for (color_id, material) in &materials {
+            vox.set_material(*color_id, *material);
+        }
+    }
+
+    if nodes.is_empty() {
+        let instances = models
+            .iter()
+            .enumerate()
+            .map(|(model, handle)| {
+                let vox = registry.get(handle).unwrap();
+                VoxInstance {
+                    model,
+                    rotation: IDENTITY,
+                    translation: [vox.x_size as i32 / 2, vox.y_size as i32 / 2, vox.z_size as i32 / 2],
+                }
+            })
+            .collect();
+        return Ok(VoxScene {
+            models,
+            frames: vec![instances],
+        });
+    }
+
+    let frame_count = nodes
+        .values()
+        .flat_map(|node| match node {
+            Node::Transform { frames, .. } => frames.iter().map(|(frame, _, _)| *frame).collect(),
+            Node::Shape { models } => models.iter().map(|(frame, _)| *frame).collect(),
+            Node::Group { .. } => Vec::new(),
+        })
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut frames = Vec::new();
+    for frame in 0..frame_count {
+        let mut instances = Vec::new();
+        collect_instances(
+            &nodes,
+            &hidden_layers,
+            0,
+            0,
+            frame,
+            (IDENTITY, [0, 0, 0]),
+            &mut instances,
+        )?;
+        frames.push(
+            instances
+                .into_iter()
+                .map(|(model, rotation, translation)| VoxInstance {
+                    model,
+                    // MagicaVoxel has z up, like the models the transforms get y and z swapped
+                    rotation: swap_y_and_z(rotation),
+                    translation: [translation[0], translation[2], translation[1]],
+                })
+                .collect(),
+        );
+    }
+    Ok(VoxScene { models, frames })
+}
+
+impl VoxScene {
+    // The shapes of the frame as models in world voxel coordinates. Shapes that are rotated get a new rotated
+    // model, the others use their model as is.
+    pub fn placements(&self, frame: usize, registry: &mut Registry<Vox>) -> Vec<VoxPlacement> {
+        self.frames[frame]
+            .iter()
+            .map(|instance| {
+                let handle = &self.models[instance.model];
+                let vox = registry.get(handle).unwrap();
+                let size = [vox.x_size, vox.y_size, vox.z_size];
+                let center = [size[0] as i32 / 2, size[1] as i32 / 2, size[2] as i32 / 2];
+                let mut position = [0; 3];
+                let mut rotated_size = [0; 3];
+                for (i, row) in instance.rotation.iter().enumerate() {
+                    let j = row.iter().position(|v| *v != 0).unwrap();
+                    rotated_size[i] = size[j];
+                    position[i] = instance.translation[i]
+                        + if row[j] > 0 {
+                            -center[j]
+                        } else {
+                            center[j] + 1 - size[j] as i32
+                        };
+                }
+                if instance.rotation == IDENTITY {
+                    return VoxPlacement {
+                        handle: handle.clone(),
+                        position,
+                    };
+                }
+
+                let mut rotated = Vox::new(rotated_size[0], rotated_size[1], rotated_size[2]);
+                for z in 0..size[2] {
+                    for y in 0..size[1] {
+                        for x in 0..size[0] {
+                            if let Some(color_id) = vox.get(x, y, z) {
+                                let v = [x as i32 - center[0], y as i32 - center[1], z as i32 - center[2]];
+                                let at = |i: usize| {
+                                    let row = instance.rotation[i];
+                                    (row[0] * v[0] + row[1] * v[1] + row[2] * v[2] + instance.translation[i]
+                                        - position[i]) as usize
+                                };
+                                rotated.set(at(0), at(1), at(2), color_id, vox.get_color(color_id));
+                                rotated.set_material(color_id, vox.get_material(color_id));
+                            }
+                        }
+                    }
+                }
+                VoxPlacement {
+                    handle: registry.add(rotated),
+                    position,
+                }
+            })
+            .collect()
+    }
+}
+
+fn key_frame(attributes: &HashMap<String, String>) -> usize {
+    attributes.get("_f").and_then(|f| f.parse().ok()).unwrap_or(0)
+}
+
+fn parse_translation(text: &str) -> Result<[i32; 3], VoxError> {
+    let values = text
+        .split_whitespace()
+        .map(|v| v.parse().map_err(|_| VoxError::Corrupt))
+        .collect::<Result<Vec<i32>, _>>()?;
+    match values.as_slice() {
+        [x, y, z] => Ok([*x, *y, *z]),
+        _ => Err(VoxError::Corrupt),
+    }
+}
+
+// Bits 0-1 and 2-3 are the column of the non zero entry in the first and second row, bits 4, 5 and 6 make the entry
+// of the first, second and third row negative. Both rows having it in the same column is no rotation.
+fn rotation_from_byte(byte: u8) -> Result<Rotation, VoxError> {
+    let first = (byte & 3) as usize;
+    let second = (byte >> 2 & 3) as usize;
+    if first == 3 || second == 3 || first == second {
+        return Err(VoxError::Corrupt);
+    }
+    let third = 3 - first - second;
+    let mut rotation = [[0; 3]; 3];
+    for (row, column) in [first, second, third].iter().enumerate() {
+        rotation[row][*column] = if byte >> (4 + row) & 1 == 1 { -1 } else { 1 };
+    }
+    Ok(rotation)
+}
+
+// The same rotation with the y and z axes swapped, its second and third rows and columns change places.
+fn swap_y_and_z(rotation: Rotation) -> Rotation {
+    [
+        [rotation[0][0], rotation[0][2], rotation[0][1]],
+        [rotation[2][0], rotation[2][2], rotation[2][1]],
+        [rotation[1][0], rotation[1][2], rotation[1][1]],
+    ]
+}
+
+fn multiply(a: &Rotation, b: &Rotation) -> Rotation {
+    let mut result = [[0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(rotation: &Rotation, point: [i32; 3]) -> [i32; 3] {
+    let row = |i: usize| rotation[i][0] * point[0] + rotation[i][1] * point[1] + rotation[i][2] * point[2];
+    [row(0), row(1), row(2)]
+}
+
+// Walks the scene graph from node, a transform applies its key frame at frame to everything below it. depth is the
+// number of nodes above node, a path longer than there are nodes goes around in a circle.
+fn collect_instances(
+    nodes: &HashMap<i32, Node>,
+    hidden_layers: &[i32],
+    node: i32,
+    depth: usize,
+    frame: usize,
+    (rotation, translation): (Rotation, [i32; 3]),
+    instances: &mut Vec<(usize, Rotation, [i32; 3])>,
+) -> Result<(), VoxError> {
+    if depth > nodes.len() {
+        return Err(VoxError::Corrupt);
+    }
+    match nodes.get(&node).ok_or(VoxError::Corrupt)? {
+        Node::Transform {
+            child,
+            layer,
+            hidden,
+            frames,
+        } => {
+            if *hidden || hidden_layers.contains(layer) {
+                return Ok(());
+            }
+            let (node_rotation, node_translation) = frames
+                .iter()
+                .rev()
+                .find(|(key, _, _)| *key <= frame)
+                .or_else(|| frames.first())
+                .map(|(_, r, t)| (*r, *t))
+                .unwrap_or((IDENTITY, [0, 0, 0]));
+            let moved = transform_point(&rotation, node_translation);
+            let transform = (
+                multiply(&rotation, &node_rotation),
+                [
+                    moved[0] + translation[0],
+                    moved[1] + translation[1],
+                    moved[2] + translation[2],
+                ],
+            );
+            collect_instances(nodes, hidden_layers, *child, depth + 1, frame, transform, instances)?;
+        }
+        Node::Group { children } => {
+            for child in children {
+                collect_instances(
+                    nodes,
+                    hidden_layers,
+                    *child,
+                    depth + 1,
+                    frame,
+                    (rotation, translation),
+                    instances,
+                )?;
+            }
+        }
+        Node::Shape { models } => {
+            if let Some((_, model)) = models
+                .iter()
+                .rev()
+                .find(|(key, _)| *key <= frame)
+                .or_else(|| models.first())
+            {
+                instances.push((*model, rotation, translation));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        registry::Registry,
+        vox::{
+            load_vox_scene,
+            scene::{collect_instances, rotation_from_byte, Node, VoxInstance, VoxScene, IDENTITY},
+            Vox, VoxError,
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn rotation_bytes() {
+        assert_eq!(rotation_from_byte(4).unwrap(), IDENTITY);
+        assert_eq!(rotation_from_byte(17).unwrap(), [[0, -1, 0], [1, 0, 0], [0, 0, 1]]);
+        // both rows in the first column, the first row in a fourth column
+        assert!(rotation_from_byte(0).is_err());
+        assert!(rotation_from_byte(7).is_err());
+    }
+
+    #[test]
+    fn circles_in_the_scene_graph_are_corrupt() {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            0,
+            Node::Transform {
+                child: 1,
+                layer: 0,
+                hidden: false,
+                frames: Vec::new(),
+            },
+        );
+        nodes.insert(1, Node::Group { children: vec![2, 0] });
+        nodes.insert(2, Node::Shape { models: vec![(0, 0)] });
+        let mut instances = Vec::new();
+        let result = collect_instances(&nodes, &[], 0, 0, 0, (IDENTITY, [0, 0, 0]), &mut instances);
+        assert!(matches!(result, Err(VoxError::Corrupt)));
+    }
+
+    #[test]
+    fn every_shape_of_the_scene_is_placed() {
+        let mut registry = Registry::new();
+        let scene = load_vox_scene(
+            std::fs::read("res/vox-models/#skyscraper/#skyscraper_03_000.vox")
+                .unwrap()
+                .as_slice(),
+            &mut registry,
+        )
+        .unwrap();
+        assert_eq!(scene.models.len(), 3);
+        assert_eq!(scene.frames.len(), 1);
+        assert_eq!(scene.frames[0].len(), 3);
+        // the group lists its children last to first
+        assert_eq!(
+            scene.frames[0][2],
+            VoxInstance {
+                model: 0,
+                rotation: IDENTITY,
+                translation: [127, 197, 0],
+            }
+        );
+        // the 35 x 35 x 7 (z up) model is centered on its translation
+        let placements = scene.placements(0, &mut registry);
+        assert_eq!(placements[2].position, [110, 194, -17]);
+        assert_eq!(placements[2].handle.id, scene.models[0].id);
+    }
+
+    #[test]
+    fn rotated_shapes_get_a_rotated_model() {
+        let mut registry = Registry::new();
+        let mut vox = Vox::new(3, 1, 2);
+        vox.set(2, 0, 0, 1, [1.0, 0.0, 0.0]);
+        vox.set(0, 0, 1, 2, [0.0, 1.0, 0.0]);
+        let scene = VoxScene {
+            models: vec![registry.add(vox)],
+            frames: vec![vec![VoxInstance {
+                model: 0,
+                // a quarter turn around y, x becomes z and z becomes -x
+                rotation: [[0, 0, 1], [0, 1, 0], [-1, 0, 0]],
+                translation: [10, 0, 0],
+            }]],
+        };
+        let placement = &scene.placements(0, &mut registry)[0];
+        assert_eq!(placement.position, [9, 0, -1]);
+        let rotated = registry.get(&placement.handle).unwrap();
+        assert_eq!([rotated.x_size, rotated.y_size, rotated.z_size], [2, 1, 3]);
+        assert_eq!(rotated.get(0, 0, 0), Some(1));
+        assert_eq!(rotated.get(1, 0, 2), Some(2));
+    }
+}
@@ -1,8 +1,14 @@
-use crate::registry::{Handle, Registry};
+use crate::{
+    registry::{Handle, Registry},
+    vox::VoxMaterial,
+};
 use std::collections::HashMap;
+#[derive(Clone)]
 pub struct Vox {
     data: Vec<Option<u8>>,
     palette: HashMap<u8, [f32; 3]>,
+    // the color ids that are not diffuse
+    materials: HashMap<u8, VoxMaterial>,
     pub x_size: usize,
     pub y_size: usize,
     pub z_size: usize,
@@ -14,6 +20,7 @@ impl Vox {
         Self {
             data: vec![None; z_size * y_size * x_size],
             palette: HashMap::default(),
+            materials: HashMap::default(),
             x_size,
             y_size,
             z_size,
@@ -27,23 +34,72 @@ impl Vox {
         self.palette.insert(color_id, color);
     }
 
+    pub fn remove(&mut self, x: usize, y: usize, z: usize) {
+        self.data[z * self.y_size * self.x_size + y * self.x_size + x] = None;
+    }
+
     pub fn get(&self, x: usize, y: usize, z: usize) -> Option<u8> {
         self.data[z * self.y_size * self.x_size + y * self.x_size + x]
     }
 
+    // color_id if it is free or already has the color and material, otherwise an id with both or a free one. None when
+    // the palette is full and has no id with both.
+    pub fn palette_id(&self, color_id: u8, color: [f32; 3], material: VoxMaterial) -> Option<u8> {
+        let same = |id: u8, existing: [f32; 3]| existing == color && self.get_material(id) == material;
+        match self.palette.get(&color_id) {
+            None => Some(color_id),
+            Some(existing) if same(color_id, *existing) => Some(color_id),
+            Some(_) => self
+                .palette
+                .iter()
+                .find(|(id, existing)| same(**id, **existing))
+                .map(|(id, _)| *id)
+                .or_else(|| (0..=255).find(|id| !self.palette.contains_key(id))),
+        }
+    }
+
+    // The id with the color nearest to color, for a voxel that palette_id has no room for.
+    pub fn closest_id(&self, color: [f32; 3]) -> Option<u8> {
+        let distance = |other: &[f32; 3]| (0..3).map(|i| (other[i] - color[i]).powi(2)).sum::<f32>();
+        self.palette
+            .iter()
+            .min_by(|(_, a), (_, b)| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(id, _)| *id)
+    }
+
     pub fn get_color(&self, color_id: u8) -> [f32; 3] {
         self.palette[&color_id]
     }
+
+    pub fn set_material(&mut self, color_id: u8, material: VoxMaterial) {
+        if material == VoxMaterial::Diffuse {
+            self.materials.remove(&color_id);
+        } else {
+            self.materials.insert(color_id, material);
+        }
+    }
+
+    pub fn get_material(&self, color_id: u8) -> VoxMaterial {
+        self.materials.get(&color_id).copied().unwrap_or(VoxMaterial::Diffuse)
+    }
 }
 
 pub fn load_vox(data: &dot_vox::DotVoxData, registry: &mut Registry<Vox>) -> Handle<Vox> {
-    let model = &data.models[0];
+    registry.add(model_to_vox(&data.models[0], &data.palette))
+}
+
+// MagicaVoxel has z up, the axes are swapped so y is up.
+pub fn model_to_vox(model: &dot_vox::Model, palette: &[u32]) -> Vox {
     let mut vox_model = Vox::new(model.size.x as usize, model.size.z as usize, model.size.y as usize);
     for v in &model.voxels {
-        let color = palette_to_color(data.palette[v.i as usize]);
+        let color = palette_to_color(palette[v.i as usize]);
         vox_model.set(v.x as usize, v.z as usize, v.y as usize, v.i, color);
     }
-    registry.add(vox_model)
+    vox_model
 }
 
 fn palette_to_color(from: u32) -> [f32; 3] {
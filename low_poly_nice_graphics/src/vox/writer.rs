@@ -0,0 +1,194 @@
+use crate::vox::{Vox, VoxError, VoxMaterial};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// the largest model MagicaVoxel opens, voxel coordinates in a file are single bytes
+const MAX_SIZE: usize = 256;
+
+// Writes the model as a .vox file with a SIZE, XYZI and RGBA chunk, and a MATL chunk for every color of it that is not
+// diffuse. Like load_vox reads them color id i becomes palette index i + 1, a model with color id 255 gets a free index
+// for it instead.
+pub fn write_vox(vox: &Vox) -> Result<Vec<u8>, VoxError> {
+    let size = [vox.x_size, vox.y_size, vox.z_size];
+    if size.iter().any(|size| *size > MAX_SIZE) {
+        return Err(VoxError::TooLarge);
+    }
+    let mut voxels = Vec::new();
+    let mut color_ids = BTreeSet::new();
+    for z in 0..vox.z_size {
+        for y in 0..vox.y_size {
+            for x in 0..vox.x_size {
+                if let Some(color_id) = vox.get(x, y, z) {
+                    voxels.push((x, y, z, color_id));
+                    color_ids.insert(color_id);
+                }
+            }
+        }
+    }
+    let mut indices = color_ids
+        .iter()
+        .filter(|color_id| **color_id < 255)
+        .map(|color_id| (*color_id, *color_id + 1))
+        .collect::<HashMap<_, _>>();
+    if color_ids.contains(&255) {
+        let taken = indices.values().copied().collect::<HashSet<_>>();
+        let free = (1..=255)
+            .find(|index| !taken.contains(index))
+            .ok_or(VoxError::TooLarge)?;
+        indices.insert(255, free);
+    }
+
+    let mut chunks = Vec::new();
+    // MagicaVoxel has z up, y and z are swapped back
+    let mut content = Vec::new();
+    for size in [vox.x_size, vox.z_size, vox.y_size].iter() {
+        content.extend_from_slice(&(*size as u32).to_le_bytes());
+    }
+    write_chunk(&mut chunks, b"SIZE", &content);
+
+    let mut content = (voxels.len() as u32).to_le_bytes().to_vec();
+    for (x, y, z, color_id) in voxels {
+        content.extend_from_slice(&[x as u8, z as u8, y as u8, indices[&color_id]]);
+    }
+    write_chunk(&mut chunks, b"XYZI", &content);
+
+    // entry i is the color of index i + 1, unused ones are black
+    let mut palette = [[0, 0, 0, 255]; 256];
+    for (color_id, index) in &indices {
+        let color = vox.get_color(*color_id);
+        let channel = |value: f32| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        palette[*index as usize - 1] = [channel(color[0]), channel(color[1]), channel(color[2]), 255];
+    }
+    write_chunk(&mut chunks, b"RGBA", &palette.concat());
+
+    let mut materials = indices.iter().collect::<Vec<_>>();
+    materials.sort_by_key(|(_, index)| **index);
+    for (color_id, index) in materials {
+        // _weight is what newer versions of MagicaVoxel call the value of every type
+        let properties = match vox.get_material(*color_id) {
+            VoxMaterial::Diffuse => continue,
+            VoxMaterial::Glass { alpha } => {
+                vec![("_type", "_glass".to_string()), ("_weight", (1.0 - alpha).to_string())]
+            }
+            VoxMaterial::Emit { emission, power } => vec![
+                ("_type", "_emit".to_string()),
+                ("_weight", emission.to_string()),
+                ("_flux", power.to_string()),
+            ],
+            VoxMaterial::Metal { metalness, roughness } => vec![
+                ("_type", "_metal".to_string()),
+                ("_weight", metalness.to_string()),
+                ("_rough", roughness.to_string()),
+            ],
+        };
+        let mut content = (*index as i32).to_le_bytes().to_vec();
+        write_dict(&mut content, &properties);
+        write_chunk(&mut chunks, b"MATL", &content);
+    }
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend_from_slice(&150u32.to_le_bytes());
+    bytes.extend_from_slice(b"MAIN");
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&chunks);
+    Ok(bytes)
+}
+
+// id, content size, size of the children (there are none) and content
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+// the count, then every key and value as a length and the bytes of the string, what VoxReader::dict reads
+fn write_dict(bytes: &mut Vec<u8>, pairs: &[(&str, String)]) {
+    bytes.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+    for (key, value) in pairs {
+        for text in [*key, value.as_str()].iter() {
+            bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        registry::Registry,
+        vox::{load_vox_scene, write_vox, Vox, VoxError, VoxMaterial},
+    };
+
+    // loads the only model of a .vox file
+    fn read_back(bytes: &[u8]) -> Vox {
+        let mut registry = Registry::new();
+        let scene = load_vox_scene(bytes, &mut registry).unwrap();
+        assert_eq!(scene.models.len(), 1);
+        registry.registry.remove(&scene.models[0].id).unwrap()
+    }
+
+    fn assert_same(a: &Vox, b: &Vox) {
+        assert_eq!([a.x_size, a.y_size, a.z_size], [b.x_size, b.y_size, b.z_size]);
+        for z in 0..a.z_size {
+            for y in 0..a.y_size {
+                for x in 0..a.x_size {
+                    assert_eq!(a.get(x, y, z).is_some(), b.get(x, y, z).is_some());
+                    if let (Some(a_id), Some(b_id)) = (a.get(x, y, z), b.get(x, y, z)) {
+                        let (a_color, b_color) = (a.get_color(a_id), b.get_color(b_id));
+                        // the file has a byte per channel
+                        assert!((0..3).all(|i| (a_color[i] - b_color[i]).abs() < 1.0 / 255.0));
+                        match (a.get_material(a_id), b.get_material(b_id)) {
+                            (VoxMaterial::Glass { alpha: a_alpha }, VoxMaterial::Glass { alpha: b_alpha }) => {
+                                assert!((a_alpha - b_alpha).abs() < 1e-6)
+                            }
+                            (a_material, b_material) => assert_eq!(a_material, b_material),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn models_of_the_example_files_round_trip() {
+        let mut paths = vec![
+            "res/vox-models/#phantom_mansion/#phantom_mansion.vox".to_string(),
+            "res/vox-models/#treehouse/#treehouse.vox".to_string(),
+        ];
+        for i in 1..=6 {
+            paths.push(format!("res/vox-models/#skyscraper/#skyscraper_0{}_000.vox", i));
+        }
+        for path in paths {
+            let mut registry = Registry::new();
+            let scene = load_vox_scene(std::fs::read(&path).unwrap().as_slice(), &mut registry).unwrap();
+            for handle in &scene.models {
+                let vox = registry.get(handle).unwrap();
+                let written = read_back(&write_vox(vox).unwrap());
+                assert_same(vox, &written);
+            }
+        }
+    }
+
+    #[test]
+    fn color_id_255_and_materials_are_written() {
+        let mut vox = Vox::new(3, 2, 1);
+        vox.set(0, 0, 0, 255, [1.0, 0.0, 0.0]);
+        vox.set(1, 1, 0, 0, [0.0, 0.5, 1.0]);
+        vox.set(2, 1, 0, 7, [0.2, 0.2, 0.2]);
+        vox.set_material(0, VoxMaterial::Glass { alpha: 0.25 });
+        vox.set_material(
+            7,
+            VoxMaterial::Emit {
+                emission: 0.5,
+                power: 2.0,
+            },
+        );
+        let written = read_back(&write_vox(&vox).unwrap());
+        assert_same(&vox, &written);
+        // 255 has no palette index of its own, it gets the first free one
+        assert_eq!(written.get(0, 0, 0), Some(1));
+        assert_eq!(written.get(2, 1, 0), Some(7));
+        assert!(matches!(write_vox(&Vox::new(257, 1, 1)), Err(VoxError::TooLarge)));
+    }
+}
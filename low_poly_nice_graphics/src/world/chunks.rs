@@ -1,9 +1,10 @@
-use crate::{entity::Entity, registry::Handle};
+use crate::{entity::Entity, registry::Handle, renderer::Light};
 
+// What a chunk in view added: the entities of its opaque and its transparent mesh and the lights of its lamps.
 #[derive(Clone)]
 pub struct Chunk {
-    pub entity: Handle<Entity>,
-    pub just_added: bool,
+    pub entities: Vec<Handle<Entity>>,
+    pub lights: Vec<Handle<Light>>,
 }
 
 pub struct Diff {
@@ -32,14 +33,6 @@ impl Chunks {
         }
     }
 
-    pub fn clear_just_added(&mut self) {
-        for chunk in &mut self.chunks {
-            if let Some(chunk) = chunk {
-                chunk.just_added = false;
-            }
-        }
-    }
-
     pub fn range_diff_1d_i32(&self, old: i32, new: i32) -> ([i32; 2], [i32; 2]) {
         let extent = (self.size as i32) / 2;
         let old_min = old - extent;
@@ -124,6 +117,18 @@ impl Chunks {
         self.chunks[z * self.size * self.size + y * self.size + x].clone()
     }
 
+    // Whether the chunk is in view of the current position.
+    pub fn contains(&self, chunk_position: [i32; 3]) -> bool {
+        let extent = self.size as i32 / 2;
+        match self.position {
+            Some(position) => {
+                let center = self.position_to_chunk(position);
+                (0..3).all(|i| (center[i] - extent..center[i] + extent).contains(&chunk_position[i]))
+            }
+            None => false,
+        }
+    }
+
     pub fn set_position(&mut self, position: [f32; 3]) {
         self.previous_position = self.position;
         self.position = Some(position);
@@ -200,6 +205,17 @@ mod tests {
         let x = 0;
     }
 
+    #[test]
+    fn contains_test() {
+        let mut world = Chunks::new(4, 1, 0.1);
+        assert!(!world.contains([0, 0, 0]));
+        world.set_position([0.05, 0.05, 0.05]);
+        assert!(world.contains([-2, -2, -2]));
+        assert!(world.contains([1, 1, 1]));
+        assert!(!world.contains([2, 0, 0]));
+        assert!(!world.contains([0, -3, 0]));
+    }
+
     #[test]
     fn modulo_test() {
         assert_eq!(((i32::MAX / (6 * 2) * 6) + 6i32) % 6, 0);
@@ -0,0 +1,256 @@
+use crate::{generators::Height, vox::Vox};
+use noise::{MultiFractal, NoiseFn, Seedable};
+
+// the size of a voxel in metres
+pub const VOXEL_SIZE: f32 = 0.1;
+
+// Fills the empty voxels of vox, whose first voxel is at origin in world voxel coordinates. That is a chunk or a part
+// of the layer of voxels around one. Runs on the worker threads of World.
+pub trait VoxelGenerator: Send + Sync {
+    fn generate(&self, origin: [i32; 3], vox: &mut Vox);
+}
+
+// Position in metres of the voxel at x, y, z inside vox.
+fn voxel_position(origin: [i32; 3], x: usize, y: usize, z: usize) -> [f32; 3] {
+    [
+        (origin[0] + x as i32) as f32 * VOXEL_SIZE,
+        (origin[1] + y as i32) as f32 * VOXEL_SIZE,
+        (origin[2] + z as i32) as f32 * VOXEL_SIZE,
+    ]
+}
+
+// Red hills where sin(x) * sin(z) is above the voxel, nothing below -5 metres.
+pub struct Sine;
+
+impl VoxelGenerator for Sine {
+    fn generate(&self, origin: [i32; 3], vox: &mut Vox) {
+        for z in 0..vox.z_size {
+            for y in 0..vox.y_size {
+                for x in 0..vox.x_size {
+                    let [x_w, y_w, z_w] = voxel_position(origin, x, y, z);
+                    if y_w > -5.0 && (x_w.sin() * z_w.sin()) > y_w {
+                        vox.set(x, y, z, 255, [1.0, 0.0, 0.0]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Colors of the layers of the ground.
+#[derive(Debug, Copy, Clone)]
+pub struct Biome {
+    pub surface: [f32; 3],
+    pub soil: [f32; 3],
+    pub stone: [f32; 3],
+}
+
+pub const GRASSLAND: Biome = Biome {
+    surface: [0.3, 0.6, 0.2],
+    soil: [0.45, 0.3, 0.15],
+    stone: [0.5, 0.5, 0.5],
+};
+
+pub const DESERT: Biome = Biome {
+    surface: [0.9, 0.8, 0.5],
+    soil: [0.8, 0.65, 0.4],
+    stone: [0.6, 0.45, 0.3],
+};
+
+pub const SNOW: Biome = Biome {
+    surface: [0.95, 0.95, 1.0],
+    soil: [0.55, 0.55, 0.6],
+    stone: [0.4, 0.4, 0.45],
+};
+
+// Picks a biome for every point on the ground from low frequency noise, so neighbouring points mostly share theirs.
+pub struct Biomes {
+    biomes: Vec<Biome>,
+    noise: noise::Fbm,
+}
+
+impl Biomes {
+    pub fn new(biomes: Vec<Biome>, seed: u32) -> Self {
+        assert!(!biomes.is_empty());
+        Self {
+            biomes,
+            noise: noise::Fbm::new().set_seed(seed).set_octaves(2).set_frequency(0.005),
+        }
+    }
+
+    pub fn standard(seed: u32) -> Self {
+        Self::new(vec![GRASSLAND, DESERT, SNOW], seed)
+    }
+
+    // Index and biome at x, z in metres.
+    pub fn biome(&self, x: f32, z: f32) -> (usize, &Biome) {
+        let value = (self.noise.get([x as f64, z as f64]) as f32 + 1.0) / 2.0;
+        let index = ((value * self.biomes.len() as f32) as usize).min(self.biomes.len() - 1);
+        (index, &self.biomes[index])
+    }
+}
+
+// Fills every voxel below the height of a heightfield, with the surface, soil and stone colors of the biome there.
+pub struct Heightfield {
+    height: Box<dyn Height + Send + Sync>,
+    biomes: Biomes,
+    height_scale: f32,
+}
+
+impl Heightfield {
+    pub fn new(height: Box<dyn Height + Send + Sync>, biomes: Biomes) -> Self {
+        Self {
+            height,
+            biomes,
+            height_scale: 1.0,
+        }
+    }
+
+    // Metres per unit of the height function.
+    pub fn with_height_scale(mut self, height_scale: f32) -> Self {
+        self.height_scale = height_scale;
+        self
+    }
+}
+
+// nothing is generated below the floor, in metres
+const FLOOR: f32 = -5.0;
+// depth in metres below the height at which soil starts and then stone, the surface is a single voxel
+const SOIL_DEPTH: f32 = 0.5 * VOXEL_SIZE;
+const STONE_DEPTH: f32 = 5.0 * VOXEL_SIZE;
+
+impl VoxelGenerator for Heightfield {
+    fn generate(&self, origin: [i32; 3], vox: &mut Vox) {
+        for z in 0..vox.z_size {
+            for x in 0..vox.x_size {
+                let [x_w, _, z_w] = voxel_position(origin, x, 0, z);
+                let height = self.height.height(x_w, z_w) * self.height_scale;
+                let (index, biome) = self.biomes.biome(x_w, z_w);
+                for y in 0..vox.y_size {
+                    let y_w = voxel_position(origin, x, y, z)[1];
+                    if y_w <= FLOOR || y_w > height {
+                        continue;
+                    }
+                    // every biome has its own three color ids
+                    let depth = height - y_w;
+                    let (layer, color) = if depth < SOIL_DEPTH {
+                        (0, biome.surface)
+                    } else if depth < STONE_DEPTH {
+                        (1, biome.soil)
+                    } else {
+                        (2, biome.stone)
+                    };
+                    vox.set(x, y, z, (index * 3 + layer + 1) as u8, color);
+                }
+            }
+        }
+    }
+}
+
+// higher thresholds make fewer and narrower caves
+const CAVE_THRESHOLD: f32 = 0.3;
+
+// Carves caves out of the voxels of another generator where 3D noise is above CAVE_THRESHOLD.
+pub struct Caves {
+    terrain: Box<dyn VoxelGenerator>,
+    noise: noise::Fbm,
+}
+
+impl Caves {
+    pub fn new(terrain: Box<dyn VoxelGenerator>, seed: u32) -> Self {
+        Self {
+            terrain,
+            noise: noise::Fbm::new().set_seed(seed).set_octaves(3).set_frequency(0.15),
+        }
+    }
+}
+
+impl VoxelGenerator for Caves {
+    fn generate(&self, origin: [i32; 3], vox: &mut Vox) {
+        self.terrain.generate(origin, vox);
+        if !vox.touched {
+            return;
+        }
+        for z in 0..vox.z_size {
+            for y in 0..vox.y_size {
+                for x in 0..vox.x_size {
+                    if vox.get(x, y, z).is_none() {
+                        continue;
+                    }
+                    let [x_w, y_w, z_w] = voxel_position(origin, x, y, z);
+                    if self.noise.get([x_w as f64, y_w as f64, z_w as f64]) as f32 > CAVE_THRESHOLD {
+                        vox.remove(x, y, z);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generators::Zero,
+        vox::Vox,
+        world::{generator::GRASSLAND, Biomes, Caves, Heightfield, VoxelGenerator},
+    };
+    use std::collections::HashSet;
+
+    fn generate(generator: &dyn VoxelGenerator, chunk: (i32, i32, i32)) -> Vox {
+        let mut vox = Vox::new(32, 32, 32);
+        generator.generate([chunk.0 * 32, chunk.1 * 32, chunk.2 * 32], &mut vox);
+        vox
+    }
+
+    fn count(vox: &Vox) -> usize {
+        let mut count = 0;
+        for z in 0..vox.z_size {
+            for y in 0..vox.y_size {
+                for x in 0..vox.x_size {
+                    count += vox.get(x, y, z).is_some() as usize;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn flat_heightfield_has_layers() {
+        let flat = Heightfield::new(Box::new(Zero), Biomes::new(vec![GRASSLAND], 0));
+        let surface = generate(&flat, (0, 0, 0));
+        // only the voxels at height 0 are in the chunk above the ground
+        assert_eq!(count(&surface), 32 * 32);
+        assert_eq!(surface.get(3, 0, 3), Some(1));
+        assert_eq!(surface.get_color(1), GRASSLAND.surface);
+        let below = generate(&flat, (0, -1, 0));
+        assert_eq!(count(&below), 32 * 32 * 32);
+        assert_eq!(below.get(3, 31, 3), Some(2));
+        assert_eq!(below.get(3, 0, 3), Some(3));
+        // the floor is at -5 metres
+        let deep = count(&generate(&flat, (0, -2, 0)));
+        assert!(deep > 0 && deep < 32 * 32 * 32);
+        assert!(!generate(&flat, (0, -3, 0)).touched);
+    }
+
+    #[test]
+    fn caves_depend_on_the_seed() {
+        let caves = |seed| Caves::new(Box::new(Heightfield::new(Box::new(Zero), Biomes::standard(0))), seed);
+        let a = generate(&caves(1), (0, -1, 0));
+        let carved = count(&a);
+        assert!(carved > 0 && carved < 32 * 32 * 32);
+        assert_eq!(count(&generate(&caves(1), (0, -1, 0))), carved);
+        assert_ne!(count(&generate(&caves(2), (0, -1, 0))), carved);
+    }
+
+    #[test]
+    fn biomes_change_over_distance() {
+        let biomes = Biomes::standard(7);
+        let mut seen = HashSet::new();
+        for z in 0..40 {
+            for x in 0..40 {
+                seen.insert(biomes.biome(x as f32 * 50.0 + 0.37, z as f32 * 50.0 + 0.61).0);
+            }
+        }
+        assert!(seen.len() > 1);
+    }
+}
@@ -1,7 +1,12 @@
 mod chunks;
-mod heightmap_loader;
+mod generator;
+mod raycast;
+mod region;
+mod workers;
 mod world;
 
 pub use chunks::Chunks;
-pub use heightmap_loader::Heightmap;
+pub use generator::{Biomes, Caves, Heightfield, Sine, VoxelGenerator, VOXEL_SIZE};
+pub use raycast::{voxel_raycast, VoxelHit};
+pub use region::{RegionError, RegionStorage};
 pub use world::World;
@@ -0,0 +1,133 @@
+// The first solid voxel along a ray, in voxel coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelHit {
+    pub voxel: [i32; 3],
+    // the side of the voxel the ray came in through, zero when the ray starts inside the voxel
+    pub normal: [i32; 3],
+    // the cell in front of that side, where a voxel placed against the hit one goes
+    pub empty: [i32; 3],
+    pub distance: f32,
+}
+
+// Walks the voxels along the ray one cell at a time (Amanatides and Woo) until is_solid is true or the ray is longer
+// than max_distance. Voxel v covers v up to v + 1, origin and max_distance are in voxels as well. A ray that is not
+// finite would never end, it hits nothing.
+pub fn voxel_raycast<E>(
+    origin: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+    mut is_solid: impl FnMut([i32; 3]) -> Result<bool, E>,
+) -> Result<Option<VoxelHit>, E> {
+    let length = (0..3).map(|i| direction[i] * direction[i]).sum::<f32>().sqrt();
+    if length == 0.0 || !length.is_finite() || !max_distance.is_finite() || origin.iter().any(|o| !o.is_finite()) {
+        return Ok(None);
+    }
+    let direction = [direction[0] / length, direction[1] / length, direction[2] / length];
+    let mut voxel = [
+        origin[0].floor() as i32,
+        origin[1].floor() as i32,
+        origin[2].floor() as i32,
+    ];
+    if is_solid(voxel)? {
+        return Ok(Some(VoxelHit {
+            voxel,
+            normal: [0, 0, 0],
+            empty: voxel,
+            distance: 0.0,
+        }));
+    }
+
+    let mut step = [0; 3];
+    // distance along the ray to the next boundary on every axis and between two boundaries
+    let mut next = [f32::INFINITY; 3];
+    let mut delta = [f32::INFINITY; 3];
+    for i in 0..3 {
+        if direction[i] > 0.0 {
+            step[i] = 1;
+            next[i] = (voxel[i] as f32 + 1.0 - origin[i]) / direction[i];
+            delta[i] = 1.0 / direction[i];
+        } else if direction[i] < 0.0 {
+            step[i] = -1;
+            next[i] = (voxel[i] as f32 - origin[i]) / direction[i];
+            delta[i] = -1.0 / direction[i];
+        }
+    }
+
+    loop {
+        let axis = if next[0] < next[1] && next[0] < next[2] {
+            0
+        } else if next[1] < next[2] {
+            1
+        } else {
+            2
+        };
+        let distance = next[axis];
+        if distance > max_distance {
+            return Ok(None);
+        }
+        voxel[axis] += step[axis];
+        next[axis] += delta[axis];
+        if is_solid(voxel)? {
+            let mut normal = [0; 3];
+            normal[axis] = -step[axis];
+            let mut empty = voxel;
+            empty[axis] -= step[axis];
+            return Ok(Some(VoxelHit {
+                voxel,
+                normal,
+                empty,
+                distance,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{voxel_raycast, VoxelHit};
+
+    fn cast(origin: [f32; 3], direction: [f32; 3], max_distance: f32, solid: fn([i32; 3]) -> bool) -> Option<VoxelHit> {
+        voxel_raycast(origin, direction, max_distance, |voxel| Ok::<bool, ()>(solid(voxel))).unwrap()
+    }
+
+    #[test]
+    fn ray_along_an_axis_hits_the_near_side() {
+        let hit = cast([0.5, 0.5, 0.5], [2.0, 0.0, 0.0], 10.0, |v| v == [3, 0, 0]).unwrap();
+        assert_eq!(hit.voxel, [3, 0, 0]);
+        assert_eq!(hit.normal, [-1, 0, 0]);
+        assert_eq!(hit.empty, [2, 0, 0]);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+        assert_eq!(cast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 2.0, |v| v == [3, 0, 0]), None);
+        assert_eq!(cast([0.5, 0.5, 0.5], [0.0, 0.0, 0.0], 10.0, |_| true), None);
+    }
+
+    #[test]
+    fn rays_that_are_not_finite_hit_nothing() {
+        assert_eq!(cast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], f32::INFINITY, |_| false), None);
+        assert_eq!(cast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], f32::NAN, |_| false), None);
+        assert_eq!(cast([0.5, 0.5, 0.5], [f32::NAN, 1.0, 0.0], 10.0, |_| false), None);
+        assert_eq!(cast([0.5, 0.5, 0.5], [f32::INFINITY, 0.0, 0.0], 10.0, |_| false), None);
+        assert_eq!(cast([f32::NAN, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0, |_| false), None);
+    }
+
+    #[test]
+    fn diagonal_ray_hits_the_ground_from_above() {
+        let hit = cast([0.3, 5.5, -0.5], [1.0, -1.0, -0.5], 100.0, |v| v[1] < 0).unwrap();
+        assert_eq!(hit.voxel[1], -1);
+        assert_eq!(hit.normal, [0, 1, 0]);
+        assert_eq!(hit.empty, [hit.voxel[0], 0, hit.voxel[2]]);
+        // the hit is on the plane y = 0
+        let scale = hit.distance / (1.0f32 + 1.0 + 0.25).sqrt();
+        assert!((5.5 - scale).abs() < 1e-4);
+        assert_eq!(hit.voxel[0], (0.3 + scale).floor() as i32);
+        assert_eq!(hit.voxel[2], (-0.5 - 0.5 * scale).floor() as i32);
+    }
+
+    #[test]
+    fn ray_starting_inside_a_voxel_hits_it() {
+        let hit = cast([-0.5, 0.5, 0.5], [0.0, 1.0, 0.0], 10.0, |v| v == [-1, 0, 0]).unwrap();
+        assert_eq!(hit.voxel, [-1, 0, 0]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.distance, 0.0);
+    }
+}
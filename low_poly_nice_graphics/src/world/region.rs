@@ -0,0 +1,409 @@
+use crate::{
+    vox::{Vox, VoxMaterial},
+    world::workers::WorkersStopped,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::TryInto,
+    path::{Path, PathBuf},
+};
+
+// Regions are REGION_SIZE x REGION_SIZE x REGION_SIZE chunks stored in one file.
+const REGION_SIZE: i32 = 8;
+const SLOTS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+const MAGIC: &[u8; 4] = b"LPNR";
+const VERSION: u32 = 1;
+// magic, version and an (offset, length) per slot
+const HEADER_SIZE: usize = 8 + SLOTS * 8;
+// voxels along an axis of a saved chunk, the chunks of World are far smaller. A file with more is corrupt.
+const MAX_CHUNK_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub enum RegionError {
+    Io(std::io::Error),
+    Corrupt,
+    // a copied region with more than 256 colors and materials, what a .vox model can not have
+    TooManyColors,
+    // the worker threads that mesh the chunks are gone
+    WorkersStopped,
+}
+
+impl From<WorkersStopped> for RegionError {
+    fn from(_: WorkersStopped) -> RegionError {
+        RegionError::WorkersStopped
+    }
+}
+
+impl From<std::io::Error> for RegionError {
+    fn from(e: std::io::Error) -> RegionError {
+        RegionError::Io(e)
+    }
+}
+
+// Keeps the voxels of chunks in region files in a directory. A region file starts with a header with the offset and
+// length of every chunk in it, a length of 0 means the chunk is not stored.
+pub struct RegionStorage {
+    directory: PathBuf,
+}
+
+impl RegionStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn region_and_slot(chunk: (i32, i32, i32)) -> ((i32, i32, i32), usize) {
+        let region = (
+            chunk.0.div_euclid(REGION_SIZE),
+            chunk.1.div_euclid(REGION_SIZE),
+            chunk.2.div_euclid(REGION_SIZE),
+        );
+        let (x, y, z) = (
+            chunk.0.rem_euclid(REGION_SIZE),
+            chunk.1.rem_euclid(REGION_SIZE),
+            chunk.2.rem_euclid(REGION_SIZE),
+        );
+        (region, ((z * REGION_SIZE + y) * REGION_SIZE + x) as usize)
+    }
+
+    fn region_path(&self, region: (i32, i32, i32)) -> PathBuf {
+        self.directory
+            .join(format!("r.{}.{}.{}.region", region.0, region.1, region.2))
+    }
+
+    // None if the chunk was never saved.
+    pub fn load_chunk(&self, chunk: (i32, i32, i32)) -> Result<Option<Vox>, RegionError> {
+        let (region, slot) = RegionStorage::region_and_slot(chunk);
+        match &read_region(&self.region_path(region))?[slot] {
+            Some(bytes) => Ok(Some(decode_chunk(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Like load_chunk for every chunk, but every region file is read once.
+    pub fn load_chunks(&self, chunks: &[(i32, i32, i32)]) -> Result<Vec<Option<Vox>>, RegionError> {
+        let mut regions = HashMap::new();
+        chunks
+            .iter()
+            .map(|chunk| {
+                let (region, slot) = RegionStorage::region_and_slot(*chunk);
+                let slots = match regions.entry(region) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(read_region(&self.region_path(region))?),
+                };
+                slots[slot].as_ref().map(|bytes| decode_chunk(bytes)).transpose()
+            })
+            .collect()
+    }
+
+    // Every region file is read and written once, chunks already in it that are not saved again are kept.
+    pub fn save_chunks(&self, chunks: &[((i32, i32, i32), &Vox)]) -> Result<(), RegionError> {
+        let mut regions = HashMap::new();
+        for (chunk, vox) in chunks {
+            let (region, slot) = RegionStorage::region_and_slot(*chunk);
+            regions.entry(region).or_insert_with(Vec::new).push((slot, *vox));
+        }
+        std::fs::create_dir_all(&self.directory)?;
+        for (region, chunks) in regions {
+            let path = self.region_path(region);
+            let mut slots = read_region(&path)?;
+            for (slot, vox) in chunks {
+                slots[slot] = Some(encode_chunk(vox));
+            }
+            write_region(&path, &slots)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_region(path: &Path) -> Result<Vec<Option<Vec<u8>>>, RegionError> {
+    if !path.exists() {
+        return Ok(vec![None; SLOTS]);
+    }
+    let bytes = std::fs::read(path)?;
+    if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC || read_u32(&bytes, 4)? != VERSION {
+        return Err(RegionError::Corrupt);
+    }
+    (0..SLOTS)
+        .map(|slot| {
+            let offset = read_u32(&bytes, 8 + slot * 8)? as usize;
+            let length = read_u32(&bytes, 12 + slot * 8)? as usize;
+            if length == 0 {
+                Ok(None)
+            } else {
+                let blob = bytes.get(offset..offset + length).ok_or(RegionError::Corrupt)?;
+                Ok(Some(blob.to_vec()))
+            }
+        })
+        .collect()
+}
+
+fn write_region(path: &Path, slots: &[Option<Vec<u8>>]) -> Result<(), RegionError> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    let mut data = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    for slot in slots {
+        let (offset, length) = match slot {
+            Some(bytes) => (HEADER_SIZE + data.len(), bytes.len()),
+            None => (0, 0),
+        };
+        header.extend_from_slice(&(offset as u32).to_le_bytes());
+        header.extend_from_slice(&(length as u32).to_le_bytes());
+        if let Some(bytes) = slot {
+            data.extend_from_slice(bytes);
+        }
+    }
+    header.extend_from_slice(&data);
+    // written next to the region first, a crash while saving leaves the old region intact
+    let temporary = path.with_extension("region.tmp");
+    std::fs::write(&temporary, header)?;
+    std::fs::rename(temporary, path)?;
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, RegionError> {
+    let word = bytes.get(at..at + 4).ok_or(RegionError::Corrupt)?;
+    Ok(u32::from_le_bytes(word.try_into().unwrap()))
+}
+
+fn read_f32(bytes: &[u8], at: usize) -> Result<f32, RegionError> {
+    Ok(f32::from_bits(read_u32(bytes, at)?))
+}
+
+// The size, then the colors of the color ids that are used, then runs of (length, 0 for empty or color id + 1) in
+// the order of the voxels in Vox. Chunks with materials other than diffuse end with those, chunks saved before there
+// were materials end after the runs.
+pub fn encode_chunk(vox: &Vox) -> Vec<u8> {
+    let mut palette = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for z in 0..vox.z_size {
+        for y in 0..vox.y_size {
+            for x in 0..vox.x_size {
+                let voxel = vox.get(x, y, z);
+                if let Some(color_id) = voxel {
+                    if !palette.contains(&color_id) {
+                        palette.push(color_id);
+                    }
+                }
+                let value = voxel.map(|color_id| color_id as u16 + 1).unwrap_or(0);
+                match runs.last_mut() {
+                    Some((length, last)) if *last == value && *length < u16::MAX => *length += 1,
+                    _ => runs.push((1, value)),
+                }
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    for size in [vox.x_size, vox.y_size, vox.z_size].iter() {
+        bytes.extend_from_slice(&(*size as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+    for color_id in palette.iter() {
+        bytes.push(*color_id);
+        for channel in vox.get_color(*color_id).iter() {
+            bytes.extend_from_slice(&channel.to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (length, value) in runs {
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    let materials = palette
+        .iter()
+        .map(|color_id| (*color_id, vox.get_material(*color_id)))
+        .filter(|(_, material)| *material != VoxMaterial::Diffuse)
+        .collect::<Vec<_>>();
+    if !materials.is_empty() {
+        bytes.extend_from_slice(&(materials.len() as u32).to_le_bytes());
+        for (color_id, material) in materials {
+            // color id, kind and two values
+            let (kind, a, b) = match material {
+                VoxMaterial::Diffuse => (0, 0.0, 0.0),
+                VoxMaterial::Glass { alpha } => (1, alpha, 0.0),
+                VoxMaterial::Emit { emission, power } => (2, emission, power),
+                VoxMaterial::Metal { metalness, roughness } => (3, metalness, roughness),
+            };
+            bytes.push(color_id);
+            bytes.push(kind);
+            bytes.extend_from_slice(&a.to_le_bytes());
+            bytes.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> Result<Vox, RegionError> {
+    let x_size = read_u32(bytes, 0)? as usize;
+    let y_size = read_u32(bytes, 4)? as usize;
+    let z_size = read_u32(bytes, 8)? as usize;
+    if [x_size, y_size, z_size].iter().any(|size| *size > MAX_CHUNK_SIZE) {
+        return Err(RegionError::Corrupt);
+    }
+    let mut at = 12;
+    let palette_length = read_u32(bytes, at)? as usize;
+    at += 4;
+    let mut palette = HashMap::new();
+    for _ in 0..palette_length {
+        let color_id = *bytes.get(at).ok_or(RegionError::Corrupt)?;
+        let color = [
+            read_f32(bytes, at + 1)?,
+            read_f32(bytes, at + 5)?,
+            read_f32(bytes, at + 9)?,
+        ];
+        palette.insert(color_id, color);
+        at += 13;
+    }
+    let run_count = read_u32(bytes, at)? as usize;
+    at += 4;
+
+    // the runs have to be there before the voxels are allocated
+    let total = x_size * y_size * z_size;
+    if run_count > (bytes.len() - at) / 4 || total > run_count * u16::MAX as usize {
+        return Err(RegionError::Corrupt);
+    }
+    let mut vox = Vox::new(x_size, y_size, z_size);
+    let mut index = 0;
+    for _ in 0..run_count {
+        let run = bytes.get(at..at + 4).ok_or(RegionError::Corrupt)?;
+        let length = u16::from_le_bytes([run[0], run[1]]) as usize;
+        let value = u16::from_le_bytes([run[2], run[3]]);
+        at += 4;
+        if index + length > total {
+            return Err(RegionError::Corrupt);
+        }
+        if value != 0 {
+            let color_id = (value - 1) as u8;
+            let color = *palette.get(&color_id).ok_or(RegionError::Corrupt)?;
+            for i in index..index + length {
+                vox.set(i % x_size, i / x_size % y_size, i / (x_size * y_size), color_id, color);
+            }
+        }
+        index += length;
+    }
+    if index != total {
+        return Err(RegionError::Corrupt);
+    }
+    if at < bytes.len() {
+        let material_count = read_u32(bytes, at)? as usize;
+        at += 4;
+        for _ in 0..material_count {
+            let header = bytes.get(at..at + 2).ok_or(RegionError::Corrupt)?;
+            let (a, b) = (read_f32(bytes, at + 2)?, read_f32(bytes, at + 6)?);
+            let material = match header[1] {
+                0 => VoxMaterial::Diffuse,
+                1 => VoxMaterial::Glass { alpha: a },
+                2 => VoxMaterial::Emit { emission: a, power: b },
+                3 => VoxMaterial::Metal {
+                    metalness: a,
+                    roughness: b,
+                },
+                _ => return Err(RegionError::Corrupt),
+            };
+            vox.set_material(header[0], material);
+            at += 10;
+        }
+    }
+    Ok(vox)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        vox::{Vox, VoxMaterial},
+        world::{
+            region::{decode_chunk, encode_chunk},
+            RegionError, RegionStorage,
+        },
+    };
+
+    fn test_chunk(color_id: u8) -> Vox {
+        let mut vox = Vox::new(4, 3, 2);
+        vox.set(0, 0, 0, color_id, [0.25, 0.5, 0.75]);
+        vox.set(3, 2, 1, 9, [1.0, 0.0, 0.0]);
+        vox.set(1, 1, 1, 9, [1.0, 0.0, 0.0]);
+        vox
+    }
+
+    fn assert_same(a: &Vox, b: &Vox) {
+        assert_eq!([a.x_size, a.y_size, a.z_size], [b.x_size, b.y_size, b.z_size]);
+        for z in 0..a.z_size {
+            for y in 0..a.y_size {
+                for x in 0..a.x_size {
+                    assert_eq!(a.get(x, y, z), b.get(x, y, z));
+                    if let Some(color_id) = a.get(x, y, z) {
+                        assert_eq!(a.get_color(color_id), b.get_color(color_id));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip_through_run_lengths() {
+        let vox = test_chunk(3);
+        let bytes = encode_chunk(&vox);
+        // 3 used voxels with empty space between them are 5 runs
+        assert_eq!(bytes.len(), 16 + 2 * 13 + 4 + 5 * 4);
+        assert_same(&vox, &decode_chunk(&bytes).unwrap());
+        assert!(decode_chunk(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn sizes_that_do_not_fit_the_runs_are_corrupt() {
+        let bytes = encode_chunk(&test_chunk(3));
+        let with_size = |x_size: u32| [&x_size.to_le_bytes(), &bytes[4..]].concat();
+        assert!(decode_chunk(&with_size(4)).is_ok());
+        assert!(matches!(decode_chunk(&with_size(u32::MAX)), Err(RegionError::Corrupt)));
+        // small enough to allocate, but the 5 runs cover only 4 x 3 x 2 voxels
+        assert!(matches!(decode_chunk(&with_size(200)), Err(RegionError::Corrupt)));
+    }
+
+    #[test]
+    fn materials_are_saved_after_the_runs() {
+        let mut vox = test_chunk(3);
+        vox.set_material(3, VoxMaterial::Glass { alpha: 0.25 });
+        vox.set_material(
+            9,
+            VoxMaterial::Emit {
+                emission: 0.5,
+                power: 2.0,
+            },
+        );
+        let bytes = encode_chunk(&vox);
+        assert_eq!(bytes.len(), 16 + 2 * 13 + 4 + 5 * 4 + 4 + 2 * 10);
+        let decoded = decode_chunk(&bytes).unwrap();
+        assert_same(&vox, &decoded);
+        assert_eq!(decoded.get_material(3), VoxMaterial::Glass { alpha: 0.25 });
+        assert_eq!(decoded.get_material(9), vox.get_material(9));
+        // without the materials it is still a chunk, only a diffuse one
+        assert_eq!(
+            decode_chunk(&bytes[..16 + 2 * 13 + 4 + 5 * 4]).unwrap().get_material(3),
+            VoxMaterial::Diffuse
+        );
+        assert!(decode_chunk(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn saved_chunks_are_loaded_from_their_region() {
+        let directory = std::env::temp_dir().join(format!("lpng_regions_{}", std::process::id()));
+        let storage = RegionStorage::new(&directory);
+        let (a, b, c) = (test_chunk(1), test_chunk(2), test_chunk(3));
+        storage.save_chunks(&[((0, 0, 0), &a), ((-1, 5, 7), &b)]).unwrap();
+        // another chunk in the same region keeps the ones saved before
+        storage.save_chunks(&[((7, 7, 7), &c)]).unwrap();
+        assert_same(&a, &storage.load_chunk((0, 0, 0)).unwrap().unwrap());
+        assert_same(&b, &storage.load_chunk((-1, 5, 7)).unwrap().unwrap());
+        assert_same(&c, &storage.load_chunk((7, 7, 7)).unwrap().unwrap());
+        assert!(storage.load_chunk((1, 0, 0)).unwrap().is_none());
+        assert!(storage.load_chunk((8, 0, 0)).unwrap().is_none());
+        let loaded = storage.load_chunks(&[(7, 7, 7), (8, 0, 0), (-1, 5, 7)]).unwrap();
+        assert_same(&c, loaded[0].as_ref().unwrap());
+        assert!(loaded[1].is_none());
+        assert_same(&b, loaded[2].as_ref().unwrap());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
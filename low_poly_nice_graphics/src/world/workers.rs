@@ -0,0 +1,81 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+// Threads that run work on the jobs sent to them, the results are picked up with try_recv. A job that work panics on
+// comes back as Err with its key, so it can be sent again. The threads stop once the Workers are dropped.
+pub struct Workers<J, K, R> {
+    jobs: Sender<J>,
+    results: Receiver<Result<R, K>>,
+}
+
+// Every worker thread is gone, sent jobs would never come back.
+#[derive(Debug)]
+pub struct WorkersStopped;
+
+impl<J: Send + 'static, K: Send + 'static, R: Send + 'static> Workers<J, K, R> {
+    pub fn new(count: usize, key: fn(&J) -> K, work: fn(J) -> R) -> Self {
+        let (jobs, job_receiver) = channel::<J>();
+        let (result_sender, results) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..count {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::spawn(move || loop {
+                // the lock is only held while waiting for a job, not while working on it
+                let job = job_receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => {
+                        let key = key(&job);
+                        // the thread outlives the panic, work has no state that a panic can leave broken
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| work(job))).map_err(|_| key);
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+        Self { jobs, results }
+    }
+
+    pub fn send(&self, job: J) -> Result<(), WorkersStopped> {
+        self.jobs.send(job).map_err(|_| WorkersStopped)
+    }
+
+    pub fn try_recv(&self) -> Option<Result<R, K>> {
+        self.results.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::workers::Workers;
+
+    fn halve(job: u32) -> u32 {
+        assert_ne!(job, 3, "3 can not be halved");
+        job / 2
+    }
+
+    #[test]
+    fn panicking_jobs_come_back_as_errors() {
+        let workers = Workers::new(1, |job: &u32| *job, halve);
+        for job in [4, 3, 8].iter() {
+            workers.send(*job).unwrap();
+        }
+        let mut results = Vec::new();
+        while results.len() < 3 {
+            if let Some(result) = workers.try_recv() {
+                results.push(result);
+            }
+        }
+        // the one thread is still there after the panic
+        assert_eq!(results, vec![Ok(2), Err(3), Ok(4)]);
+    }
+}
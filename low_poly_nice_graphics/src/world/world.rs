@@ -1,14 +1,29 @@
 use crate::{
     entity::Entity,
-    mesh::{Mesh, Plane, Vertex},
+    mesh::{simplify_levels, Lod, Mesh, Vertex},
+    physics::{Body, BodyStatus, CollisionShape, Physics, TriMesh},
     registry::{Handle, Registry},
+    renderer::{Light, PointProperties, MAX_NR_OF_POINT_LIGHTS},
     transform::Transform,
     vox,
-    vox::Vox,
-    world::{chunks::Chunk, Chunks},
+    vox::{Vox, VoxPlacement},
+    world::{
+        chunks::Chunk, voxel_raycast, workers::Workers, Chunks, RegionError, RegionStorage, Sine, VoxelGenerator,
+        VoxelHit, VOXEL_SIZE,
+    },
 };
 use glam::Vec3;
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+// Chunks with more triangles than this get simpler meshes for when they are far away.
+const LOD_MIN_TRIANGLES: usize = 256;
+// The largest groups of emissive voxels in a chunk that become point lights.
+const MAX_CHUNK_LIGHTS: usize = 8;
 
 struct Descriptor {
     pub u: usize,
@@ -32,8 +47,28 @@ impl Descriptor {
     }
 }
 
+// ambient occlusion of a corner from 0 (fully occluded) to 3 (open) mapped to a light factor
+const AMBIENT_OCCLUSION_CURVE: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+// Visible side of one voxel. Only faces with the same color and the same occlusion at each corner are
+// merged, corners ordered (v, w), (v + 1, w), (v, w + 1), (v + 1, w + 1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Face {
+    color_id: u8,
+    ambient_occlusion: [u8; 4],
+}
+
+// classic voxel corner occlusion from the two voxels along the edges and the one on the diagonal
+fn corner_ambient_occlusion(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - side1 as u8 - side2 as u8 - corner as u8
+    }
+}
+
 struct Mask {
-    data: Vec<Option<u8>>,
+    data: Vec<Option<Face>>,
     size_x: usize,
     size_y: usize,
 }
@@ -47,50 +82,383 @@ impl Mask {
         }
     }
 
-    pub fn set(&mut self, x: usize, y: usize, color_id: Option<u8>) {
+    pub fn set(&mut self, x: usize, y: usize, face: Option<Face>) {
         assert!(x < self.size_x);
         assert!(y < self.size_y);
-        self.data[y * self.size_x + x] = color_id;
+        self.data[y * self.size_x + x] = face;
     }
 
-    pub fn get(&mut self, x: usize, y: usize) -> Option<u8> {
+    pub fn get(&mut self, x: usize, y: usize) -> Option<Face> {
         self.data[y * self.size_x + x]
     }
 }
 
+// (model, offset in the model, offset in the chunk, size) of the part of a model inside a chunk
+type ChunkPart = (Arc<Vox>, [usize; 3], [usize; 3], [usize; 3]);
+
+// Chunks that get_voxel, raycast and copy_region keep around after loading them, the older ones are dropped.
+const MAX_LOADED_CHUNKS: usize = 64;
+// Chunks are generated and meshed by this many threads.
+const WORKER_THREADS: usize = 4;
+// Chunks handed to the workers at once, the others wait so that chunks closer to the player can still go first.
+const MAX_JOBS_IN_FLIGHT: usize = 64;
+
+// The offsets of the chunks around a chunk, those it shares a side, an edge or a corner with.
+const NEIGHBOURS: [[i32; 3]; 26] = [
+    [-1, -1, -1],
+    [0, -1, -1],
+    [1, -1, -1],
+    [-1, 0, -1],
+    [0, 0, -1],
+    [1, 0, -1],
+    [-1, 1, -1],
+    [0, 1, -1],
+    [1, 1, -1],
+    [-1, -1, 0],
+    [0, -1, 0],
+    [1, -1, 0],
+    [-1, 0, 0],
+    [1, 0, 0],
+    [-1, 1, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [-1, -1, 1],
+    [0, -1, 1],
+    [1, -1, 1],
+    [-1, 0, 1],
+    [0, 0, 1],
+    [1, 0, 1],
+    [-1, 1, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+
+struct ChunkJob {
+    id: u64,
+    chunk: (i32, i32, i32),
+    chunk_size: usize,
+    // saved or edited voxels, without them the chunk is generated
+    voxels: Option<Vox>,
+    // (position, voxels) of the parts of the layer around the chunk whose voxels are known, the rest is generated
+    borders: Vec<([usize; 3], Vox)>,
+    generator: Arc<dyn VoxelGenerator>,
+    // the parts of the models in the chunk and in the generated neighbours, with the offset of their chunk
+    parts: Vec<([i32; 3], ChunkPart)>,
+}
+
+struct ChunkResult {
+    id: u64,
+    chunk: (i32, i32, i32),
+    voxels: Vox,
+    // None if no opaque face of the chunk is visible
+    mesh: Option<ChunkMesh>,
+    // the faces of the glass voxels, None if there are none
+    transparent: Option<ChunkMesh>,
+    // (position in metres inside the chunk, color) of the point lights of the emissive voxels
+    lights: Vec<([f32; 3], [f32; 3])>,
+}
+
+struct ChunkMesh {
+    mesh: Mesh,
+    // levels of detail with their screen sizes
+    lods: Vec<(Mesh, f32)>,
+    collider: TriMesh,
+}
+
+impl ChunkMesh {
+    fn new(mesh: Mesh, lods: Vec<(Mesh, f32)>) -> Self {
+        // the quads of the greedy mesh do not share vertices, the collider has the same triangles
+        let collider = TriMesh {
+            vertices: mesh.vertices.iter().map(|vertex| vertex.position).collect(),
+            indices: mesh.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect(),
+        };
+        Self { mesh, lods, collider }
+    }
+}
+
 pub struct World {
-    entities: Vec<(Handle<Vox>, [usize; 3], [i32; 3])>,
-    chunk_entity_map: HashMap<(i32, i32, i32), (usize, [usize; 3], [usize; 3], [usize; 3])>,
+    // the parts of the models in every chunk in the order they were added, later ones are drawn over earlier ones
+    chunk_entity_map: HashMap<(i32, i32, i32), Vec<ChunkPart>>,
+    // voxels of the chunks in view that are not empty and of every chunk that was edited
+    chunk_voxels: HashMap<(i32, i32, i32), Vox>,
+    // chunks that were read while they were not in memory, until they change
+    loaded_chunks: RefCell<HashMap<(i32, i32, i32), Vox>>,
+    // chunks changed by set_voxel or add since they were saved, without storage they stay in memory
+    edited_chunks: HashSet<(i32, i32, i32)>,
+    // chunks that need a new mesh, once they are in view
+    dirty_chunks: HashSet<(i32, i32, i32)>,
+    // chunks in view waiting for a worker
+    queued_chunks: HashSet<(i32, i32, i32)>,
+    // the job whose result is wanted for every chunk a worker is busy with, results of older jobs are dropped
+    pending_chunks: HashMap<(i32, i32, i32), u64>,
+    next_job: u64,
+    jobs_in_flight: usize,
+    // a job comes back as its chunk and id when building it panicked
+    workers: Workers<ChunkJob, ((i32, i32, i32), u64), ChunkResult>,
+    generator: Arc<dyn VoxelGenerator>,
     chunk_size: usize,
     chunks: Chunks,
+    storage: Option<RegionStorage>,
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
-            entities: vec![],
             chunk_entity_map: HashMap::new(),
+            chunk_voxels: HashMap::new(),
+            loaded_chunks: RefCell::new(HashMap::new()),
+            edited_chunks: HashSet::new(),
+            dirty_chunks: HashSet::new(),
+            queued_chunks: HashSet::new(),
+            pending_chunks: HashMap::new(),
+            next_job: 0,
+            jobs_in_flight: 0,
+            workers: Workers::new(WORKER_THREADS, |job: &ChunkJob| (job.chunk, job.id), build_chunk),
+            generator: Arc::new(Sine),
             chunk_size: 32,
-            chunks: Chunks::new(16, 32, 0.1),
+            chunks: Chunks::new(16, 32, VOXEL_SIZE),
+            storage: None,
+        }
+    }
+
+    // Edited chunks are saved in the storage when they go out of view and loaded from it instead of generated.
+    pub fn with_storage(mut self, storage: RegionStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    // Fills the chunks that were never saved, the sine hills by default.
+    pub fn with_generator(mut self, generator: impl VoxelGenerator + 'static) -> Self {
+        self.generator = Arc::new(generator);
+        self
+    }
+
+    // The number of chunks along each axis that are kept around the position given to generate_around. Only the tests
+    // use fewer chunks than the default.
+    #[cfg(test)]
+    pub fn with_view_size(mut self, view_size: usize) -> Self {
+        self.chunks = Chunks::new(view_size, self.chunk_size, VOXEL_SIZE);
+        self
+    }
+
+    // Saves every edited chunk that is still in memory.
+    pub fn save(&mut self) -> Result<(), RegionError> {
+        if let Some(storage) = &self.storage {
+            let chunks = self
+                .edited_chunks
+                .iter()
+                .filter_map(|chunk| self.chunk_voxels.get(chunk).map(|vox| (*chunk, vox)))
+                .collect::<Vec<_>>();
+            storage.save_chunks(&chunks)?;
+            self.edited_chunks.clear();
         }
+        Ok(())
+    }
+
+    // True once the chunk with the position and the one below it have their meshes and colliders, so that what
+    // stands there has ground to stand on.
+    pub fn is_generated(&self, position: [f32; 3]) -> bool {
+        let [x, y, z] = self.chunks.position_to_chunk(position);
+        [(x, y, z), (x, y - 1, z)].iter().all(|chunk| self.is_meshed(*chunk))
+    }
+
+    // In view and not waiting for a worker. The voxels of meshed chunks are in chunk_voxels unless they are empty.
+    fn is_meshed(&self, chunk: (i32, i32, i32)) -> bool {
+        self.chunks.contains([chunk.0, chunk.1, chunk.2])
+            && !self.queued_chunks.contains(&chunk)
+            && !self.pending_chunks.contains_key(&chunk)
+    }
+
+    // True while chunks in view are still waiting for their mesh.
+    #[cfg(test)]
+    pub fn is_generating(&self) -> bool {
+        !self.queued_chunks.is_empty() || !self.pending_chunks.is_empty()
     }
 
     fn chunk_number_and_offset(start: i32, chunk_size: usize) -> (i32, usize) {
-        if start >= 0 {
-            let chunk_number = start / chunk_size as i32;
-            let offset = start as usize % chunk_size;
-            (chunk_number, offset)
-        } else {
-            let chunk_number = (start + 1) / chunk_size as i32 - 1;
-            let offset = chunk_size - (-start as usize % (chunk_size + 1));
-            (chunk_number, offset)
+        let chunk_number = start.div_euclid(chunk_size as i32);
+        let offset = start.rem_euclid(chunk_size as i32) as usize;
+        (chunk_number, offset)
+    }
+
+    // The chunk a voxel (in world voxel coordinates) is in and where it is inside that chunk.
+    fn voxel_chunk(&self, position: [i32; 3]) -> ((i32, i32, i32), [usize; 3]) {
+        let (x, x_offset) = World::chunk_number_and_offset(position[0], self.chunk_size);
+        let (y, y_offset) = World::chunk_number_and_offset(position[1], self.chunk_size);
+        let (z, z_offset) = World::chunk_number_and_offset(position[2], self.chunk_size);
+        ((x, y, z), [x_offset, y_offset, z_offset])
+    }
+
+    // Reads the voxels of the chunk, None if it is meshed and empty. Chunks that are not in memory are loaded once and
+    // kept for the next reads.
+    fn read_chunk<T>(&self, chunk: (i32, i32, i32), read: impl FnOnce(&Vox) -> T) -> Result<Option<T>, RegionError> {
+        if let Some(vox) = self.chunk_voxels.get(&chunk) {
+            return Ok(Some(read(vox)));
+        }
+        if self.is_meshed(chunk) {
+            return Ok(None);
+        }
+        let mut loaded_chunks = self.loaded_chunks.borrow_mut();
+        if !loaded_chunks.contains_key(&chunk) {
+            if loaded_chunks.len() >= MAX_LOADED_CHUNKS {
+                loaded_chunks.clear();
+            }
+            let vox = self.load_voxels(chunk)?;
+            loaded_chunks.insert(chunk, vox);
+        }
+        Ok(Some(read(&loaded_chunks[&chunk])))
+    }
+
+    // Color id and color of the voxel, None if it is empty.
+    pub fn get_voxel(&self, position: [i32; 3]) -> Result<Option<(u8, [f32; 3])>, RegionError> {
+        let (chunk, offset) = self.voxel_chunk(position);
+        let voxel = self.read_chunk(chunk, |vox| {
+            vox.get(offset[0], offset[1], offset[2])
+                .map(|color_id| (color_id, vox.get_color(color_id)))
+        })?;
+        Ok(voxel.flatten())
+    }
+
+    // Sets or, with None, clears the voxel. Its chunk gets a new mesh in the next generate_around, and so do the
+    // neighbours it touches, their meshes look at the voxels on the sides of the chunk as well.
+    pub fn set_voxel(&mut self, position: [i32; 3], voxel: Option<(u8, [f32; 3])>) -> Result<(), RegionError> {
+        let (chunk, offset) = self.voxel_chunk(position);
+        let loaded = self.loaded_chunks.get_mut().remove(&chunk);
+        if !self.chunk_voxels.contains_key(&chunk) {
+            let vox = match loaded {
+                Some(vox) => vox,
+                None => self.load_voxels(chunk)?,
+            };
+            self.chunk_voxels.insert(chunk, vox);
+        }
+        let vox = self.chunk_voxels.get_mut(&chunk).unwrap();
+        match voxel {
+            Some((color_id, color)) => vox.set(offset[0], offset[1], offset[2], color_id, color),
+            None => vox.remove(offset[0], offset[1], offset[2]),
+        }
+        self.edited_chunks.insert(chunk);
+        let last = self.chunk_size - 1;
+        let around = |i: usize| -((offset[i] == 0) as i32)..=(offset[i] == last) as i32;
+        for z in around(2) {
+            for y in around(1) {
+                for x in around(0) {
+                    self.dirty_chunks.insert((chunk.0 + x, chunk.1 + y, chunk.2 + z));
+                }
+            }
         }
+        Ok(())
     }
 
-    pub fn add(&mut self, handle: Handle<Vox>, position: [i32; 3], registry: &Registry<Vox>) {
-        let vox = registry.get(&handle).unwrap();
-        self.entities
-            .push((handle, [vox.x_size, vox.y_size, vox.z_size], position));
+    // The first voxel along the ray from origin, in metres, that is not empty. The distance of the hit is in metres,
+    // its voxels are in world voxel coordinates like those of get_voxel and set_voxel.
+    pub fn raycast(
+        &self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        max_distance: f32,
+    ) -> Result<Option<VoxelHit>, RegionError> {
+        let hit = voxel_raycast(
+            [origin[0] / VOXEL_SIZE, origin[1] / VOXEL_SIZE, origin[2] / VOXEL_SIZE],
+            direction,
+            max_distance / VOXEL_SIZE,
+            |position| -> Result<bool, RegionError> {
+                let (chunk, offset) = self.voxel_chunk(position);
+                let solid = self.read_chunk(chunk, |vox| vox.get(offset[0], offset[1], offset[2]).is_some())?;
+                Ok(solid.unwrap_or(false))
+            },
+        )?;
+        Ok(hit.map(|hit| VoxelHit {
+            distance: hit.distance * VOXEL_SIZE,
+            ..hit
+        }))
+    }
+
+    // Sets every voxel from min up to and including max.
+    pub fn fill_box(&mut self, min: [i32; 3], max: [i32; 3], voxel: Option<(u8, [f32; 3])>) -> Result<(), RegionError> {
+        for z in min[2]..=max[2] {
+            for y in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    self.set_voxel([x, y, z], voxel)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The voxels from min up to and including max as a model, with their colors and materials, e.g. to write them
+    // with write_vox.
+    pub fn copy_region(&self, min: [i32; 3], max: [i32; 3]) -> Result<Vox, RegionError> {
+        let size = |i: usize| (max[i] - min[i] + 1).max(0) as usize;
+        let size = [size(0), size(1), size(2)];
+        let mut region = Vox::new(size[0], size[1], size[2]);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let (chunk, offset) = self.voxel_chunk([min[0] + x as i32, min[1] + y as i32, min[2] + z as i32]);
+                    let voxel = self.read_chunk(chunk, |vox| {
+                        vox.get(offset[0], offset[1], offset[2])
+                            .map(|color_id| (color_id, vox.get_color(color_id), vox.get_material(color_id)))
+                    })?;
+                    if let Some((color_id, color, material)) = voxel.flatten() {
+                        // chunks can use the same color ids for different colors
+                        let color_id = region
+                            .palette_id(color_id, color, material)
+                            .ok_or(RegionError::TooManyColors)?;
+                        region.set(x, y, z, color_id, color);
+                        region.set_material(color_id, material);
+                    }
+                }
+            }
+        }
+        Ok(region)
+    }
+
+    // The saved voxels of the chunk, or newly generated ones if it was never saved.
+    fn load_voxels(&self, chunk: (i32, i32, i32)) -> Result<Vox, RegionError> {
+        if let Some(storage) = &self.storage {
+            if let Some(vox) = storage.load_chunk(chunk)? {
+                return Ok(vox);
+            }
+        }
+        Ok(generate_voxels(
+            chunk,
+            self.chunk_size,
+            &*self.generator,
+            self.chunk_parts(chunk),
+        ))
+    }
+
+    fn chunk_parts(&self, chunk: (i32, i32, i32)) -> &[ChunkPart] {
+        self.chunk_entity_map.get(&chunk).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // Adds the placed models of a vox scene with the scene origin at position.
+    pub fn add_scene(
+        &mut self,
+        placements: &[VoxPlacement],
+        position: [i32; 3],
+        registry: &Registry<Vox>,
+    ) -> Result<(), RegionError> {
+        for placement in placements {
+            let at = placement.position;
+            self.add(
+                placement.handle.clone(),
+                [position[0] + at[0], position[1] + at[1], position[2] + at[2]],
+                registry,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Saved chunks the model overlaps are loaded to put it on top of their voxels, so it can fail to read them.
+    pub fn add(
+        &mut self,
+        handle: Handle<Vox>,
+        position: [i32; 3],
+        registry: &Registry<Vox>,
+    ) -> Result<(), RegionError> {
+        // the world keeps its own copy, the workers read it while they generate chunks
+        let vox = Arc::new(registry.get(&handle).unwrap().clone());
         let x_min = position[0];
         let y_min = position[1];
         let z_min = position[2];
@@ -109,15 +477,33 @@ impl World {
                 let mut source_x_offset = 0;
                 while x_size != 0 {
                     let x_current_size = std::cmp::min(x_size, self.chunk_size - target_x_offset);
-                    self.chunk_entity_map.insert(
-                        (x_number, y_number, z_number),
-                        (
-                            self.entities.len() - 1,
-                            [source_x_offset, source_y_offset, source_z_offset],
-                            [target_x_offset, target_y_offset, target_z_offset],
-                            [x_current_size, y_current_size, z_current_size],
-                        ),
+                    let part = (
+                        vox.clone(),
+                        [source_x_offset, source_y_offset, source_z_offset],
+                        [target_x_offset, target_y_offset, target_z_offset],
+                        [x_current_size, y_current_size, z_current_size],
                     );
+                    let chunk = (x_number, y_number, z_number);
+                    self.loaded_chunks.get_mut().remove(&chunk);
+                    // edited and saved chunks get the model on top of their voxels, others are generated again
+                    let edited = self.edited_chunks.contains(&chunk);
+                    let voxels = match self.chunk_voxels.remove(&chunk) {
+                        Some(vox) if edited => Some(vox),
+                        _ if edited => Some(self.load_voxels(chunk)?),
+                        _ => match &self.storage {
+                            Some(storage) => storage.load_chunk(chunk)?,
+                            None => None,
+                        },
+                    };
+                    if let Some(mut vox_to_gen) = voxels {
+                        add_part(&mut vox_to_gen, &part, [0, 0, 0]);
+                        self.chunk_voxels.insert(chunk, vox_to_gen);
+                        self.edited_chunks.insert(chunk);
+                    }
+                    if self.chunks.contains([chunk.0, chunk.1, chunk.2]) {
+                        self.dirty_chunks.insert(chunk);
+                    }
+                    self.chunk_entity_map.entry(chunk).or_default().push(part);
                     x_number += 1;
                     source_x_offset += x_current_size;
                     target_x_offset = 0;
@@ -133,105 +519,558 @@ impl World {
             target_z_offset = 0;
             z_size -= z_current_size;
         }
+        Ok(())
     }
 
-    pub fn generate_chunk(
+    // The job for a worker that meshes the chunk, with the voxels it has now if they are in memory or saved.
+    // The neighbours give the job the voxels of theirs that touch the chunk, those in memory and the saved ones.
+    fn chunk_job(&mut self, chunk: (i32, i32, i32)) -> Result<ChunkJob, RegionError> {
+        let neighbour = |offset: [i32; 3]| (chunk.0 + offset[0], chunk.1 + offset[1], chunk.2 + offset[2]);
+        // meshed chunks that are not in memory are empty, the others can be saved and are loaded at once
+        let unknown = std::iter::once(chunk)
+            .filter(|chunk| !self.chunk_voxels.contains_key(chunk))
+            .chain(
+                NEIGHBOURS
+                    .iter()
+                    .map(|offset| neighbour(*offset))
+                    .filter(|chunk| !self.chunk_voxels.contains_key(chunk) && !self.is_meshed(*chunk)),
+            )
+            .collect::<Vec<_>>();
+        let mut saved = match &self.storage {
+            Some(storage) => unknown
+                .iter()
+                .cloned()
+                .zip(storage.load_chunks(&unknown)?)
+                .collect::<HashMap<_, _>>(),
+            None => HashMap::new(),
+        };
+        let voxels = match self.chunk_voxels.get(&chunk) {
+            Some(vox) => Some(vox.clone()),
+            None => saved.remove(&chunk).flatten(),
+        };
+
+        let n = self.chunk_size;
+        let mut borders = Vec::new();
+        let mut parts = self
+            .chunk_parts(chunk)
+            .iter()
+            .map(|part| ([0, 0, 0], part.clone()))
+            .collect::<Vec<_>>();
+        for offset in NEIGHBOURS.iter() {
+            let (position, from, size) = border_layer(*offset, n);
+            let neighbour = neighbour(*offset);
+            let border = match self.chunk_voxels.get(&neighbour) {
+                Some(vox) => Some(cut(vox, from, size)),
+                None if self.is_meshed(neighbour) => Some(Vox::new(size[0], size[1], size[2])),
+                None => saved
+                    .get(&neighbour)
+                    .and_then(Option::as_ref)
+                    .map(|vox| cut(vox, from, size)),
+            };
+            match border {
+                Some(border) => borders.push((position, border)),
+                None => parts.extend(self.chunk_parts(neighbour).iter().map(|part| (*offset, part.clone()))),
+            }
+        }
+        self.next_job += 1;
+        Ok(ChunkJob {
+            id: self.next_job,
+            chunk,
+            chunk_size: n,
+            voxels,
+            borders,
+            generator: self.generator.clone(),
+            parts,
+        })
+    }
+
+    // Keeps the voxels of the chunk and adds an entity with a collider for each of its meshes and the lights of its
+    // emissive voxels.
+    fn place_chunk(
         &mut self,
-        registry: &Registry<Vox>,
-        chunk: (i32, i32, i32),
+        result: ChunkResult,
         meshes: &mut Registry<Mesh>,
         entities: &mut Registry<Entity>,
+        physics: &mut Physics,
+        lights: &mut Registry<Light>,
     ) -> Option<Chunk> {
-        let mut vox_to_gen = Vox::new(self.chunk_size, self.chunk_size, self.chunk_size);
-        for z in 0..self.chunk_size {
-            for y in 0..self.chunk_size {
-                for x in 0..self.chunk_size {
-                    let x_w = chunk.0 as f32 * self.chunk_size as f32 * 0.1 + x as f32 * 0.1;
-                    let y_w = chunk.1 as f32 * self.chunk_size as f32 * 0.1 + y as f32 * 0.1;
-                    let z_w = chunk.2 as f32 * self.chunk_size as f32 * 0.1 + z as f32 * 0.1;
-                    if y_w > -5.0 && ((x_w as f32).sin() * (z_w as f32).sin()) > y_w {
-                        vox_to_gen.set(x, y, z, 255, [1.0, 0.0, 0.0]);
-                    }
-                }
-            }
+        let chunk = result.chunk;
+        // empty chunks are not kept, while in view get_voxel knows they are empty
+        if result.voxels.touched {
+            self.chunk_voxels.entry(chunk).or_insert(result.voxels);
         }
-        if let Some((vox_id, source_offset, target_offset, size)) = &self.chunk_entity_map.get(&chunk) {
-            let (handle, _, _) = &self.entities[*vox_id];
-            let vox = registry.get(handle).unwrap();
-            for z in 0..size[2] {
-                for y in 0..size[1] {
-                    for x in 0..size[0] {
-                        if let Some(color_id) =
-                            vox.get(source_offset[0] + x, source_offset[1] + y, source_offset[2] + z)
-                        {
-                            let color = vox.get_color(color_id);
-                            vox_to_gen.set(
-                                target_offset[0] + x,
-                                target_offset[1] + y,
-                                target_offset[2] + z,
-                                color_id,
-                                color,
-                            );
-                        }
-                    }
-                }
+        let translation = Vec3::new(
+            chunk.0 as f32 * self.chunk_size as f32 * VOXEL_SIZE,
+            chunk.1 as f32 * self.chunk_size as f32 * VOXEL_SIZE,
+            chunk.2 as f32 * self.chunk_size as f32 * VOXEL_SIZE,
+        );
+        let mut placed = Chunk {
+            entities: Vec::new(),
+            lights: Vec::new(),
+        };
+        for ChunkMesh {
+            mut mesh,
+            lods,
+            collider,
+        } in result.mesh.into_iter().chain(result.transparent)
+        {
+            for (level, screen_size) in lods {
+                mesh.lods.push(Lod {
+                    mesh_handle: meshes.add(level),
+                    screen_size,
+                });
             }
-        }
-        let mesh = greedy_mesh(vox_to_gen);
-        if let Some(mesh) = mesh {
             let mesh_handle = meshes.add(mesh);
-            Some(Chunk {
-                entity: entities.add(Entity {
-                    mesh_handle,
-                    collision_shape: None,
-                    transform: Transform::from_translation(Vec3::new(
-                        chunk.0 as f32 * self.chunk_size as f32 * 0.1,
-                        chunk.1 as f32 * self.chunk_size as f32 * 0.1,
-                        chunk.2 as f32 * self.chunk_size as f32 * 0.1,
-                    )),
+            let entity = entities.add(Entity {
+                mesh_handle,
+                material_handle: None,
+                collision_shape: Some(CollisionShape {
+                    body_status: BodyStatus::Static,
+                    body: Body::TriMesh(collider),
                 }),
-                just_added: true,
-            })
-        } else {
+                transform: Transform::from_translation(translation),
+            });
+            physics.register(entity.clone(), entities);
+            placed.entities.push(entity);
+        }
+        // chunks nearer to the player are placed first, further ones go without lights once the renderer is full
+        let point_lights = lights
+            .registry
+            .values()
+            .filter(|light| matches!(light, Light::Point(_)))
+            .count();
+        for (position, color) in result
+            .lights
+            .into_iter()
+            .take(MAX_NR_OF_POINT_LIGHTS.saturating_sub(point_lights))
+        {
+            let position = translation + Vec3::from(position);
+            placed.lights.push(
+                lights.add(Light::Point(
+                    PointProperties::new([position.x, position.y, position.z, 1.0])
+                        .with_color(color)
+                        // lamps are a few voxels big, they only light what is close to them
+                        .with_attenuation(1.0, 0.7, 1.8),
+                )),
+            );
+        }
+        if placed.entities.is_empty() && placed.lights.is_empty() {
             None
+        } else {
+            Some(placed)
         }
     }
 
+    // Generates and meshes the chunk right away instead of on a worker, so the tests need not wait for one.
+    #[cfg(test)]
+    pub fn generate_chunk(
+        &mut self,
+        chunk: (i32, i32, i32),
+        meshes: &mut Registry<Mesh>,
+        entities: &mut Registry<Entity>,
+        physics: &mut Physics,
+        lights: &mut Registry<Light>,
+    ) -> Result<Option<Chunk>, RegionError> {
+        let job = self.chunk_job(chunk)?;
+        Ok(self.place_chunk(build_chunk(job), meshes, entities, physics, lights))
+    }
+
+    // The chunk gets a new mesh from a worker, a result of a job sent before is dropped.
+    fn queue_chunk(&mut self, chunk: (i32, i32, i32)) {
+        self.pending_chunks.remove(&chunk);
+        self.queued_chunks.insert(chunk);
+    }
+
+    // Queues the chunks that came into view and the edited ones, adds the meshes the workers finished and sends them
+    // the queued chunks nearest to the position. The old mesh of an edited chunk stays until its new one is done.
     pub fn generate_around(
         &mut self,
-        registry: &Registry<Vox>,
         position: [f32; 3],
         meshes: &mut Registry<Mesh>,
         entities: &mut Registry<Entity>,
-    ) {
-        self.chunks.clear_just_added();
+        physics: &mut Physics,
+        lights: &mut Registry<Light>,
+    ) -> Result<(), RegionError> {
         self.chunks.set_position(position);
         let diff = self.chunks.range_diff();
+        // the added ranges overlap, chunks in more than one of them are only queued once
+        let mut added_chunks = HashSet::new();
         for added in diff.added.iter() {
             for z in added[2].clone() {
                 for y in added[1].clone() {
                     for x in added[0].clone() {
-                        if let Some(previous_chunk) = self.chunks.get_chunk([x, y, z]) {
-                            if !previous_chunk.just_added {
-                                if let Some(previous_entity) = entities.get(&previous_chunk.entity) {
-                                    meshes.remove(previous_entity.mesh_handle.clone());
-                                    entities.remove(previous_chunk.entity.clone());
-                                }
-                                let chunk = self.generate_chunk(registry, (x, y, z), meshes, entities);
-                                self.chunks.set_chunk([x, y, z], chunk);
+                        if added_chunks.insert((x, y, z)) {
+                            if let Some(previous_chunk) = self.chunks.get_chunk([x, y, z]) {
+                                remove_chunk(&previous_chunk, meshes, entities, physics, lights);
                             }
-                        } else {
-                            let chunk = self.generate_chunk(registry, (x, y, z), meshes, entities);
-                            self.chunks.set_chunk([x, y, z], chunk);
+                            self.chunks.set_chunk([x, y, z], None);
+                            self.queue_chunk((x, y, z));
                         }
                     }
                 }
             }
         }
+        if diff
+            .added
+            .iter()
+            .any(|added| added.iter().all(|range| !range.is_empty()))
+        {
+            // with storage edited chunks out of view are saved and dropped as well
+            let out_of_view = self
+                .chunk_voxels
+                .keys()
+                .filter(|chunk| !self.chunks.contains([chunk.0, chunk.1, chunk.2]))
+                .filter(|chunk| self.storage.is_some() || !self.edited_chunks.contains(chunk))
+                .cloned()
+                .collect::<Vec<_>>();
+            if let Some(storage) = &self.storage {
+                let unsaved = out_of_view
+                    .iter()
+                    .filter(|chunk| self.edited_chunks.contains(chunk))
+                    .map(|chunk| (*chunk, &self.chunk_voxels[chunk]))
+                    .collect::<Vec<_>>();
+                storage.save_chunks(&unsaved)?;
+            }
+            for chunk in out_of_view {
+                self.chunk_voxels.remove(&chunk);
+                self.edited_chunks.remove(&chunk);
+            }
+        }
+
+        // only the edited chunks in view are meshed again, the others are when they come into view
+        for (x, y, z) in self.dirty_chunks.drain().collect::<Vec<_>>() {
+            if self.chunks.contains([x, y, z]) {
+                self.queue_chunk((x, y, z));
+            }
+        }
+
+        while let Some(result) = self.workers.try_recv() {
+            self.jobs_in_flight -= 1;
+            let result = match result {
+                Ok(result) => result,
+                Err((chunk, id)) => {
+                    // tried again, unless the chunk was queued again already
+                    if self.pending_chunks.get(&chunk) == Some(&id) {
+                        self.queue_chunk(chunk);
+                    }
+                    continue;
+                }
+            };
+            let chunk = result.chunk;
+            if self.pending_chunks.get(&chunk) != Some(&result.id) {
+                continue;
+            }
+            self.pending_chunks.remove(&chunk);
+            if self.chunks.contains([chunk.0, chunk.1, chunk.2]) {
+                if let Some(previous_chunk) = self.chunks.get_chunk([chunk.0, chunk.1, chunk.2]) {
+                    remove_chunk(&previous_chunk, meshes, entities, physics, lights);
+                }
+                let placed = self.place_chunk(result, meshes, entities, physics, lights);
+                self.chunks.set_chunk([chunk.0, chunk.1, chunk.2], placed);
+            }
+        }
+
+        let center = self.chunks.position_to_chunk(position);
+        let chunks = &self.chunks;
+        self.queued_chunks
+            .retain(|chunk| chunks.contains([chunk.0, chunk.1, chunk.2]));
+        let mut nearest = self.queued_chunks.iter().cloned().collect::<Vec<_>>();
+        nearest.sort_by_key(|chunk| {
+            let (x, y, z) = (chunk.0 - center[0], chunk.1 - center[1], chunk.2 - center[2]);
+            x * x + y * y + z * z
+        });
+        for chunk in nearest
+            .into_iter()
+            .take(MAX_JOBS_IN_FLIGHT.saturating_sub(self.jobs_in_flight))
+        {
+            let job = self.chunk_job(chunk)?;
+            self.queued_chunks.remove(&chunk);
+            self.pending_chunks.insert(chunk, job.id);
+            self.workers.send(job)?;
+            self.jobs_in_flight += 1;
+        }
+        Ok(())
     }
 }
 
-fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
+// Copies the voxels of the part over the ones in the chunk, empty voxels of the model leave the chunk as it is. With
+// a shift the part goes that far from where it is in its own chunk, what ends up outside of vox_to_gen is left out.
+fn add_part(vox_to_gen: &mut Vox, part: &ChunkPart, shift: [i32; 3]) {
+    let (vox, source_offset, target_offset, size) = part;
+    let target_size = [vox_to_gen.x_size, vox_to_gen.y_size, vox_to_gen.z_size];
+    let start = |i: usize| target_offset[i] as i32 + shift[i];
+    let inside = |i: usize| {
+        let from = (-start(i)).max(0) as usize;
+        let to = (target_size[i] as i32 - start(i)).max(0) as usize;
+        from.min(size[i])..to.min(size[i])
+    };
+    let at = |i: usize, value: usize| (start(i) + value as i32) as usize;
+    for z in inside(2) {
+        for y in inside(1) {
+            for x in inside(0) {
+                if let Some(color_id) = vox.get(source_offset[0] + x, source_offset[1] + y, source_offset[2] + z) {
+                    // models from different files use the same color ids for different colors
+                    set_from(vox_to_gen, [at(0, x), at(1, y), at(2, z)], vox, color_id);
+                }
+            }
+        }
+    }
+}
+
+// Sets the voxel of target at at to color_id of vox, with its color and material. With a full palette target has no
+// id left for them, the voxel gets the closest color target has and keeps the material of that.
+fn set_from(target: &mut Vox, at: [usize; 3], vox: &Vox, color_id: u8) {
+    let color = vox.get_color(color_id);
+    let material = vox.get_material(color_id);
+    match target.palette_id(color_id, color, material) {
+        Some(color_id) => {
+            target.set(at[0], at[1], at[2], color_id, color);
+            target.set_material(color_id, material);
+        }
+        None => {
+            if let Some(closest) = target.closest_id(color) {
+                target.set(at[0], at[1], at[2], closest, target.get_color(closest));
+            }
+        }
+    }
+}
+
+// The voxels of vox from at up to at + size with their colors and materials.
+fn cut(vox: &Vox, at: [usize; 3], size: [usize; 3]) -> Vox {
+    let mut cut = Vox::new(size[0], size[1], size[2]);
+    for z in 0..size[2] {
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                if let Some(color_id) = vox.get(at[0] + x, at[1] + y, at[2] + z) {
+                    cut.set(x, y, z, color_id, vox.get_color(color_id));
+                    cut.set_material(color_id, vox.get_material(color_id));
+                }
+            }
+        }
+    }
+    cut
+}
+
+// Puts the voxels of vox in target at at, also the empty ones.
+fn paste(target: &mut Vox, vox: &Vox, at: [usize; 3]) {
+    for z in 0..vox.z_size {
+        for y in 0..vox.y_size {
+            for x in 0..vox.x_size {
+                match vox.get(x, y, z) {
+                    Some(color_id) => set_from(target, [at[0] + x, at[1] + y, at[2] + z], vox, color_id),
+                    None => target.remove(at[0] + x, at[1] + y, at[2] + z),
+                }
+            }
+        }
+    }
+}
+
+// The generated voxels of the chunk with the parts of the added vox models in it.
+fn generate_voxels(
+    chunk: (i32, i32, i32),
+    chunk_size: usize,
+    generator: &dyn VoxelGenerator,
+    parts: &[ChunkPart],
+) -> Vox {
+    let mut vox_to_gen = Vox::new(chunk_size, chunk_size, chunk_size);
+    let size = chunk_size as i32;
+    generator.generate([chunk.0 * size, chunk.1 * size, chunk.2 * size], &mut vox_to_gen);
+    for part in parts {
+        add_part(&mut vox_to_gen, part, [0, 0, 0]);
+    }
+    vox_to_gen
+}
+
+// Where the layer of the neighbour at offset goes in the chunk with its border, where that layer starts in the
+// neighbour and its size.
+fn border_layer(offset: [i32; 3], chunk_size: usize) -> ([usize; 3], [usize; 3], [usize; 3]) {
+    let mut position = [0; 3];
+    let mut from = [0; 3];
+    let mut size = [1; 3];
+    for i in 0..3 {
+        match offset[i] {
+            -1 => from[i] = chunk_size - 1,
+            0 => {
+                position[i] = 1;
+                size[i] = chunk_size;
+            }
+            _ => position[i] = chunk_size + 1,
+        }
+    }
+    (position, from, size)
+}
+
+// Runs on the workers, generates the voxels if the job has none, meshes them and finds their lights. The mesh looks at
+// a layer of the voxels around the chunk as well, so that the faces and corners on its sides match its neighbours.
+fn build_chunk(job: ChunkJob) -> ChunkResult {
+    let ChunkJob {
+        id,
+        chunk,
+        chunk_size,
+        voxels,
+        borders,
+        generator,
+        parts,
+    } = job;
+    let n = chunk_size as i32;
+    // the generated voxels at position in the chunk with its border, cut keeps only the colors that are still used
+    let generate = |position: [usize; 3], size: [usize; 3]| {
+        let mut generated = Vox::new(size[0], size[1], size[2]);
+        let origin = |i: usize, chunk: i32| chunk * n - 1 + position[i] as i32;
+        generator.generate(
+            [origin(0, chunk.0), origin(1, chunk.1), origin(2, chunk.2)],
+            &mut generated,
+        );
+        cut(&generated, [0, 0, 0], size)
+    };
+    let voxels = voxels.unwrap_or_else(|| {
+        let mut voxels = generate([1, 1, 1], [chunk_size; 3]);
+        for (_, part) in parts.iter().filter(|(offset, _)| *offset == [0, 0, 0]) {
+            add_part(&mut voxels, part, [0, 0, 0]);
+        }
+        voxels
+    });
+    // only the layers around the chunk that no neighbour gave are generated
+    let mut bordered = Vox::new(chunk_size + 2, chunk_size + 2, chunk_size + 2);
+    for offset in NEIGHBOURS.iter() {
+        let (position, _, size) = border_layer(*offset, chunk_size);
+        if borders.iter().all(|(given, _)| *given != position) {
+            paste(&mut bordered, &generate(position, size), position);
+        }
+    }
+    for (offset, part) in parts.iter().filter(|(offset, _)| *offset != [0, 0, 0]) {
+        add_part(
+            &mut bordered,
+            part,
+            [offset[0] * n + 1, offset[1] * n + 1, offset[2] * n + 1],
+        );
+    }
+    paste(&mut bordered, &voxels, [1, 1, 1]);
+    for (position, border) in &borders {
+        paste(&mut bordered, border, *position);
+    }
+    let mesh = greedy_mesh(&bordered, false)
+        .filter(|mesh| !mesh.indices.is_empty())
+        .map(|mesh| {
+            let triangles = mesh.indices.len() / 3;
+            let lods = if triangles > LOD_MIN_TRIANGLES {
+                simplify_levels(&mesh, &[(triangles / 2, 0.25), (triangles / 4, 0.1)])
+            } else {
+                Vec::new()
+            };
+            ChunkMesh::new(mesh, lods)
+        });
+    // simplifying would merge glass of different transparency, the transparent mesh has no levels of detail
+    let transparent = greedy_mesh(&bordered, true)
+        .filter(|mesh| !mesh.indices.is_empty())
+        .map(|mesh| ChunkMesh::new(mesh, Vec::new()));
+    let lights = emissive_lights(&voxels);
+    ChunkResult {
+        id,
+        chunk,
+        voxels,
+        mesh,
+        transparent,
+        lights,
+    }
+}
+
+// Removes the entities of the chunk together with their colliders, their meshes and the levels of detail of those,
+// and the lights of the chunk.
+fn remove_chunk(
+    chunk: &Chunk,
+    meshes: &mut Registry<Mesh>,
+    entities: &mut Registry<Entity>,
+    physics: &mut Physics,
+    lights: &mut Registry<Light>,
+) {
+    for handle in &chunk.entities {
+        physics.remove(handle);
+        if let Some(entity) = entities.get(handle) {
+            let lods = meshes
+                .get(&entity.mesh_handle)
+                .map(|mesh| mesh.lods.clone())
+                .unwrap_or_default();
+            for lod in lods {
+                meshes.remove(lod.mesh_handle);
+            }
+            meshes.remove(entity.mesh_handle.clone());
+            entities.remove(handle.clone());
+        }
+    }
+    for light in &chunk.lights {
+        lights.remove(light.clone());
+    }
+}
+
+// A light for every group of touching voxels with the same emissive color id, at the center of the group in metres
+// from the corner of the chunk and with the color of its voxels times how much they glow. Only the largest groups
+// get one.
+fn emissive_lights(vox: &Vox) -> Vec<([f32; 3], [f32; 3])> {
+    let size = [vox.x_size, vox.y_size, vox.z_size];
+    let emission = |p: [usize; 3]| {
+        vox.get(p[0], p[1], p[2])
+            .map(|color_id| (color_id, vox.get_material(color_id).emission()))
+            .filter(|(_, emission)| *emission > 0.0)
+    };
+    let mut visited = HashSet::new();
+    // (voxel count, position, color) of every group
+    let mut groups = Vec::new();
+    for z in 0..size[2] {
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                let (color_id, strength) = match emission([x, y, z]) {
+                    Some(found) if !visited.contains(&[x, y, z]) => found,
+                    _ => continue,
+                };
+                let mut sum = [0.0f32; 3];
+                let mut count = 0;
+                let mut stack = vec![[x, y, z]];
+                visited.insert([x, y, z]);
+                while let Some(p) = stack.pop() {
+                    for i in 0..3 {
+                        sum[i] += p[i] as f32 + 0.5;
+                    }
+                    count += 1;
+                    for i in 0..3 {
+                        for step in [-1i32, 1].iter() {
+                            let mut next = p;
+                            let coordinate = p[i] as i32 + step;
+                            if coordinate < 0 || coordinate as usize >= size[i] {
+                                continue;
+                            }
+                            next[i] = coordinate as usize;
+                            if emission(next).map(|(id, _)| id) == Some(color_id) && visited.insert(next) {
+                                stack.push(next);
+                            }
+                        }
+                    }
+                }
+                let color = vox.get_color(color_id);
+                groups.push((
+                    count,
+                    [
+                        sum[0] / count as f32 * VOXEL_SIZE,
+                        sum[1] / count as f32 * VOXEL_SIZE,
+                        sum[2] / count as f32 * VOXEL_SIZE,
+                    ],
+                    [color[0] * strength, color[1] * strength, color[2] * strength],
+                ));
+            }
+        }
+    }
+    groups.sort_by_key(|group| Reverse(group.0));
+    groups
+        .into_iter()
+        .take(MAX_CHUNK_LIGHTS)
+        .map(|(_, position, color)| (position, color))
+        .collect()
+}
+
+// Meshes the faces of the voxels that are transparent or those of the ones that are not. The outer layer of vox are
+// the voxels around the chunk, they hide faces and darken corners but get no faces of their own. Glass does not hide
+// the faces of the voxels behind it and does not darken the corners around it.
+fn greedy_mesh(vox: &vox::Vox, transparent: bool) -> Option<Mesh> {
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
 
@@ -244,7 +1083,20 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
         Descriptor::new(2, 0, 1, -1, [0, 0, -1], [1, 0, 0]),
     ];
 
-    let vox_size = [vox.x_size, vox.y_size, vox.z_size];
+    let vox_size = [vox.x_size - 2, vox.y_size - 2, vox.z_size - 2];
+    // positions in the chunk, from -1 to its size on every axis
+    let get = |position: [i32; 3]| {
+        vox.get(
+            (position[0] + 1) as usize,
+            (position[1] + 1) as usize,
+            (position[2] + 1) as usize,
+        )
+    };
+    let occupied = |position: [i32; 3]| {
+        get(position)
+            .map(|color_id| !vox.get_material(color_id).is_transparent())
+            .unwrap_or(false)
+    };
 
     for d in descriptors.iter() {
         let u = d.u;
@@ -256,37 +1108,55 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
         for slice in 0..vox_size[u] {
             let slice = if d.step == 1 { slice } else { vox_size[u] - (slice + 1) };
             let mut cursor = [0, 0, 0];
-            let no_voxel_back = (slice == 0 && d.step == 1) || (slice == vox_size[u] - 1 && d.step != 1);
             cursor[u] = slice;
             let mut mask = Mask::new(vox_size[v], vox_size[w]);
             for cursor_w in 0..vox_size[w] {
                 for cursor_v in 0..vox_size[v] {
                     cursor[v] = cursor_v;
                     cursor[w] = cursor_w;
-                    let voxel_back = if !no_voxel_back {
-                        vox.get(
-                            (cursor[0] as i32 - normal[0]) as usize,
-                            (cursor[1] as i32 - normal[1]) as usize,
-                            (cursor[2] as i32 - normal[2]) as usize,
-                        )
-                    } else {
-                        None
-                    };
-                    let voxel = vox.get(cursor[0], cursor[1], cursor[2]);
+                    let voxel_back = get([
+                        cursor[0] as i32 - normal[0],
+                        cursor[1] as i32 - normal[1],
+                        cursor[2] as i32 - normal[2],
+                    ]);
+                    let voxel = get([cursor[0] as i32, cursor[1] as i32, cursor[2] as i32]);
                     let color_id = if voxel_back != None && voxel != None && voxel_back == voxel {
                         None
                     } else {
-                        voxel
+                        voxel.filter(|color_id| vox.get_material(*color_id).is_transparent() == transparent)
                     };
-                    mask.set(cursor[v], cursor[w], color_id);
+                    let face = color_id.map(|color_id| {
+                        // the layer of voxels the face looks into
+                        let front = [
+                            cursor[0] as i32 - normal[0],
+                            cursor[1] as i32 - normal[1],
+                            cursor[2] as i32 - normal[2],
+                        ];
+                        let neighbour = |dv: i32, dw: i32| {
+                            let mut position = front;
+                            position[v] += dv;
+                            position[w] += dw;
+                            occupied(position)
+                        };
+                        let mut ambient_occlusion = [3; 4];
+                        for (corner, (dv, dw)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().enumerate() {
+                            ambient_occlusion[corner] =
+                                corner_ambient_occlusion(neighbour(*dv, 0), neighbour(0, *dw), neighbour(*dv, *dw));
+                        }
+                        Face {
+                            color_id,
+                            ambient_occlusion,
+                        }
+                    });
+                    mask.set(cursor[v], cursor[w], face);
                 }
             }
             for y in 0..vox_size[w] {
                 for x in 0..vox_size[v] {
-                    let color_id = mask.get(x, y);
-                    if let Some(m) = color_id {
+                    let face = mask.get(x, y);
+                    if let Some(m) = face {
                         let mut width = 1;
-                        while x + width < vox_size[v] && mask.get(x + width, y) == color_id {
+                        while x + width < vox_size[v] && mask.get(x + width, y) == face {
                             width += 1;
                         }
                         let mut height = 1;
@@ -294,7 +1164,7 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
                         while y + height < vox_size[w] && !done {
                             let mut k = 0;
                             while k < width && !done {
-                                if mask.get(x + k, y + height) == color_id {
+                                if mask.get(x + k, y + height) == face {
                                     k += 1;
                                 } else {
                                     done = true;
@@ -305,44 +1175,49 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
                             }
                         }
                         let mut base = [0.0, 0.0, 0.0];
-                        base[u] = slice as f32 / 10.0 + d.q[0] as f32 / 10.0;
-                        base[v] = x as f32 / 10.0 + d.q[1] as f32 / 10.0;
-                        base[w] = y as f32 / 10.0 + d.q[2] as f32 / 10.0;
+                        base[u] = slice as f32 * VOXEL_SIZE + d.q[0] as f32 * VOXEL_SIZE;
+                        base[v] = x as f32 * VOXEL_SIZE + d.q[1] as f32 * VOXEL_SIZE;
+                        base[w] = y as f32 * VOXEL_SIZE + d.q[2] as f32 * VOXEL_SIZE;
 
                         let mut dv = [0.0, 0.0, 0.0];
-                        dv[v] = width as f32 / 10.0;
+                        dv[v] = width as f32 * VOXEL_SIZE;
                         let mut dw = [0.0, 0.0, 0.0];
-                        dw[w] = height as f32 / 10.0;
+                        dw[w] = height as f32 * VOXEL_SIZE;
 
-                        let color = vox.get_color(m);
+                        let color = vox.get_color(m.color_id);
+                        let material = vox.get_material(m.color_id);
+                        let ao = m.ambient_occlusion;
                         let count = vertices.len() as u32;
+                        let corner = |position: [f32; 3], ao: u8| {
+                            Vertex::new(position, normal_outside, color)
+                                .with_ambient_occlusion(AMBIENT_OCCLUSION_CURVE[ao as usize])
+                                .with_alpha(material.alpha())
+                                // brighter than the color itself it would only turn white
+                                .with_emission(material.emission().min(1.0))
+                        };
                         vertices.extend_from_slice(&[
-                            Vertex::new([base[0], base[1], base[2]], normal_outside, color),
-                            Vertex::new(
+                            corner([base[0], base[1], base[2]], ao[0]),
+                            corner(
                                 [
                                     base[0] + dv[0] + dw[0],
                                     base[1] + dv[1] + dw[1],
                                     base[2] + dv[2] + dw[2],
                                 ],
-                                normal_outside,
-                                color,
-                            ),
-                            Vertex::new(
-                                [base[0] + dv[0], base[1] + dv[1], base[2] + dv[2]],
-                                normal_outside,
-                                color,
-                            ),
-                            Vertex::new(
-                                [base[0] + dw[0], base[1] + dw[1], base[2] + dw[2]],
-                                normal_outside,
-                                color,
+                                ao[3],
                             ),
+                            corner([base[0] + dv[0], base[1] + dv[1], base[2] + dv[2]], ao[1]),
+                            corner([base[0] + dw[0], base[1] + dw[1], base[2] + dw[2]], ao[2]),
                         ]);
-                        if d.step == 1 {
-                            indices.extend_from_slice(&[count, count + 1, count + 2, count, count + 3, count + 1]);
-                        } else {
-                            indices.extend_from_slice(&[count, count + 2, count + 1, count, count + 1, count + 3]);
-                        }
+                        // split along the darker diagonal, otherwise the occlusion of a single corner is
+                        // interpolated differently depending on the orientation of the quad
+                        let flip = ao[0] as u32 + ao[3] as u32 > ao[1] as u32 + ao[2] as u32;
+                        let quad = match (d.step == 1, flip) {
+                            (true, false) => [0, 1, 2, 0, 3, 1],
+                            (true, true) => [0, 3, 2, 2, 3, 1],
+                            (false, false) => [0, 2, 1, 0, 1, 3],
+                            (false, true) => [0, 2, 3, 2, 1, 3],
+                        };
+                        indices.extend(quad.iter().map(|i| count + i));
                         for yy in y..y + height {
                             for xx in x..x + width {
                                 mask.set(xx, yy, None);
@@ -358,6 +1233,8 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
             vertices,
             indices,
             just_loaded: true,
+            vertices_changed: false,
+            lods: Vec::new(),
         })
     } else {
         None
@@ -365,7 +1242,52 @@ fn greedy_mesh(vox: vox::Vox) -> Option<Mesh> {
 }
 #[cfg(test)]
 mod tests {
-    use crate::world::World;
+    use crate::{
+        mesh::{triangle_normal, Mesh},
+        physics::{Body, Physics},
+        registry::Registry,
+        renderer::Light,
+        vox::{load_vox_scene, write_vox, Vox, VoxMaterial},
+        world::{
+            world::{border_layer, build_chunk, greedy_mesh, paste, remove_chunk, ChunkJob, NEIGHBOURS},
+            RegionError, RegionStorage, VoxelGenerator, World,
+        },
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    const COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+    // a 3x3 floor with some voxels on top of it, all with the same color
+    fn floor_with(on_top: &[[usize; 3]]) -> Vox {
+        let mut vox = Vox::new(3, 2, 3);
+        for z in 0..3 {
+            for x in 0..3 {
+                vox.set(x, 0, z, 1, COLOR);
+            }
+        }
+        for p in on_top {
+            vox.set(p[0], p[1], p[2], 1, COLOR);
+        }
+        vox
+    }
+
+    // the model with a layer of empty voxels around it, what greedy_mesh takes
+    fn bordered(vox: &Vox) -> Vox {
+        let mut bordered = Vox::new(vox.x_size + 2, vox.y_size + 2, vox.z_size + 2);
+        paste(&mut bordered, vox, [1, 1, 1]);
+        bordered
+    }
+
+    fn up_facing_occlusion_at(mesh: &Mesh, position: [f32; 3]) -> Vec<f32> {
+        mesh.vertices
+            .iter()
+            .filter(|v| v.normal == [0.0, 1.0, 0.0] && (0..3).all(|i| (v.position[i] - position[i]).abs() < 1e-4))
+            .map(|v| v.ambient_occlusion)
+            .collect()
+    }
 
     #[test]
     fn offset_test() {
@@ -373,5 +1295,449 @@ mod tests {
         assert_eq!(World::chunk_number_and_offset(-5, 4), (-2, 3));
         assert_eq!(World::chunk_number_and_offset(2, 4), (0, 2));
         assert_eq!(World::chunk_number_and_offset(5, 4), (1, 1));
+        assert_eq!(World::chunk_number_and_offset(-32, 32), (-1, 0));
+        assert_eq!(World::chunk_number_and_offset(-33, 32), (-2, 31));
+    }
+
+    #[test]
+    fn set_get_and_clear_voxels() {
+        let mut world = World::new();
+        assert_eq!(world.get_voxel([0, 100, 0]).unwrap(), None);
+        world.set_voxel([0, 100, 0], Some((7, COLOR))).unwrap();
+        assert_eq!(world.get_voxel([0, 100, 0]).unwrap(), Some((7, COLOR)));
+        // below the sine terrain everything is filled
+        assert_eq!(world.get_voxel([-1, -33, -40]).unwrap(), Some((255, [1.0, 0.0, 0.0])));
+        // the chunk is generated once for every read of it until it is edited
+        assert_eq!(world.get_voxel([-2, -33, -40]).unwrap(), Some((255, [1.0, 0.0, 0.0])));
+        assert_eq!(world.loaded_chunks.borrow().len(), 1);
+        world.set_voxel([-1, -33, -40], None).unwrap();
+        assert!(world.loaded_chunks.borrow().is_empty());
+        assert_eq!(world.get_voxel([-1, -33, -40]).unwrap(), None);
+        assert_eq!(world.get_voxel([-1, -32, -40]).unwrap(), Some((255, [1.0, 0.0, 0.0])));
+    }
+
+    #[test]
+    fn fill_box_meshes_only_edited_chunks() {
+        let mut world = World::new();
+        let mut meshes = Registry::new();
+        let mut entities = Registry::new();
+        let mut physics = Physics::default();
+        let mut lights = Registry::new();
+        world.fill_box([-2, 100, 0], [1, 101, 0], Some((1, COLOR))).unwrap();
+        let mut dirty = world.dirty_chunks.iter().cloned().collect::<Vec<_>>();
+        dirty.sort();
+        // the box is on the side of the chunks before them along z, they mesh its voxels as well
+        assert_eq!(dirty, vec![(-1, 3, -1), (-1, 3, 0), (0, 3, -1), (0, 3, 0)]);
+        let chunk = world
+            .generate_chunk((0, 3, 0), &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap()
+            .unwrap();
+        let entity = entities.get(&chunk.entities[0]).unwrap();
+        // a 2x2x1 box is one quad per side, the one against the other half of the box in the chunk before is hidden
+        assert_eq!(meshes.get(&entity.mesh_handle).unwrap().indices.len(), 5 * 6);
+        match &entity.collision_shape.as_ref().unwrap().body {
+            Body::TriMesh(trimesh) => assert_eq!(trimesh.indices.len(), 5 * 2),
+            _ => panic!("chunks collide with their triangles"),
+        }
+        assert!(world
+            .generate_chunk((1, 3, 0), &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn chunk_sides_are_meshed_with_their_neighbours() {
+        let mut world = World::new();
+        let mut meshes = Registry::new();
+        let mut entities = Registry::new();
+        let mut physics = Physics::default();
+        let mut lights = Registry::new();
+        // the last voxel of the chunk, next to one of the next chunk and one above that
+        world.set_voxel([31, 100, 0], Some((1, COLOR))).unwrap();
+        world.set_voxel([32, 100, 0], Some((1, COLOR))).unwrap();
+        world.set_voxel([32, 101, 0], Some((1, COLOR))).unwrap();
+        let chunk = world
+            .generate_chunk((0, 3, 0), &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap()
+            .unwrap();
+        let mesh = meshes
+            .get(&entities.get(&chunk.entities[0]).unwrap().mesh_handle)
+            .unwrap();
+        // no face towards the next chunk, the top is darker where the voxel above the neighbour is
+        assert_eq!(mesh.indices.len(), 5 * 6);
+        assert_eq!(up_facing_occlusion_at(mesh, [3.2, 0.5, 0.0]), vec![0.8]);
+        assert_eq!(up_facing_occlusion_at(mesh, [3.1, 0.5, 0.0]), vec![1.0]);
+    }
+
+    // counts the voxels it is asked to generate and leaves them empty
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl VoxelGenerator for Counting {
+        fn generate(&self, _: [i32; 3], vox: &mut Vox) {
+            self.0.fetch_add(vox.x_size * vox.y_size * vox.z_size, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn only_voxels_nobody_knows_are_generated() {
+        let job = |voxels: Option<Vox>, borders: Vec<([usize; 3], Vox)>, generator: Arc<Counting>| ChunkJob {
+            id: 1,
+            chunk: (0, 0, 0),
+            chunk_size: 4,
+            voxels,
+            borders,
+            generator,
+            parts: Vec::new(),
+        };
+        let generator = Arc::new(Counting::default());
+        build_chunk(job(None, Vec::new(), generator.clone()));
+        assert_eq!(generator.0.load(Ordering::SeqCst), 6 * 6 * 6);
+
+        // an edited chunk with every neighbour in memory
+        let generator = Arc::new(Counting::default());
+        let borders = NEIGHBOURS
+            .iter()
+            .map(|offset| {
+                let (position, _, size) = border_layer(*offset, 4);
+                (position, Vox::new(size[0], size[1], size[2]))
+            })
+            .collect();
+        build_chunk(job(Some(Vox::new(4, 4, 4)), borders, generator.clone()));
+        assert_eq!(generator.0.load(Ordering::SeqCst), 0);
+
+        // only the layer of the one neighbour that is missing
+        let generator = Arc::new(Counting::default());
+        let borders = NEIGHBOURS
+            .iter()
+            .filter(|offset| **offset != [1, 0, 0])
+            .map(|offset| {
+                let (position, _, size) = border_layer(*offset, 4);
+                (position, Vox::new(size[0], size[1], size[2]))
+            })
+            .collect();
+        build_chunk(job(Some(Vox::new(4, 4, 4)), borders, generator.clone()));
+        assert_eq!(generator.0.load(Ordering::SeqCst), 4 * 4);
+    }
+
+    fn bar(length: usize, color_id: u8, color: [f32; 3]) -> Vox {
+        let mut vox = Vox::new(length, 1, 1);
+        for x in 0..length {
+            vox.set(x, 0, 0, color_id, color);
+        }
+        vox
+    }
+
+    #[test]
+    fn overlapping_models_are_all_in_the_chunk() {
+        let mut world = World::new();
+        let mut registry = Registry::new();
+        let red = registry.add(bar(4, 1, [1.0, 0.0, 0.0]));
+        // same color id, but a different palette
+        let blue = registry.add(bar(4, 1, [0.0, 0.0, 1.0]));
+        let green = registry.add(bar(40, 2, [0.0, 1.0, 0.0]));
+        world.add(red, [0, 100, 0], &registry).unwrap();
+        world.add(blue, [2, 100, 0], &registry).unwrap();
+        // crosses into the next chunk, the chunk before has two models in it
+        world.add(green, [5, 100, 0], &registry).unwrap();
+        let at = |world: &World, x: i32| world.get_voxel([x, 100, 0]).unwrap().map(|(_, color)| color);
+        assert_eq!(at(&world, 0), Some([1.0, 0.0, 0.0]));
+        assert_eq!(at(&world, 1), Some([1.0, 0.0, 0.0]));
+        // later models are drawn over earlier ones
+        assert_eq!(at(&world, 2), Some([0.0, 0.0, 1.0]));
+        assert_eq!(at(&world, 4), Some([0.0, 0.0, 1.0]));
+        assert_eq!(at(&world, 5), Some([0.0, 1.0, 0.0]));
+        assert_eq!(at(&world, 44), Some([0.0, 1.0, 0.0]));
+        assert_eq!(at(&world, 45), None);
+    }
+
+    #[test]
+    fn saved_edits_are_loaded_by_a_new_world() {
+        let directory = std::env::temp_dir().join(format!("lpng_world_{}", std::process::id()));
+        let mut world = World::new().with_storage(RegionStorage::new(&directory));
+        world.set_voxel([5, 100, -70], Some((3, COLOR))).unwrap();
+        world.set_voxel([0, -40, 0], None).unwrap();
+        world.save().unwrap();
+
+        let world = World::new().with_storage(RegionStorage::new(&directory));
+        assert_eq!(world.get_voxel([5, 100, -70]).unwrap(), Some((3, COLOR)));
+        assert_eq!(world.get_voxel([0, -40, 0]).unwrap(), None);
+        assert_eq!(world.get_voxel([1, -40, 0]).unwrap(), Some((255, [1.0, 0.0, 0.0])));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn model_added_over_a_saved_chunk_is_kept() {
+        let directory = std::env::temp_dir().join(format!("lpng_world_add_{}", std::process::id()));
+        let mut world = World::new()
+            .with_storage(RegionStorage::new(&directory))
+            .with_view_size(2);
+        let mut registry = Registry::new();
+        let mut meshes = Registry::new();
+        let mut entities = Registry::new();
+        let mut physics = Physics::default();
+        let mut lights = Registry::new();
+        world.set_voxel([5, 100, 0], Some((3, COLOR))).unwrap();
+        // far away the edited chunk is out of view, it is saved and dropped
+        world
+            .generate_around([100.0, 0.0, 0.0], &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap();
+        assert!(!world.chunk_voxels.contains_key(&(0, 3, 0)));
+        world
+            .add(registry.add(bar(4, 1, [0.0, 0.0, 1.0])), [6, 100, 0], &registry)
+            .unwrap();
+        let at = |world: &World, x: i32| world.get_voxel([x, 100, 0]).unwrap().map(|(_, color)| color);
+        assert_eq!(at(&world, 5), Some(COLOR));
+        assert_eq!(at(&world, 6), Some([0.0, 0.0, 1.0]));
+        world.save().unwrap();
+
+        let world = World::new().with_storage(RegionStorage::new(&directory));
+        assert_eq!(at(&world, 5), Some(COLOR));
+        assert_eq!(at(&world, 9), Some([0.0, 0.0, 1.0]));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn raycast_finds_edited_voxels() {
+        let mut world = World::new();
+        world.set_voxel([0, 100, 0], Some((7, COLOR))).unwrap();
+        let hit = world
+            .raycast([0.05, 5.0, 0.05], [0.0, 1.0, 0.0], 10.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.voxel, [0, 100, 0]);
+        assert_eq!(hit.normal, [0, -1, 0]);
+        assert_eq!(hit.empty, [0, 99, 0]);
+        assert!((hit.distance - 5.0).abs() < 1e-3);
+        // placing against the hit side fills the empty cell, the next ray stops there
+        world.set_voxel(hit.empty, Some((7, COLOR))).unwrap();
+        let hit = world
+            .raycast([0.05, 5.0, 0.05], [0.0, 1.0, 0.0], 10.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.voxel, [0, 99, 0]);
+        assert!(world
+            .raycast([0.05, 5.0, 0.05], [0.0, 1.0, 0.0], 4.0)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn copied_region_is_written_as_vox() {
+        let mut world = World::new();
+        let mut registry = Registry::new();
+        let mut glass = bar(2, 1, [0.0, 0.0, 1.0]);
+        glass.set_material(1, VoxMaterial::Glass { alpha: 0.5 });
+        world.add(registry.add(glass), [0, 100, 0], &registry).unwrap();
+        // in the next chunk, set_voxel changes the color of the id in its chunk
+        world.set_voxel([40, 101, 0], Some((1, COLOR))).unwrap();
+        let region = world.copy_region([-1, 100, 0], [40, 101, 0]).unwrap();
+        assert_eq!([region.x_size, region.y_size, region.z_size], [42, 2, 1]);
+        assert_eq!(region.get(0, 0, 0), None);
+        assert_eq!(region.get(1, 0, 0), Some(1));
+        assert_eq!(region.get_material(1), VoxMaterial::Glass { alpha: 0.5 });
+        // the edited voxel has the same color id, but another color and no material
+        let color_id = region.get(41, 1, 0).unwrap();
+        assert_ne!(color_id, 1);
+        assert_eq!(region.get_color(color_id), COLOR);
+        assert_eq!(region.get_material(color_id), VoxMaterial::Diffuse);
+
+        // the generated terrain, its color id 255 gets another palette index in the file
+        let region = world.copy_region([-1, -33, -40], [0, -32, -40]).unwrap();
+        let mut registry = Registry::new();
+        let scene = load_vox_scene(&write_vox(&region).unwrap(), &mut registry).unwrap();
+        let written = registry.get(&scene.models[0]).unwrap();
+        assert_eq!([written.x_size, written.y_size, written.z_size], [2, 2, 1]);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            let color_id = written.get(*x, *y, 0).unwrap();
+            assert_eq!(written.get_color(color_id), [1.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn copied_region_with_too_many_colors_is_an_error() {
+        let mut world = World::new();
+        // every color id of the chunk taken, then a color id 0 with another color in the next chunk
+        for id in 0..=255u8 {
+            let (x, z) = (id as i32 % 16, id as i32 / 16);
+            world
+                .set_voxel([x, 100, z], Some((id, [x as f32 / 16.0, z as f32 / 16.0, 0.5])))
+                .unwrap();
+        }
+        world.set_voxel([40, 100, 0], Some((0, [1.0, 1.0, 1.0]))).unwrap();
+        assert!(world.copy_region([0, 100, 0], [15, 100, 15]).is_ok());
+        assert!(matches!(
+            world.copy_region([0, 100, 0], [40, 100, 15]),
+            Err(RegionError::TooManyColors)
+        ));
+    }
+
+    #[test]
+    fn pasting_into_a_full_palette_takes_the_closest_color() {
+        let mut target = Vox::new(16, 17, 1);
+        for id in 0..=255u8 {
+            target.set(id as usize % 16, id as usize / 16, 0, id, [id as f32 / 255.0, 0.0, 0.0]);
+        }
+        let mut vox = Vox::new(1, 1, 1);
+        vox.set(0, 0, 0, 0, [10.2 / 255.0, 0.0, 0.0]);
+        vox.set_material(0, VoxMaterial::Glass { alpha: 0.5 });
+        paste(&mut target, &vox, [0, 16, 0]);
+        assert_eq!(target.get(0, 16, 0), Some(10));
+        // the palette is left as it was
+        assert_eq!(target.get_color(10), [10.0 / 255.0, 0.0, 0.0]);
+        assert_eq!(target.get_material(10), VoxMaterial::Diffuse);
+    }
+
+    #[test]
+    fn chunks_in_view_are_meshed_by_the_workers() {
+        let mut world = World::new().with_view_size(2);
+        let mut meshes = Registry::new();
+        let mut entities = Registry::new();
+        let mut physics = Physics::default();
+        let mut lights = Registry::new();
+        world
+            .generate_around([0.0, 0.0, 0.0], &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap();
+        assert!(world.is_generating());
+        assert!(!world.is_generated([0.0, 0.0, 0.0]));
+        // an edit while the chunk is on a worker is not lost when the older result comes back
+        world.set_voxel([0, 31, 0], Some((7, COLOR))).unwrap();
+        for _ in 0..1000 {
+            if !world.is_generating() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            world
+                .generate_around([0.0, 0.0, 0.0], &mut meshes, &mut entities, &mut physics, &mut lights)
+                .unwrap();
+        }
+        assert!(!world.is_generating());
+        assert!(world.is_generated([0.0, 0.0, 0.0]));
+        // the 8 chunks around the origin all have terrain in them
+        assert_eq!(entities.registry.len(), 8);
+        assert_eq!(world.get_voxel([0, 31, 0]).unwrap(), Some((7, COLOR)));
+        assert_eq!(world.get_voxel([0, 30, 0]).unwrap(), None);
+    }
+
+    #[test]
+    fn glass_and_lamps_get_their_own_mesh_and_lights() {
+        let mut world = World::new();
+        let mut registry = Registry::new();
+        let mut meshes = Registry::new();
+        let mut entities = Registry::new();
+        let mut physics = Physics::default();
+        let mut lights = Registry::new();
+        // a floor with a glass voxel and two lamps on it, one of them two voxels big
+        let mut vox = floor_with(&[]);
+        vox.set(1, 1, 1, 2, [0.5, 0.5, 1.0]);
+        vox.set_material(2, VoxMaterial::Glass { alpha: 0.5 });
+        vox.set(0, 1, 0, 3, [1.0, 1.0, 0.0]);
+        vox.set(0, 1, 1, 3, [1.0, 1.0, 0.0]);
+        vox.set(2, 1, 2, 3, [1.0, 1.0, 0.0]);
+        vox.set_material(
+            3,
+            VoxMaterial::Emit {
+                emission: 1.0,
+                power: 0.0,
+            },
+        );
+        world.add(registry.add(vox), [0, 100, 0], &registry).unwrap();
+        let chunk = world
+            .generate_chunk((0, 3, 0), &mut meshes, &mut entities, &mut physics, &mut lights)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.entities.len(), 2);
+        let mesh = |i: usize| {
+            meshes
+                .get(&entities.get(&chunk.entities[i]).unwrap().mesh_handle)
+                .unwrap()
+        };
+        // glass is not culled by the floor below it, the floor below it is not culled or darkened by the glass
+        assert_eq!(mesh(1).indices.len(), 6 * 6);
+        assert!(mesh(1).vertices.iter().all(|v| v.alpha == 0.5));
+        let next_to_glass = up_facing_occlusion_at(mesh(0), [0.2, 0.5, 0.1]);
+        assert!(!next_to_glass.is_empty() && next_to_glass.iter().all(|ao| *ao == 1.0));
+        assert!(mesh(0).vertices.iter().all(|v| v.alpha == 1.0));
+        assert!(mesh(0).vertices.iter().any(|v| v.emission == 1.0));
+        // the larger lamp comes first, the lights are in the world
+        assert_eq!(chunk.lights.len(), 2);
+        match lights.get(&chunk.lights[0]).unwrap() {
+            Light::Point(properties) => {
+                let expected = [0.05, 10.15, 0.1];
+                assert!((0..3).all(|i| (properties.position[i] - expected[i]).abs() < 1e-4));
+                assert_eq!(properties.diffuse, [1.0, 1.0, 0.0, 1.0]);
+            }
+            _ => panic!("lamps are point lights"),
+        }
+        remove_chunk(&chunk, &mut meshes, &mut entities, &mut physics, &mut lights);
+        assert!(lights.registry.is_empty());
+        assert!(entities.registry.is_empty());
+    }
+
+    #[test]
+    fn single_voxel_is_not_occluded() {
+        let mut vox = Vox::new(3, 3, 3);
+        vox.set(1, 1, 1, 1, COLOR);
+        let mesh = greedy_mesh(&bordered(&vox), false).unwrap();
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert!(mesh.vertices.iter().all(|v| v.ambient_occlusion == 1.0));
+    }
+
+    #[test]
+    fn voxel_occludes_floor_around_it() {
+        let mesh = greedy_mesh(&bordered(&floor_with(&[[1, 1, 1]])), false).unwrap();
+        // at the corners of the voxel the floor is darker, far away it is open
+        let at_corner = up_facing_occlusion_at(&mesh, [0.1, 0.1, 0.1]);
+        assert!(!at_corner.is_empty());
+        assert!(at_corner.iter().all(|ao| *ao == 0.8));
+        assert_eq!(up_facing_occlusion_at(&mesh, [0.0, 0.1, 0.0]), vec![1.0]);
+        // the floor would be one quad with a hole, differing occlusion splits it up further
+        let floor_quads = mesh
+            .vertices
+            .iter()
+            .filter(|v| v.normal == [0.0, 1.0, 0.0] && (v.position[1] - 0.1).abs() < 1e-4)
+            .count()
+            / 4;
+        assert_eq!(floor_quads, 8);
+    }
+
+    #[test]
+    fn inner_corner_is_darkest() {
+        let mesh = greedy_mesh(
+            &bordered(&floor_with(&[[0, 1, 0], [0, 1, 1], [0, 1, 2], [1, 1, 0], [2, 1, 0]])),
+            false,
+        )
+        .unwrap();
+        assert_eq!(up_facing_occlusion_at(&mesh, [0.1, 0.1, 0.1]), vec![0.4]);
+    }
+
+    #[test]
+    fn quads_split_along_darker_diagonal() {
+        let mesh = greedy_mesh(&bordered(&floor_with(&[[1, 1, 1]])), false).unwrap();
+        let mut single_dark_corners = 0;
+        for (quad, indices) in mesh.vertices.chunks(4).zip(mesh.indices.chunks(6)) {
+            let darkest = quad.iter().map(|v| v.ambient_occlusion).fold(1.0, f32::min);
+            let dark: Vec<_> = (0..4).filter(|i| quad[*i].ambient_occlusion == darkest).collect();
+            if darkest < 1.0 && dark.len() == 1 {
+                // the dark vertex is on the diagonal, shared by both triangles
+                let base = indices.iter().min().unwrap();
+                assert_eq!(indices.iter().filter(|i| **i - base == dark[0] as u32).count(), 2);
+                single_dark_corners += 1;
+            }
+        }
+        assert!(single_dark_corners > 0);
+    }
+
+    #[test]
+    fn flipped_quads_keep_winding() {
+        let mesh = greedy_mesh(&bordered(&floor_with(&[[1, 1, 1]])), false).unwrap();
+        for triangle in mesh.indices.chunks(3) {
+            let p = |i: u32| mesh.vertices[i as usize].position;
+            let n = triangle_normal(p(triangle[0]), p(triangle[1]), p(triangle[2]));
+            let vertex_normal = mesh.vertices[triangle[0] as usize].normal;
+            let dot: f32 = (0..3).map(|i| n[i] * vertex_normal[i]).sum();
+            assert!(dot > 0.0);
+        }
     }
 }
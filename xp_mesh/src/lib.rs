@@ -1,9 +1,11 @@
 mod error;
 mod obj;
+mod simplify;
 
 pub mod mesh {
     pub use crate::error::*;
     pub use crate::obj::Obj;
+    pub use crate::simplify::{simplify, simplify_triangles, Simplified};
 }
 
 #[derive(Clone)]
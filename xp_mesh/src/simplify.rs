@@ -0,0 +1,334 @@
+use crate::Triangle;
+use nalgebra_glm::{vec3, Vec3};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+// Symmetric 4x4 matrix, the sum of the squared distances to a set of planes.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // plane n . p + d = 0 with a unit normal
+    fn from_plane(n: [f64; 3], d: f64, weight: f64) -> Self {
+        let [a, b, c] = n;
+        let mut q = [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d];
+        for v in q.iter_mut() {
+            *v *= weight;
+        }
+        Quadric(q)
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (v, o) in self.0.iter_mut().zip(other.0.iter()) {
+            *v += o;
+        }
+    }
+
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let [x, y, z] = p;
+        let q = &self.0;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+// Moving vertex from onto vertex to, only valid while both vertices have the version they had when it was queued.
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, so the binary heap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+pub struct Simplified {
+    pub triangles: Vec<[u32; 3]>,
+    // the input triangle every triangle comes from, its corners are in the same order
+    pub sources: Vec<usize>,
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f64; 3] {
+    [
+        a[0] as f64 - b[0] as f64,
+        a[1] as f64 - b[1] as f64,
+        a[2] as f64 - b[2] as f64,
+    ]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normal(positions: &[[f32; 3]], triangle: &[u32; 3]) -> [f64; 3] {
+    let p0 = positions[triangle[0] as usize];
+    cross(
+        sub(positions[triangle[1] as usize], p0),
+        sub(positions[triangle[2] as usize], p0),
+    )
+}
+
+// Quadric error metric edge collapse (Garland & Heckbert) down to about target_triangles. A vertex is only ever
+// moved onto one of its neighbours, so the result uses the input vertices. Vertices on the border of the mesh stay
+// where they are, so neighbouring meshes still fit together.
+pub fn simplify(positions: &[[f32; 3]], triangles: &[[u32; 3]], target_triangles: usize) -> Simplified {
+    let mut triangles = triangles.to_vec();
+    let mut alive = vec![true; triangles.len()];
+    let mut live_triangles = triangles.len();
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    let mut vertex_triangles = vec![Vec::new(); positions.len()];
+    let mut edges = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        let n = normal(positions, triangle);
+        let area = dot(n, n).sqrt();
+        for (i, corner) in triangle.iter().enumerate() {
+            vertex_triangles[*corner as usize].push(t);
+            let next = triangle[(i + 1) % 3];
+            *edges.entry((*corner.min(&next), *corner.max(&next))).or_insert(0) += 1;
+        }
+        if area > 0.0 {
+            let unit = [n[0] / area, n[1] / area, n[2] / area];
+            let p0 = positions[triangle[0] as usize];
+            let d = -dot(unit, [p0[0] as f64, p0[1] as f64, p0[2] as f64]);
+            // weighted by area, so small triangles do not keep large flat areas from simplifying
+            let quadric = Quadric::from_plane(unit, d, area);
+            for corner in triangle {
+                quadrics[*corner as usize].add(&quadric);
+            }
+        }
+    }
+    let mut locked = vec![false; positions.len()];
+    for ((a, b), count) in edges.iter() {
+        if *count == 1 {
+            locked[*a as usize] = true;
+            locked[*b as usize] = true;
+        }
+    }
+
+    let mut versions = vec![0u32; positions.len()];
+    let mut heap = BinaryHeap::new();
+    let position = |v: u32| {
+        let p = positions[v as usize];
+        [p[0] as f64, p[1] as f64, p[2] as f64]
+    };
+    let queue = |heap: &mut BinaryHeap<Collapse>, versions: &[u32], quadrics: &[Quadric], a: u32, b: u32| {
+        let mut quadric = quadrics[a as usize];
+        quadric.add(&quadrics[b as usize]);
+        for (from, to) in [(a, b), (b, a)].iter() {
+            if !locked[*from as usize] {
+                heap.push(Collapse {
+                    cost: quadric.error(position(*to)),
+                    from: *from,
+                    to: *to,
+                    versions: (versions[*from as usize], versions[*to as usize]),
+                });
+            }
+        }
+    };
+    for (a, b) in edges.keys() {
+        queue(&mut heap, &versions, &quadrics, *a, *b);
+    }
+
+    while live_triangles > target_triangles {
+        let collapse = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        let (from, to) = (collapse.from as usize, collapse.to as usize);
+        if collapse.versions != (versions[from], versions[to]) {
+            continue;
+        }
+        // triangles that would flip over or become slivers make the collapse invalid
+        let flips = vertex_triangles[from].iter().filter(|t| alive[**t]).any(|t| {
+            let triangle = triangles[*t];
+            if triangle.contains(&collapse.to) {
+                return false;
+            }
+            let mut moved = triangle;
+            for corner in moved.iter_mut() {
+                if *corner == collapse.from {
+                    *corner = collapse.to;
+                }
+            }
+            let (before, after) = (normal(positions, &triangle), normal(positions, &moved));
+            dot(before, before) > 0.0 && dot(before, after) <= 0.0
+        });
+        if flips {
+            continue;
+        }
+        let moved_triangles = std::mem::take(&mut vertex_triangles[from]);
+        for t in moved_triangles {
+            if !alive[t] {
+                continue;
+            }
+            if triangles[t].contains(&collapse.to) {
+                alive[t] = false;
+                live_triangles -= 1;
+            } else {
+                for corner in triangles[t].iter_mut() {
+                    if *corner == collapse.from {
+                        *corner = collapse.to;
+                    }
+                }
+                vertex_triangles[to].push(t);
+            }
+        }
+        let from_quadric = quadrics[from];
+        quadrics[to].add(&from_quadric);
+        versions[from] += 1;
+        versions[to] += 1;
+        vertex_triangles[to].retain(|t| alive[*t]);
+        let mut neighbours = vertex_triangles[to]
+            .iter()
+            .flat_map(|t| triangles[*t].iter().copied())
+            .filter(|v| *v != collapse.to)
+            .collect::<Vec<_>>();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            queue(&mut heap, &versions, &quadrics, collapse.to, neighbour);
+        }
+    }
+
+    let mut simplified = Simplified {
+        triangles: Vec::with_capacity(live_triangles),
+        sources: Vec::with_capacity(live_triangles),
+    };
+    for (t, triangle) in triangles.iter().enumerate() {
+        if alive[t] {
+            simplified.triangles.push(*triangle);
+            simplified.sources.push(t);
+        }
+    }
+    simplified
+}
+
+// Simplifies a triangle soup, corners at the same position are joined first. Every triangle keeps its color.
+pub fn simplify_triangles(triangles: &[Triangle<Vec3>], target_triangles: usize) -> Vec<Triangle<Vec3>> {
+    let mut lookup = HashMap::new();
+    let mut positions = Vec::new();
+    let indices = triangles
+        .iter()
+        .map(|triangle| {
+            let mut corners = [0; 3];
+            for (corner, p) in corners.iter_mut().zip(triangle.positions.iter()) {
+                let position = [p[0], p[1], p[2]];
+                let key = [position[0].to_bits(), position[1].to_bits(), position[2].to_bits()];
+                *corner = *lookup.entry(key).or_insert_with(|| {
+                    positions.push(position);
+                    positions.len() as u32 - 1
+                });
+            }
+            corners
+        })
+        .collect::<Vec<_>>();
+    let simplified = simplify(&positions, &indices, target_triangles);
+    simplified
+        .triangles
+        .iter()
+        .zip(simplified.sources.iter())
+        .map(|(corners, source)| {
+            let position = |i: u32| {
+                let p = positions[i as usize];
+                vec3(p[0], p[1], p[2])
+            };
+            Triangle {
+                positions: [position(corners[0]), position(corners[1]), position(corners[2])],
+                diffuse_color: triangles[*source].diffuse_color,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simplify::simplify;
+
+    // a flat grid of size x size quads with a bump in the middle
+    fn grid(size: u32, bump: f32) -> (Vec<[f32; 3]>, Vec<[u32; 3]>) {
+        let mut positions = Vec::new();
+        for z in 0..=size {
+            for x in 0..=size {
+                let y = if x == size / 2 && z == size / 2 { bump } else { 0.0 };
+                positions.push([x as f32, y, z as f32]);
+            }
+        }
+        let mut triangles = Vec::new();
+        for z in 0..size {
+            for x in 0..size {
+                let i = z * (size + 1) + x;
+                triangles.push([i, i + size + 1, i + size + 2]);
+                triangles.push([i, i + size + 2, i + 1]);
+            }
+        }
+        (positions, triangles)
+    }
+
+    #[test]
+    fn flat_areas_collapse_and_the_border_stays() {
+        let (positions, triangles) = grid(8, 0.0);
+        let simplified = simplify(&positions, &triangles, 0);
+        // only the border is left, it needs at least one triangle per border edge less two
+        assert!(simplified.triangles.len() < 40);
+        for border in 0..=8 {
+            assert!(simplified.triangles.iter().any(|t| t.contains(&border)));
+        }
+        // nothing flipped
+        for triangle in &simplified.triangles {
+            let p = |i: u32| positions[i as usize];
+            let (a, b, c) = (p(triangle[0]), p(triangle[1]), p(triangle[2]));
+            let y = (b[2] - a[2]) * (c[0] - a[0]) - (b[0] - a[0]) * (c[2] - a[2]);
+            assert!(y > 0.0);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_target_and_keeps_features() {
+        let (positions, triangles) = grid(8, 4.0);
+        let simplified = simplify(&positions, &triangles, 100);
+        assert_eq!(simplified.triangles.len(), 100);
+        // the bump is the most expensive vertex to lose
+        assert!(simplified.triangles.iter().any(|t| t.contains(&40)));
+        for (triangle, source) in simplified.triangles.iter().zip(simplified.sources.iter()) {
+            let original = triangles[*source];
+            assert!(triangle.iter().zip(original.iter()).filter(|(a, b)| a == b).count() >= 1);
+        }
+    }
+}
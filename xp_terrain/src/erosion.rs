@@ -0,0 +1,276 @@
+use crate::{grid::HeightGrid, random::Random};
+
+// Droplet based hydraulic erosion, every droplet runs downhill, picks up sediment while it is
+// faster than its capacity allows and drops it again when slowing down or running uphill.
+#[derive(Clone, Debug)]
+pub struct HydraulicParameters {
+    pub droplets: usize,
+    pub max_lifetime: usize,
+    pub inertia: f32,
+    pub sediment_capacity_factor: f32,
+    pub min_sediment_capacity: f32,
+    pub erode_speed: f32,
+    pub deposit_speed: f32,
+    pub evaporate_speed: f32,
+    pub gravity: f32,
+    pub seed: u32,
+}
+
+impl Default for HydraulicParameters {
+    fn default() -> Self {
+        Self {
+            droplets: 10000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            sediment_capacity_factor: 4.0,
+            min_sediment_capacity: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            gravity: 4.0,
+            seed: 1,
+        }
+    }
+}
+
+// Thermal slumping, material slides to lower neighbours while the slope is steeper than
+// max_slope (height difference per unit of distance).
+#[derive(Clone, Debug)]
+pub struct ThermalParameters {
+    pub iterations: usize,
+    pub max_slope: f32,
+    pub strength: f32,
+}
+
+impl Default for ThermalParameters {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            max_slope: 1.0,
+            strength: 0.5,
+        }
+    }
+}
+
+pub struct Erosion {
+    pub heights: HeightGrid,
+    // amount of water that passed through every cell, high along rivers and valleys
+    pub flow: HeightGrid,
+    // amount of material deposited in every cell by water
+    pub sediment: HeightGrid,
+}
+
+// Runs iterations times a hydraulic pass followed by a thermal pass on a copy of grid
+pub fn erode(
+    grid: &HeightGrid,
+    iterations: usize,
+    hydraulic: &HydraulicParameters,
+    thermal: &ThermalParameters,
+) -> Erosion {
+    let mut erosion = Erosion {
+        heights: grid.clone(),
+        flow: HeightGrid::new(grid.width, grid.height, grid.cell_size),
+        sediment: HeightGrid::new(grid.width, grid.height, grid.cell_size),
+    };
+    let mut random = Random::new(hydraulic.seed);
+    // a droplet needs a cell to run through, a single row or column has none
+    let droplets = if grid.width < 2 || grid.height < 2 {
+        0
+    } else {
+        hydraulic.droplets
+    };
+    for _ in 0..iterations {
+        for _ in 0..droplets {
+            droplet(&mut erosion, hydraulic, &mut random);
+        }
+        for _ in 0..thermal.iterations {
+            slump(&mut erosion.heights, thermal);
+        }
+    }
+    erosion
+}
+
+// height and gradient (per cell) at grid position, bilinear within the cell
+fn height_and_gradient(grid: &HeightGrid, x: f32, z: f32) -> (f32, f32, f32) {
+    let x0 = x.floor() as usize;
+    let z0 = z.floor() as usize;
+    let fx = x - x0 as f32;
+    let fz = z - z0 as f32;
+    let h00 = grid.get(x0, z0);
+    let h10 = grid.get(x0 + 1, z0);
+    let h01 = grid.get(x0, z0 + 1);
+    let h11 = grid.get(x0 + 1, z0 + 1);
+    let gradient_x = (h10 - h00) * (1.0 - fz) + (h11 - h01) * fz;
+    let gradient_z = (h01 - h00) * (1.0 - fx) + (h11 - h10) * fx;
+    let height = h00 * (1.0 - fx) * (1.0 - fz)
+        + h10 * fx * (1.0 - fz)
+        + h01 * (1.0 - fx) * fz
+        + h11 * fx * fz;
+    (height, gradient_x, gradient_z)
+}
+
+// spreads amount over the four corners of the cell containing (x, z)
+fn distribute(grid: &mut HeightGrid, x: f32, z: f32, amount: f32) {
+    let x0 = x.floor() as usize;
+    let z0 = z.floor() as usize;
+    let fx = x - x0 as f32;
+    let fz = z - z0 as f32;
+    grid.add(x0, z0, amount * (1.0 - fx) * (1.0 - fz));
+    grid.add(x0 + 1, z0, amount * fx * (1.0 - fz));
+    grid.add(x0, z0 + 1, amount * (1.0 - fx) * fz);
+    grid.add(x0 + 1, z0 + 1, amount * fx * fz);
+}
+
+fn inside(grid: &HeightGrid, x: f32, z: f32) -> bool {
+    x >= 0.0 && z >= 0.0 && x < (grid.width - 1) as f32 && z < (grid.height - 1) as f32
+}
+
+fn droplet(erosion: &mut Erosion, parameters: &HydraulicParameters, random: &mut Random) {
+    let heights = &mut erosion.heights;
+    let mut x = random.next_f32() * (heights.width - 1) as f32;
+    let mut z = random.next_f32() * (heights.height - 1) as f32;
+    let mut direction = [0.0f32, 0.0f32];
+    let mut speed = 1.0f32;
+    let mut water = 1.0f32;
+    let mut sediment = 0.0f32;
+    for _ in 0..parameters.max_lifetime {
+        let (height, gradient_x, gradient_z) = height_and_gradient(heights, x, z);
+        direction[0] = direction[0] * parameters.inertia - gradient_x * (1.0 - parameters.inertia);
+        direction[1] = direction[1] * parameters.inertia - gradient_z * (1.0 - parameters.inertia);
+        let length = (direction[0] * direction[0] + direction[1] * direction[1]).sqrt();
+        if length < f32::EPSILON {
+            break;
+        }
+        direction[0] /= length;
+        direction[1] /= length;
+        let (new_x, new_z) = (x + direction[0], z + direction[1]);
+        erosion
+            .flow
+            .add(x.round() as usize, z.round() as usize, water);
+        if !inside(heights, new_x, new_z) {
+            break;
+        }
+        let delta_height = height_and_gradient(heights, new_x, new_z).0 - height;
+        let capacity = (-delta_height * speed * water * parameters.sediment_capacity_factor)
+            .max(parameters.min_sediment_capacity);
+        if sediment > capacity || delta_height > 0.0 {
+            // going uphill fills the pit behind the droplet, otherwise drop what is over capacity
+            let deposit = if delta_height > 0.0 {
+                delta_height.min(sediment)
+            } else {
+                (sediment - capacity) * parameters.deposit_speed
+            };
+            sediment -= deposit;
+            distribute(heights, x, z, deposit);
+            distribute(&mut erosion.sediment, x, z, deposit);
+        } else {
+            // never dig deeper than the height difference, that would create spikes
+            let erode = ((capacity - sediment) * parameters.erode_speed).min(-delta_height);
+            sediment += erode;
+            distribute(heights, x, z, -erode);
+        }
+        speed = (speed * speed - delta_height * parameters.gravity)
+            .max(0.0)
+            .sqrt();
+        water *= 1.0 - parameters.evaporate_speed;
+        x = new_x;
+        z = new_z;
+    }
+}
+
+fn slump(heights: &mut HeightGrid, parameters: &ThermalParameters) {
+    const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    let talus = parameters.max_slope * heights.cell_size;
+    let mut delta = vec![0.0f32; heights.data.len()];
+    for z in 0..heights.height {
+        for x in 0..heights.width {
+            let height = heights.get(x, z);
+            for (dx, dz) in NEIGHBOURS.iter() {
+                let nx = x as i32 + dx;
+                let nz = z as i32 + dz;
+                if nx < 0 || nz < 0 || nx >= heights.width as i32 || nz >= heights.height as i32 {
+                    continue;
+                }
+                let (nx, nz) = (nx as usize, nz as usize);
+                let difference = height - heights.get(nx, nz);
+                if difference > talus {
+                    // a quarter per neighbour, so a cell never gives away more than its excess
+                    let amount = (difference - talus) * 0.5 * parameters.strength * 0.25;
+                    delta[z * heights.width + x] -= amount;
+                    delta[nz * heights.width + nx] += amount;
+                }
+            }
+        }
+    }
+    for (height, delta) in heights.data.iter_mut().zip(delta.iter()) {
+        *height += delta;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{erode, HeightGrid, HydraulicParameters, ThermalParameters};
+
+    fn max_slope(grid: &HeightGrid) -> f32 {
+        let mut max = 0.0f32;
+        for z in 0..grid.height {
+            for x in 0..grid.width - 1 {
+                max = max.max((grid.get(x, z) - grid.get(x + 1, z)).abs());
+            }
+        }
+        max
+    }
+
+    #[test]
+    fn thermal_conserves_material_and_flattens_test() {
+        let cliff = HeightGrid::from_fn(16, 16, 1.0, |x, _| if x < 8 { 10.0 } else { 0.0 });
+        let hydraulic = HydraulicParameters {
+            droplets: 0,
+            ..Default::default()
+        };
+        let thermal = ThermalParameters {
+            iterations: 200,
+            ..Default::default()
+        };
+        let erosion = erode(&cliff, 1, &hydraulic, &thermal);
+        assert!((erosion.heights.total() - cliff.total()).abs() < 0.01);
+        assert!(max_slope(&erosion.heights) < 1.5);
+        assert_eq!(erosion.flow.total(), 0.0);
+    }
+
+    #[test]
+    fn hydraulic_carves_slope_test() {
+        let slope = HeightGrid::from_fn(32, 32, 1.0, |x, z| {
+            32.0 - x as f32 + ((z as f32) * 0.7).sin() * 0.5
+        });
+        let hydraulic = HydraulicParameters {
+            droplets: 2000,
+            ..Default::default()
+        };
+        let thermal = ThermalParameters {
+            iterations: 0,
+            ..Default::default()
+        };
+        let erosion = erode(&slope, 2, &hydraulic, &thermal);
+        assert_ne!(erosion.heights, slope);
+        assert!(erosion.flow.total() > 0.0);
+        assert!(erosion.sediment.data.iter().all(|s| *s >= 0.0));
+        // material is moved downhill, the top half lost height
+        let top: f32 = (0..32)
+            .map(|z| erosion.heights.get(4, z) - slope.get(4, z))
+            .sum();
+        assert!(top < 0.0);
+        // same seed, same result
+        let again = erode(&slope, 2, &hydraulic, &thermal);
+        assert_eq!(again.heights, erosion.heights);
+    }
+
+    #[test]
+    fn single_row_is_left_to_thermal_test() {
+        let row = HeightGrid::from_fn(4, 1, 1.0, |x, _| if x == 0 { 10.0 } else { 0.0 });
+        let erosion = erode(&row, 1, &Default::default(), &Default::default());
+        assert_eq!(erosion.flow.total(), 0.0);
+        assert!((erosion.heights.total() - row.total()).abs() < 0.01);
+        assert!(erosion.heights.get(0, 0) < 10.0);
+    }
+}
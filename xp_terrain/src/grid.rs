@@ -0,0 +1,92 @@
+// What samples outside the grid read, the nearest border sample or the grid repeated.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Wrap {
+    Clamp,
+    Tile,
+}
+
+impl Wrap {
+    fn index(self, i: i64, size: usize) -> usize {
+        match self {
+            Wrap::Clamp => i.max(0).min(size as i64 - 1) as usize,
+            Wrap::Tile => i.rem_euclid(size as i64) as usize,
+        }
+    }
+}
+
+// Row major grid of heights, x runs along width and z along height. Neighbouring samples are
+// cell_size apart in world units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeightGrid {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f32,
+    pub data: Vec<f32>,
+}
+
+impl HeightGrid {
+    pub fn new(width: usize, height: usize, cell_size: f32) -> Self {
+        assert!(width >= 1 && height >= 1);
+        Self {
+            width,
+            height,
+            cell_size,
+            data: vec![0.0; width * height],
+        }
+    }
+
+    // Fills the grid from a function of the sample position, the game fills it from a
+    // terrain::Generator
+    pub fn from_fn(
+        width: usize,
+        height: usize,
+        cell_size: f32,
+        f: impl Fn(usize, usize) -> f32,
+    ) -> Self {
+        let mut grid = Self::new(width, height, cell_size);
+        for z in 0..height {
+            for x in 0..width {
+                grid.set(x, z, f(x, z));
+            }
+        }
+        grid
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> f32 {
+        self.data[z * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, value: f32) {
+        self.data[z * self.width + x] = value;
+    }
+
+    pub fn add(&mut self, x: usize, z: usize, value: f32) {
+        self.data[z * self.width + x] += value;
+    }
+
+    // bilinear sample in grid coordinates, heightmap images and masks are sampled through this too
+    pub fn sample(&self, x: f32, z: f32, wrap: Wrap) -> f32 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let (x0, z0) = (x0 as i64, z0 as i64);
+        let at = |x: i64, z: i64| self.get(wrap.index(x, self.width), wrap.index(z, self.height));
+        let top = at(x0, z0) * (1.0 - fx) + at(x0 + 1, z0) * fx;
+        let bottom = at(x0, z0 + 1) * (1.0 - fx) + at(x0 + 1, z0 + 1) * fx;
+        top * (1.0 - fz) + bottom * fz
+    }
+
+    // copy scaled to [0, 1], useful to turn flow and sediment maps into texture masks
+    pub fn normalized(&self) -> Self {
+        let min = self.data.iter().cloned().fold(f32::MAX, f32::min);
+        let max = self.data.iter().cloned().fold(f32::MIN, f32::max);
+        let range = if max > min { max - min } else { 1.0 };
+        Self {
+            data: self.data.iter().map(|v| (v - min) / range).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn total(&self) -> f32 {
+        self.data.iter().sum()
+    }
+}
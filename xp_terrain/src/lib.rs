@@ -0,0 +1,6 @@
+mod erosion;
+mod grid;
+mod random;
+
+pub use erosion::{erode, Erosion, HydraulicParameters, ThermalParameters};
+pub use grid::{HeightGrid, Wrap};